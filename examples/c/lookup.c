@@ -0,0 +1,56 @@
+/* Copyright 2023 IPQualityScore LLC */
+/*
+ * Looks up an address from C through the ffi feature:
+ *
+ *   cargo build --release --features ffi
+ *   cc -Iinclude examples/c/lookup.c target/release/libipqs_db_reader.a -lpthread -ldl -lm -o lookup
+ *   ./lookup IPv4.ipqs 8.8.8.8
+ */
+#include <stdio.h>
+
+#include "ipqs_db_reader.h"
+
+int main(int argc, char **argv) {
+    if (argc != 3) {
+        fprintf(stderr, "usage: %s FILE IP\n", argv[0]);
+        return 2;
+    }
+
+    IpqsReader *reader = NULL;
+    IpqsStatus status = ipqs_reader_open_in_memory(argv[1], &reader);
+    if (status != IPQS_STATUS_OK) {
+        fprintf(stderr, "failed to open %s: %s\n", argv[1], ipqs_last_error());
+        return 1;
+    }
+
+    IpqsRecord *record = NULL;
+    status = ipqs_reader_lookup(reader, argv[2], &record);
+    if (status == IPQS_STATUS_NO_RECORD_BEFORE || status == IPQS_STATUS_NOT_IN_FILE) {
+        printf("%s has no record\n", argv[2]);
+    } else if (status != IPQS_STATUS_OK) {
+        fprintf(stderr, "failed to look up %s: %s\n", argv[2], ipqs_last_error());
+    } else {
+        bool is_proxy = false;
+        uint32_t fraud_score = 0;
+        uint64_t asn = 0;
+        const char *country = ipqs_record_country(record);
+
+        printf("connection type: %s\n", ipqs_record_connection_type(record));
+        if (ipqs_record_is_proxy(record, &is_proxy)) {
+            printf("proxy: %s\n", is_proxy ? "yes" : "no");
+        }
+        if (ipqs_record_fraud_score(record, 0, &fraud_score)) {
+            printf("fraud score: %u\n", fraud_score);
+        }
+        if (ipqs_record_asn(record, &asn)) {
+            printf("ASN: %llu\n", (unsigned long long)asn);
+        }
+        if (country != NULL) {
+            printf("country: %s\n", country);
+        }
+        ipqs_record_free(record);
+    }
+
+    ipqs_reader_close(reader);
+    return status == IPQS_STATUS_OK ? 0 : 1;
+}
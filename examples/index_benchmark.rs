@@ -0,0 +1,79 @@
+// Copyright 2023 IPQualityScore LLC
+use ipqs_db_reader::{Fields, FileReader};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::PathBuf;
+use std::time::Instant;
+
+// Compares lookups walking the tree with lookups through the in-memory index:
+// cargo run --release --example index_benchmark -- [path to database] [number of lookups]
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+    let path = match args.next() {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let mut path_buf = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+            path_buf.push("resources/IPQualityScore-IP-Reputation-Database-IPv4.ipqs");
+            path_buf
+        }
+    };
+    let lookups: usize = match args.next() {
+        Some(lookups) => lookups.parse()?,
+        None => 1_000_000,
+    };
+
+    let mut walk = FileReader::open_in_memory(&path)?;
+    walk.set_string_cache_capacity(usize::MAX);
+    let mut indexed = FileReader::open_in_memory(&path)?;
+    indexed.set_string_cache_capacity(usize::MAX);
+    let start = Instant::now();
+    indexed.build_index()?;
+    println!("built index in {:?}", start.elapsed());
+
+    // the same pseudo-random addresses for both readers
+    let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+    let addresses: Vec<IpAddr> = (0..lookups)
+        .map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            if walk.is_ipv6() {
+                IpAddr::V6(Ipv6Addr::from(u128::from(seed) << 64 | u128::from(!seed)))
+            } else {
+                IpAddr::V4(Ipv4Addr::from(seed as u32))
+            }
+        })
+        .collect();
+
+    // time the tree lookups, decoding as little of each record as possible
+    let start = Instant::now();
+    for ip in &addresses {
+        let _ = walk.fetch_fields(ip, Fields::NONE);
+    }
+    let walk_time = start.elapsed();
+
+    let start = Instant::now();
+    for ip in &addresses {
+        let _ = indexed.fetch_fields(ip, Fields::NONE);
+    }
+    let index_time = start.elapsed();
+
+    println!(
+        "{} lookups: tree walk {:?}, index {:?} ({:.1}x faster)",
+        lookups,
+        walk_time,
+        index_time,
+        walk_time.as_secs_f64() / index_time.as_secs_f64(),
+    );
+
+    // both readers must return the same records
+    let mismatches = addresses
+        .iter()
+        .filter(|ip| {
+            let expected = walk.fetch(ip).map(|record| record.to_string());
+            let actual = indexed.fetch(ip).map(|record| record.to_string());
+            expected.ok() != actual.ok()
+        })
+        .count();
+    println!("{} mismatches", mismatches);
+    Ok(())
+}
@@ -0,0 +1,168 @@
+/* Copyright 2023 IPQualityScore LLC */
+
+#ifndef IPQS_DB_READER_H
+#define IPQS_DB_READER_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit. */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+// Result of the functions that can fail: 0 on success, the EID of the error for invalid
+// files and failed lookups, and a negative value for other failures
+typedef enum IpqsStatus {
+  IPQS_STATUS_OK = 0,
+  IPQS_STATUS_INVALID_FIRST_BYTE = 1,
+  IPQS_STATUS_INVALID_VERSION = 2,
+  IPQS_STATUS_INVALID_HEADER_BYTES = 3,
+  IPQS_STATUS_NO_COLUMN_DATA = 4,
+  IPQS_STATUS_INVALID_COLUMN_DATA = 5,
+  IPQS_STATUS_INVALID_RECORD_BYTES = 6,
+  IPQS_STATUS_BAD_BINARY_TREE = 7,
+  IPQS_STATUS_TREE_TOO_SMALL = 8,
+  // no record at or before the address
+  IPQS_STATUS_NO_RECORD_BEFORE = 9,
+  // the address is not in the blacklist file
+  IPQS_STATUS_NOT_IN_FILE = 10,
+  IPQS_STATUS_INVALID_STRING_DATA = 13,
+  // a null pointer, or a string that is not UTF-8
+  IPQS_STATUS_INVALID_ARGUMENT = -1,
+  // not an IPv4 or IPv6 address, or not of the IP version of the file
+  IPQS_STATUS_INVALID_ADDRESS = -2,
+  // the file could not be opened or read
+  IPQS_STATUS_IO = -3,
+  // a panic in the library, which is a bug
+  IPQS_STATUS_PANIC = -4,
+  IPQS_STATUS_OTHER = -5,
+} IpqsStatus;
+
+// An open database file
+typedef struct IpqsReader IpqsReader;
+
+// The record of an address, with its fields read by the `ipqs_record_` functions
+typedef struct IpqsRecord IpqsRecord;
+
+// What the header of a database file describes, see [ipqs_reader_column_name] for the columns
+typedef struct IpqsMetadata {
+  bool is_ipv6;
+  bool is_blacklist;
+  bool binary_data;
+  size_t column_count;
+  size_t record_bytes;
+  uint64_t tree_bytes;
+} IpqsMetadata;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Returns the message of the last failure on the calling thread, empty if there was none.
+// The string is valid until the next call that fails on this thread.
+const char *ipqs_last_error(void);
+
+// Opens the file at `path`, reading records from the file as they are looked up
+enum IpqsStatus ipqs_reader_open(const char *path, struct IpqsReader **out);
+
+// Reads the whole file at `path` into memory and builds its index, for the fastest lookups
+enum IpqsStatus ipqs_reader_open_in_memory(const char *path, struct IpqsReader **out);
+
+// Copies the `length` bytes of a database file at `bytes`, e.g. one embedded in the program
+enum IpqsStatus ipqs_reader_from_bytes(const uint8_t *bytes,
+                                       size_t length,
+                                       struct IpqsReader **out);
+
+// Opens another reader of the same file, for another thread, sharing the index and strings
+// already loaded by `reader`
+enum IpqsStatus ipqs_reader_clone(const struct IpqsReader *reader, struct IpqsReader **out);
+
+// Closes a reader opened by one of the `ipqs_reader_` functions. Records looked up with it
+// stay valid.
+void ipqs_reader_close(struct IpqsReader *reader);
+
+// Fills `out` with the details of the file given in its header
+enum IpqsStatus ipqs_reader_metadata(const struct IpqsReader *reader, struct IpqsMetadata *out);
+
+// Returns the name of the column at `index`, in file order, or null past the last column
+const char *ipqs_reader_column_name(const struct IpqsReader *reader, size_t index);
+
+// Looks up an address written as text, e.g. `"8.8.8.8"` or `"2001:4860:4860::8888"`
+enum IpqsStatus ipqs_reader_lookup(struct IpqsReader *reader,
+                                   const char *ip,
+                                   struct IpqsRecord **out);
+
+// Looks up an address given as 4 (IPv4) or 16 (IPv6) bytes in network order, such as the
+// `sin_addr` of a `sockaddr_in` or the `sin6_addr` of a `sockaddr_in6`
+enum IpqsStatus ipqs_reader_lookup_binary(struct IpqsReader *reader,
+                                          const uint8_t *address,
+                                          size_t length,
+                                          struct IpqsRecord **out);
+
+// Frees a record returned by a lookup
+void ipqs_record_free(struct IpqsRecord *record);
+
+// Writes whether the address is a proxy to `out`, returning false if the file does not have
+// the field. The other accessors of optional fields work the same way.
+bool ipqs_record_is_proxy(const struct IpqsRecord *record, bool *out);
+
+bool ipqs_record_is_vpn(const struct IpqsRecord *record, bool *out);
+
+bool ipqs_record_is_tor(const struct IpqsRecord *record, bool *out);
+
+bool ipqs_record_is_crawler(const struct IpqsRecord *record, bool *out);
+
+bool ipqs_record_is_bot(const struct IpqsRecord *record, bool *out);
+
+bool ipqs_record_recent_abuse(const struct IpqsRecord *record, bool *out);
+
+bool ipqs_record_is_blacklisted(const struct IpqsRecord *record, bool *out);
+
+bool ipqs_record_is_private(const struct IpqsRecord *record, bool *out);
+
+bool ipqs_record_is_mobile(const struct IpqsRecord *record, bool *out);
+
+bool ipqs_record_has_open_ports(const struct IpqsRecord *record, bool *out);
+
+bool ipqs_record_is_hosting_provider(const struct IpqsRecord *record, bool *out);
+
+bool ipqs_record_active_vpn(const struct IpqsRecord *record, bool *out);
+
+bool ipqs_record_active_tor(const struct IpqsRecord *record, bool *out);
+
+bool ipqs_record_public_access_point(const struct IpqsRecord *record, bool *out);
+
+bool ipqs_record_asn(const struct IpqsRecord *record, uint64_t *out);
+
+bool ipqs_record_latitude(const struct IpqsRecord *record, float *out);
+
+bool ipqs_record_longitude(const struct IpqsRecord *record, float *out);
+
+// Writes the fraud score for `strictness` (0 to 3) to `out` and returns true if the record
+// has one
+bool ipqs_record_fraud_score(const struct IpqsRecord *record, uint32_t strictness, uint32_t *out);
+
+// Returns the two letter country code, or null if the file does not have it. The other
+// string accessors work the same way.
+const char *ipqs_record_country(const struct IpqsRecord *record);
+
+const char *ipqs_record_city(const struct IpqsRecord *record);
+
+const char *ipqs_record_region(const struct IpqsRecord *record);
+
+const char *ipqs_record_isp(const struct IpqsRecord *record);
+
+const char *ipqs_record_organization(const struct IpqsRecord *record);
+
+const char *ipqs_record_timezone(const struct IpqsRecord *record);
+
+// Returns the connection type, e.g. `"Residential"`, `"Unknown"` if the file does not have it
+const char *ipqs_record_connection_type(const struct IpqsRecord *record);
+
+// Returns the abuse velocity, e.g. `"high"`, `"none"` if the file does not have it
+const char *ipqs_record_abuse_velocity(const struct IpqsRecord *record);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* IPQS_DB_READER_H */
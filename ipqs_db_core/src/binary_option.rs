@@ -47,8 +47,12 @@ pub const THREE_UNION_TWO: u8 = CONNECTION_TYPE_THREE | CONNECTION_TYPE_TWO;
 pub const THREE_UNION_ONE: u8 = CONNECTION_TYPE_THREE | CONNECTION_TYPE_ONE;
 pub const ABUSE_BOTH: u8 = ABUSE_VELOCITY_ONE | ABUSE_VELOCITY_TWO;
 
-#[derive(Clone, Debug, Default)]
-#[cfg_attr(feature = "json", derive(serde::Serialize))]
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 pub struct BinaryOption {
     pub data: u8,
 }
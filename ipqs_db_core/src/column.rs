@@ -1,12 +1,14 @@
 // Copyright 2023 IPQualityScore LLC
 
 use crate::binary_option::BinaryOption;
+use alloc::string::String;
+use alloc::sync::Arc;
 
 // Copyright 2023 IPQualityScore LLC
-#[derive(Clone, Debug, Default)]
-#[cfg_attr(feature = "json", derive(serde::Serialize))]
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Column {
     pub name: String,
     pub record_type: BinaryOption,
-    pub value: String,
+    pub value: Arc<str>,
 }
@@ -0,0 +1,91 @@
+// Copyright 2023 IPQualityScore LLC
+use alloc::sync::Arc;
+use core::net::IpAddr;
+
+use crate::utility::{slice_at, string_at};
+use crate::{tree, Error, Fields, Header, Metadata, Record};
+
+/// A database held in memory as a byte slice, such as a file embedded in the program or
+/// stored in flash. Lookups walk the tree in the slice and only allocate the returned
+/// [Record].
+/// ```
+/// use ipqs_db_core::Database;
+///
+/// fn is_vpn(database: &[u8], ip: &str) -> bool {
+///     let database = match Database::new(database) {
+///         Ok(database) => database,
+///         Err(_) => return false,
+///     };
+///     ip.parse()
+///         .ok()
+///         .and_then(|ip| database.fetch(&ip).ok())
+///         .and_then(|record| record.is_vpn())
+///         .unwrap_or(false)
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Database<'a> {
+    data: &'a [u8],
+    header: Header,
+}
+
+impl<'a> Database<'a> {
+    /// Parses the header of the database in `data`
+    pub fn new(data: &'a [u8]) -> Result<Database<'a>, Error> {
+        Ok(Database {
+            header: Header::parse(data)?,
+            data,
+        })
+    }
+
+    /// Retrieve the record associated with `IpAddr`, if one exists
+    pub fn fetch(&self, ip: &IpAddr) -> Result<Record, Error> {
+        self.fetch_fields(ip, Fields::ALL)
+    }
+
+    /// Retrieve the record associated with `IpAddr`, decoding only the selected `fields`.
+    /// Every other field of the returned record is None.
+    pub fn fetch_fields(&self, ip: &IpAddr, fields: Fields) -> Result<Record, Error> {
+        if self.header.is_v6 && ip.is_ipv4() {
+            return Err(Error::Ipv4InIpv6File);
+        }
+        if !self.header.is_v6 && ip.is_ipv6() {
+            return Err(Error::Ipv6InIpv4File);
+        }
+
+        let data = self.data;
+        let (file_position, _) = tree::find_record(
+            ip,
+            self.header.tree_start,
+            self.header.tree_end,
+            self.header.is_blacklist,
+            |position, node| {
+                node.copy_from_slice(slice_at(data, position, 8)?);
+                Ok(())
+            },
+        )?;
+        let raw = slice_at(data, file_position, self.header.record_bytes)?;
+        Record::parse(
+            raw,
+            &self.header.columns,
+            self.header.binary_data,
+            fields,
+            |offset| Ok(Arc::from(string_at(data, offset)?)),
+        )
+    }
+
+    /// Returns the header of the database
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Returns the details of the file given in its header
+    pub fn metadata(&self) -> Metadata {
+        self.header.metadata()
+    }
+
+    /// Returns true if the file contains IPv6 addresses
+    pub fn is_ipv6(&self) -> bool {
+        self.header.is_v6
+    }
+}
@@ -0,0 +1,64 @@
+// Copyright 2023 IPQualityScore LLC
+use core::fmt;
+
+/// Errors of decoding a database and looking up addresses. Most messages end with the EID
+/// (error ID) given to the error in the flat file documentation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// the first byte is neither an IPv4 nor an IPv6 file (EID 1)
+    InvalidFirstByte,
+    /// the file was written for another version of the reader (EID 2)
+    InvalidVersion,
+    /// the header size is invalid (EID 3)
+    InvalidHeader,
+    /// the header has no columns (EID 4)
+    NoColumns,
+    /// the column headers are not 24 bytes each (EID 5)
+    InvalidColumns,
+    /// the record size is invalid (EID 6)
+    InvalidRecordBytes,
+    /// the tree is invalid (EID 7)
+    InvalidTree,
+    /// the tree is empty (EID 8)
+    TreeTooSmall,
+    /// there is no record at or before the address (EID 9)
+    NoRecordBefore,
+    /// a blacklist file does not contain the address (EID 10)
+    NotInFile,
+    /// a column or string could not be decoded (EID 13)
+    InvalidString,
+    /// a position in the file is past its end
+    UnexpectedEnd,
+    /// a variable length integer does not fit in 64 bits
+    VarintOverflow,
+    /// an IPv4 address was looked up in an IPv6 file
+    Ipv4InIpv6File,
+    /// an IPv6 address was looked up in an IPv4 file
+    Ipv6InIpv4File,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Error::InvalidFirstByte => "invalid file format, invalid first byte (EID 1)",
+            Error::InvalidVersion => "invalid file version (EID 2)",
+            Error::InvalidHeader => "invalid file format, invalid header bytes (EID 3)",
+            Error::NoColumns => "file appears to be invalid, no column data found (EID 4)",
+            Error::InvalidColumns => "invalid column data, too many or too few bytes (EID 5)",
+            Error::InvalidRecordBytes => "invalid file format, invalid record bytes (EID 6)",
+            Error::InvalidTree => "file does not appear to be valid, bad binary tree (EID 7)",
+            Error::TreeTooSmall => {
+                "File does not appear to be valid, tree size is too small (EID 8)"
+            }
+            Error::NoRecordBefore => "invalid or nonexistent IP specified for lookup (EID 9)",
+            Error::NotInFile => "invalid or nonexistent IP specified for lookup (EID 10)",
+            Error::InvalidString => "failed to parse string data (EID 13)",
+            Error::UnexpectedEnd => "attempted to read past the end of the file",
+            Error::VarintOverflow => "variable length integer overflows 64 bits",
+            Error::Ipv4InIpv6File => "attempted to fetch IPv4 record using IPv6 data file",
+            Error::Ipv6InIpv4File => "attempted to fetch IPv6 record using IPv4 data file",
+        })
+    }
+}
+
+impl core::error::Error for Error {}
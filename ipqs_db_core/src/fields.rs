@@ -0,0 +1,74 @@
+// Copyright 2023 IPQualityScore LLC
+use core::ops::{BitOr, BitOrAssign};
+
+/// A selection of [Record](crate::Record) fields, used with
+/// [Database::fetch_fields](crate::Database::fetch_fields) to decode only the fields
+/// a caller needs. Fields can be combined with `|`:
+/// ```
+/// use ipqs_db_core::Fields;
+/// let fields = Fields::IS_PROXY | Fields::IS_VPN | Fields::FRAUD_SCORE_ONE;
+/// assert!(fields.contains(Fields::IS_VPN));
+/// assert!(!fields.contains(Fields::COUNTRY));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Fields(u32);
+
+impl Fields {
+    pub const NONE: Fields = Fields(0);
+    pub const ALL: Fields = Fields((1 << 27) - 1);
+
+    pub const IS_PROXY: Fields = Fields(1 << 0);
+    pub const IS_VPN: Fields = Fields(1 << 1);
+    pub const IS_TOR: Fields = Fields(1 << 2);
+    pub const IS_CRAWLER: Fields = Fields(1 << 3);
+    pub const IS_BOT: Fields = Fields(1 << 4);
+    pub const RECENT_ABUSE: Fields = Fields(1 << 5);
+    pub const IS_BLACKLISTED: Fields = Fields(1 << 6);
+    pub const IS_PRIVATE: Fields = Fields(1 << 7);
+    pub const IS_MOBILE: Fields = Fields(1 << 8);
+    pub const HAS_OPEN_PORTS: Fields = Fields(1 << 9);
+    pub const IS_HOSTING_PROVIDER: Fields = Fields(1 << 10);
+    pub const ACTIVE_VPN: Fields = Fields(1 << 11);
+    pub const ACTIVE_TOR: Fields = Fields(1 << 12);
+    pub const PUBLIC_ACCESS_POINT: Fields = Fields(1 << 13);
+
+    pub const COUNTRY: Fields = Fields(1 << 14);
+    pub const CITY: Fields = Fields(1 << 15);
+    pub const REGION: Fields = Fields(1 << 16);
+    pub const ISP: Fields = Fields(1 << 17);
+    pub const ORGANIZATION: Fields = Fields(1 << 18);
+    pub const ASN: Fields = Fields(1 << 19);
+    pub const TIMEZONE: Fields = Fields(1 << 20);
+    pub const LATITUDE: Fields = Fields(1 << 21);
+    pub const LONGITUDE: Fields = Fields(1 << 22);
+
+    pub const FRAUD_SCORE_ZERO: Fields = Fields(1 << 23);
+    pub const FRAUD_SCORE_ONE: Fields = Fields(1 << 24);
+    pub const FRAUD_SCORE_TWO: Fields = Fields(1 << 25);
+    pub const FRAUD_SCORE_THREE: Fields = Fields(1 << 26);
+
+    /// Returns true if every field in `other` is selected
+    pub fn contains(self, other: Fields) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl Default for Fields {
+    fn default() -> Fields {
+        Fields::ALL
+    }
+}
+
+impl BitOr for Fields {
+    type Output = Fields;
+
+    fn bitor(self, other: Fields) -> Fields {
+        Fields(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for Fields {
+    fn bitor_assign(&mut self, other: Fields) {
+        self.0 |= other.0;
+    }
+}
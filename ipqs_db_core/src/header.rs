@@ -0,0 +1,241 @@
+// Copyright 2023 IPQualityScore LLC
+use alloc::borrow::ToOwned;
+use alloc::vec::Vec;
+
+use crate::binary_option as flag;
+use crate::binary_option::BinaryOption;
+use crate::column::Column;
+use crate::metadata::Metadata;
+use crate::{utility, variable_length_int, Error};
+
+const RUST_IPQS_READER_VERSION: u8 = 0x01;
+
+const IPV4_MAP: u8 = 0b0000_0001;
+const IPV6_MAP: u8 = 0b0000_0010;
+const BLACKLIST_FILE: u8 = 0b0000_0100;
+// const RESERVED_SEVEN: u8 = 0b0000_1000;
+// const RESERVED_EIGHT: u8 = 0b0001_0000;
+// const RESERVED_NINE: u8 = 0b0010_0000;
+// const RESERVED_TEN: u8 = 0b0100_0000;
+const BINARY_DATA: u8 = 0b1000_0000;
+
+/// The file details, columns and tree bounds at the start of a database file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Header {
+    pub is_v6: bool,
+    pub is_blacklist: bool,
+    /// records have two bytes of binary flags (is_proxy, is_vpn, ...)
+    pub binary_data: bool,
+    #[doc(hidden)]
+    pub columns: Vec<Column>,
+    /// size of each record
+    pub record_bytes: usize,
+    /// file position of the tree header, the tree nodes start 5 bytes later
+    pub tree_start: u64,
+    /// file position of the end of the tree, every pointer at or after it points to a record
+    pub tree_end: u64,
+}
+
+impl Header {
+    /// Number of bytes at the start of the file that [Header::size] needs
+    pub const PREFIX_BYTES: usize = 11;
+
+    /// Returns the number of bytes at the start of the file that [Header::parse] reads, from
+    /// the first [Header::PREFIX_BYTES] bytes of the file. Readers that do not hold the whole
+    /// file in memory can read that many bytes and parse them.
+    pub fn size(prefix: &[u8]) -> Result<usize, Error> {
+        let tree_start = Prefix::parse(prefix)?.tree_start;
+        usize::try_from(tree_start + 5).map_err(|_| Error::InvalidHeader)
+    }
+
+    /// Parses the header at the start of `bytes`, which holds the beginning of the file,
+    /// at least [Header::size] bytes of it
+    pub fn parse(bytes: &[u8]) -> Result<Header, Error> {
+        let prefix = Prefix::parse(bytes)?;
+
+        // column pairs
+        // header bytes 2,3,4 give the length of the header in bytes
+        // the tree begins at the end of the header
+        // after the first 11 bytes, the remaining bytes in the header are column headers
+        // each header is 24 bytes long
+        let header_size = usize::try_from(prefix.tree_start).map_err(|_| Error::InvalidHeader)?;
+        let column_bytes_length = header_size
+            .checked_sub(Header::PREFIX_BYTES)
+            .ok_or(Error::InvalidHeader)?;
+        if column_bytes_length == 0 {
+            return Err(Error::NoColumns);
+        }
+        if !column_bytes_length.is_multiple_of(24) {
+            return Err(Error::InvalidColumns);
+        }
+
+        // consume column headers
+        let column_bytes = utility::slice_at(bytes, 11, column_bytes_length)?;
+        let mut columns = Vec::new();
+        // insert column name + record type pairs into column vector
+        for column in column_bytes.chunks_exact(24) {
+            // first 23 bytes of column header are 0-padded character strings
+            // interpret the slice of bytes from 0-23 as UTF-8, trim the end, and take ownership
+            let name = core::str::from_utf8(&column[..23])
+                .map_err(|_| Error::InvalidColumns)?
+                .trim_end_matches(char::from(0x00))
+                .to_owned();
+            // 24th byte is record type
+            columns.push(Column {
+                name,
+                record_type: BinaryOption { data: column[23] },
+                value: Default::default(), // empty String
+            });
+        }
+
+        // Tree Metadata
+        let tree_header = utility::slice_at(bytes, prefix.tree_start, 5)?;
+        let tree_type = BinaryOption {
+            data: tree_header[0],
+        };
+        if !tree_type.has(flag::TREE_DATA) {
+            return Err(Error::InvalidTree);
+        }
+        let total_tree = utility::four_byte_int(&tree_header[1..5]);
+        if total_tree == 0 {
+            return Err(Error::TreeTooSmall);
+        }
+
+        Ok(Header {
+            is_v6: prefix.is_v6,
+            is_blacklist: prefix.is_blacklist,
+            binary_data: prefix.binary_data,
+            columns,
+            record_bytes: prefix.record_bytes,
+            tree_start: prefix.tree_start,
+            tree_end: prefix.tree_start + total_tree,
+        })
+    }
+
+    /// Returns the details of the file given in the header
+    pub fn metadata(&self) -> Metadata {
+        Metadata {
+            is_ipv6: self.is_v6,
+            is_blacklist: self.is_blacklist,
+            binary_data: self.binary_data,
+            columns: self
+                .columns
+                .iter()
+                .map(|column| column.name.clone())
+                .collect(),
+            record_bytes: self.record_bytes,
+            tree_bytes: self.tree_end - self.tree_start,
+        }
+    }
+}
+
+/// What the first 11 bytes of the file give
+struct Prefix {
+    is_v6: bool,
+    is_blacklist: bool,
+    binary_data: bool,
+    tree_start: u64,
+    record_bytes: usize,
+}
+
+impl Prefix {
+    fn parse(bytes: &[u8]) -> Result<Prefix, Error> {
+        //---------------- METADATA BEGIN
+
+        // first 11 bytes reserved for file metadata
+        let header = utility::slice_at(bytes, 0, Header::PREFIX_BYTES)?;
+
+        // first byte of header holds file option details
+        let binary_option = BinaryOption { data: header[0] };
+
+        let binary_data = binary_option.has(BINARY_DATA);
+
+        let is_v6 = binary_option.has(IPV6_MAP);
+
+        // file is only valid if IPv6 XOR IPv4
+        let is_valid = is_v6 ^ binary_option.has(IPV4_MAP);
+        if !is_valid {
+            return Err(Error::InvalidFirstByte);
+        }
+
+        let is_blacklist = binary_option.has(BLACKLIST_FILE);
+
+        // flat file db and library crate version must match
+        if header[1] != RUST_IPQS_READER_VERSION {
+            return Err(Error::InvalidVersion);
+        }
+
+        let tree_start = variable_length_int::uvarint64(&header[2..5])?;
+        if tree_start == 0 {
+            return Err(Error::InvalidHeader);
+        }
+
+        let record_bytes = usize::try_from(variable_length_int::uvarint64(&header[5..7])?)
+            .map_err(|_| Error::InvalidRecordBytes)?;
+        if record_bytes == 0 {
+            return Err(Error::InvalidRecordBytes);
+        }
+
+        // total bytes - should match file size in bytes
+        //let total_bytes = utility::four_byte_int(&header[7..11]);
+
+        //---------------- METADATA END
+
+        Ok(Prefix {
+            is_v6,
+            is_blacklist,
+            binary_data,
+            tree_start,
+            record_bytes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // an IPv4 file with binary data, one ASN column, 7-byte records and a 13-byte tree
+    fn file() -> Vec<u8> {
+        let mut bytes = vec![IPV4_MAP | BINARY_DATA, RUST_IPQS_READER_VERSION];
+        bytes.extend_from_slice(&[35, 0, 0, 7, 0, 0, 0, 0, 0]);
+        let mut column = [0u8; 24];
+        column[..3].copy_from_slice(b"ASN");
+        column[23] = flag::INT_DATA;
+        bytes.extend_from_slice(&column);
+        bytes.extend_from_slice(&[flag::TREE_DATA, 13, 0, 0, 0]);
+        bytes
+    }
+
+    #[test]
+    fn parses_header() -> Result<(), Error> {
+        let bytes = file();
+        assert_eq!(Header::size(&bytes[..Header::PREFIX_BYTES])?, bytes.len());
+        let header = Header::parse(&bytes)?;
+        assert!(!header.is_v6 && !header.is_blacklist && header.binary_data);
+        assert_eq!(header.columns.len(), 1);
+        assert_eq!(header.columns[0].name, "ASN");
+        assert_eq!(header.record_bytes, 7);
+        assert_eq!((header.tree_start, header.tree_end), (35, 48));
+        assert_eq!(header.metadata().columns, ["ASN"]);
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_invalid_headers() {
+        let invalid = |change: fn(&mut Vec<u8>)| {
+            let mut bytes = file();
+            change(&mut bytes);
+            Header::parse(&bytes).unwrap_err()
+        };
+        assert_eq!(invalid(|bytes| bytes[0] = 0), Error::InvalidFirstByte);
+        assert_eq!(invalid(|bytes| bytes[1] = 2), Error::InvalidVersion);
+        assert_eq!(invalid(|bytes| bytes[2] = 5), Error::InvalidHeader);
+        assert_eq!(invalid(|bytes| bytes[2] = 11), Error::NoColumns);
+        assert_eq!(invalid(|bytes| bytes[2] = 34), Error::InvalidColumns);
+        assert_eq!(invalid(|bytes| bytes[5] = 0), Error::InvalidRecordBytes);
+        assert_eq!(invalid(|bytes| bytes[35] = 0), Error::InvalidTree);
+        assert_eq!(invalid(|bytes| bytes[36] = 0), Error::TreeTooSmall);
+        assert_eq!(invalid(|bytes| bytes.truncate(38)), Error::UnexpectedEnd);
+    }
+}
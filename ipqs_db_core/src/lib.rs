@@ -0,0 +1,85 @@
+// Copyright 2023 IPQualityScore LLC
+//! # IPQualityScore Flat File Database Core
+//!
+//! Decoding of the IPQualityScore IP reputation flat file database, without the standard
+//! library: only `core` and `alloc` are used, so lookups can be embedded in appliances and
+//! other targets without a file system. The whole database is read from a byte slice, e.g. a
+//! file stored in flash:
+//! ```
+//! use ipqs_db_core::{Database, Error, Strictness};
+//! use core::net::IpAddr;
+//!
+//! fn fraud_score(database: &[u8], ip: IpAddr) -> Result<Option<u32>, Error> {
+//!     let database = Database::new(database)?;
+//!     match database.fetch(&ip) {
+//!         Ok(record) => Ok(record.fraud_score(Strictness::Zero)),
+//!         Err(Error::NoRecordBefore | Error::NotInFile) => Ok(None),
+//!         Err(error) => Err(error),
+//!     }
+//! }
+//! ```
+//! The `ipqs_db_reader` crate builds its file-based reader on top of this crate.
+//!
+//! With the `serde` feature, [Record], [ApiResponse] and [Metadata] can be serialized.
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+mod database;
+mod error;
+pub mod fields;
+pub mod header;
+pub mod metadata;
+pub mod record;
+pub mod tree;
+pub mod variable_length_int;
+
+// building blocks shared with ipqs_db_reader, not part of the API
+#[doc(hidden)]
+pub mod binary_option;
+#[doc(hidden)]
+pub mod column;
+
+pub use database::Database;
+pub use error::Error;
+pub use fields::Fields;
+pub use header::Header;
+pub use metadata::Metadata;
+#[cfg(feature = "serde")]
+pub use record::ApiResponse;
+pub use record::{Record, RecordBuilder, Strictness};
+
+#[doc(hidden)]
+pub mod utility {
+    use crate::Error;
+
+    // interpret an array of four bytes as a Little Endian unsigned integer
+    pub fn four_byte_int(bytes: &[u8]) -> u64 {
+        let mut buffer = [0u8; 4];
+        buffer[..4].copy_from_slice(bytes);
+
+        u32::from_le_bytes(buffer) as u64
+    }
+    // interpret an array of four bytes as a 32-bit floating-point number
+    pub fn four_byte_float(bytes: &[u8]) -> f32 {
+        let mut buffer = [0u8; 4];
+        buffer[..4].copy_from_slice(bytes);
+
+        f32::from_le_bytes(buffer)
+    }
+
+    /// Returns the `len` bytes starting at `position`
+    pub fn slice_at(data: &[u8], position: u64, len: usize) -> Result<&[u8], Error> {
+        usize::try_from(position)
+            .ok()
+            .and_then(|start| data.get(start..start.checked_add(len)?))
+            .ok_or(Error::UnexpectedEnd)
+    }
+
+    /// Returns the length-prefixed string at `offset`
+    pub fn string_at(data: &[u8], offset: u64) -> Result<&str, Error> {
+        let size = slice_at(data, offset, 1)?[0];
+        let raw = slice_at(data, offset + 1, usize::from(size))?;
+        core::str::from_utf8(raw).map_err(|_| Error::InvalidString)
+    }
+}
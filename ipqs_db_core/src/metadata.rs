@@ -0,0 +1,18 @@
+// Copyright 2023 IPQualityScore LLC
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// What the header of a database file describes, returned by
+/// [Database::metadata](crate::Database::metadata)
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Metadata {
+    pub is_ipv6: bool,
+    pub is_blacklist: bool,
+    /// records have the binary flags (is_proxy, is_vpn, ...)
+    pub binary_data: bool,
+    /// names of the columns each record has, in file order
+    pub columns: Vec<String>,
+    pub record_bytes: usize,
+    pub tree_bytes: u64,
+}
@@ -2,15 +2,26 @@
 use crate::binary_option as flag;
 use crate::binary_option::BinaryOption;
 use crate::column::Column;
-use crate::file_reader::FileReader;
-use crate::utility;
-use std::error::Error;
-use std::fmt;
+use crate::fields::Fields;
+use crate::{utility, Error};
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+
+#[cfg(feature = "serde")]
+pub use api::ApiResponse;
+pub use builder::RecordBuilder;
+
+#[cfg(feature = "serde")]
+mod api;
+mod builder;
 
 /// How in depth (strict) do you want this query to be? Higher values
 /// may provide a higher false-positive rate. We recommend starting at "0", the lowest strictness setting,
 /// and increasing to "1" depending on your levels of fraud. Levels 2+ are VERY strict and will produce false-positives.
 /// Note that not all files have values for each level of strictness.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Strictness {
     Zero,
     One,
@@ -24,18 +35,66 @@ pub enum Strictness {
 ///
 /// For more details about any of the particular values, please see the
 /// official [IPQualityScore Flat File Database documentation](https://www.ipqualityscore.com/documentation/ip-reputation-database/overview).
-#[derive(Clone, Default, Debug)]
-#[cfg_attr(feature = "json", derive(serde::Serialize))]
+///
+/// # JSON
+///
+/// With the `serde` feature (the `json` feature of ipqs_db_reader), records serialize to and
+/// deserialize from an object with one key per field, named like the accessors. Fields missing
+/// from the file are `null`, the fraud scores are an array indexed by [Strictness], and
+/// `columns` lists every column decoded from the file with its column type and value as a
+/// string:
+/// ```json
+/// {
+///   "connection_type": "Residential",
+///   "abuse_velocity": "none",
+///   "country": "US",
+///   "city": "Mountain View",
+///   "region": "California",
+///   "isp": "Google",
+///   "organization": "Google",
+///   "asn": 15169,
+///   "timezone": "America/Los_Angeles",
+///   "latitude": 37.386,
+///   "longitude": -122.0838,
+///   "fraud_score": { "strictness": [0, 0, null, null] },
+///   "is_proxy": false,
+///   "is_vpn": false,
+///   "is_tor": false,
+///   "is_crawler": false,
+///   "is_bot": false,
+///   "recent_abuse": false,
+///   "is_blacklisted": false,
+///   "is_private": false,
+///   "is_mobile": false,
+///   "has_open_ports": false,
+///   "is_hosting_provider": true,
+///   "active_vpn": false,
+///   "active_tor": false,
+///   "public_access_point": false,
+///   "columns": [
+///     { "name": "ASN", "record_type": 32, "value": "15169" },
+///     { "name": "Country", "record_type": 8, "value": "US" }
+///   ]
+/// }
+/// ```
+/// Missing keys deserialize as `null` (or an empty string and list), so JSON written by older
+/// versions of the crate, which did not include `columns`, can still be read.
+#[derive(Clone, Default, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
 pub struct Record {
     connection_type: String,
     abuse_velocity: String,
-    country: Option<String>,
-    city: Option<String>,
-    region: Option<String>,
-    isp: Option<String>,
-    organization: Option<String>,
+    country: Option<Arc<str>>,
+    city: Option<Arc<str>>,
+    region: Option<Arc<str>>,
+    isp: Option<Arc<str>>,
+    organization: Option<Arc<str>>,
     asn: Option<u64>,
-    timezone: Option<String>,
+    timezone: Option<Arc<str>>,
     latitude: Option<f32>,
     longitude: Option<f32>,
     fraud_score: FraudScore,
@@ -54,7 +113,6 @@ pub struct Record {
     active_tor: Option<bool>,
     public_access_point: Option<bool>,
 
-    #[cfg_attr(feature = "json", serde(skip_serializing))]
     columns: Vec<Column>,
 }
 
@@ -126,30 +184,60 @@ Public Access Point: {:#?}",
 }
 
 impl Record {
-    /// Parses the raw bytes at the leaf of the tree into a usable Record struct
-    pub(crate) fn parse(raw: Vec<u8>, file: &mut FileReader) -> Result<Record, Box<dyn Error>> {
+    /// Parses the raw bytes at the leaf of the tree into a usable Record struct.
+    /// Only the selected `fields` are decoded, every other field is left as None.
+    /// `string_value` returns the string stored at an offset in the string pool, and its
+    /// errors are returned as they are.
+    #[doc(hidden)]
+    pub fn parse<F, E>(
+        raw: &[u8],
+        columns: &[Column],
+        binary_data: bool,
+        fields: Fields,
+        mut string_value: F,
+    ) -> Result<Record, E>
+    where
+        F: FnMut(u64) -> Result<Arc<str>, E>,
+        E: From<Error>,
+    {
         let mut current_byte = 0;
-        let mut record = Record::default();
+        let mut record = Record {
+            columns: Vec::with_capacity(columns.len()),
+            ..Default::default()
+        };
         // files with the binary data flag set have two additional bytes per record
-        if file.binary_data {
+        if binary_data {
+            let requested = |field: Fields, byte: &BinaryOption, mask: u8| {
+                fields.contains(field).then(|| byte.has(mask))
+            };
             // byte 1
             let first_byte = BinaryOption { data: raw[0] };
-            record.is_proxy = Some(first_byte.has(flag::IS_PROXY));
-            record.is_vpn = Some(first_byte.has(flag::IS_VPN));
-            record.is_tor = Some(first_byte.has(flag::IS_TOR));
-            record.is_crawler = Some(first_byte.has(flag::IS_CRAWLER));
-            record.is_bot = Some(first_byte.has(flag::IS_BOT));
-            record.recent_abuse = Some(first_byte.has(flag::RECENT_ABUSE));
-            record.is_blacklisted = Some(first_byte.has(flag::IS_BLACKLISTED));
-            record.is_private = Some(first_byte.has(flag::IS_PRIVATE));
+            record.is_proxy = requested(Fields::IS_PROXY, &first_byte, flag::IS_PROXY);
+            record.is_vpn = requested(Fields::IS_VPN, &first_byte, flag::IS_VPN);
+            record.is_tor = requested(Fields::IS_TOR, &first_byte, flag::IS_TOR);
+            record.is_crawler = requested(Fields::IS_CRAWLER, &first_byte, flag::IS_CRAWLER);
+            record.is_bot = requested(Fields::IS_BOT, &first_byte, flag::IS_BOT);
+            record.recent_abuse = requested(Fields::RECENT_ABUSE, &first_byte, flag::RECENT_ABUSE);
+            record.is_blacklisted =
+                requested(Fields::IS_BLACKLISTED, &first_byte, flag::IS_BLACKLISTED);
+            record.is_private = requested(Fields::IS_PRIVATE, &first_byte, flag::IS_PRIVATE);
             // byte 2
             let second_byte = BinaryOption { data: raw[1] };
-            record.is_mobile = Some(second_byte.has(flag::IS_MOBILE));
-            record.has_open_ports = Some(second_byte.has(flag::HAS_OPEN_PORTS));
-            record.is_hosting_provider = Some(second_byte.has(flag::IS_HOSTING_PROVIDER));
-            record.active_vpn = Some(second_byte.has(flag::ACTIVE_VPN));
-            record.active_tor = Some(second_byte.has(flag::ACTIVE_TOR));
-            record.public_access_point = Some(second_byte.has(flag::PUBLIC_ACCESS_POINT));
+            record.is_mobile = requested(Fields::IS_MOBILE, &second_byte, flag::IS_MOBILE);
+            record.has_open_ports =
+                requested(Fields::HAS_OPEN_PORTS, &second_byte, flag::HAS_OPEN_PORTS);
+            record.is_hosting_provider = requested(
+                Fields::IS_HOSTING_PROVIDER,
+                &second_byte,
+                flag::IS_HOSTING_PROVIDER,
+            );
+            record.active_vpn = requested(Fields::ACTIVE_VPN, &second_byte, flag::ACTIVE_VPN);
+            record.active_tor = requested(Fields::ACTIVE_TOR, &second_byte, flag::ACTIVE_TOR);
+            record.public_access_point = requested(
+                Fields::PUBLIC_ACCESS_POINT,
+                &second_byte,
+                flag::PUBLIC_ACCESS_POINT,
+            );
 
             current_byte = 2;
         }
@@ -161,106 +249,138 @@ impl Record {
         record.abuse_velocity = abuse_velocity(common_byte).to_string();
 
         // columns
-        let mut value: String;
-        for c in 0..file.columns.len() {
-            let column = &(file.columns[c]);
+        let mut value: Arc<str>;
+        for column in columns {
             match column.name.as_str() {
                 "ASN" => {
-                    let u = utility::four_byte_int(&raw[current_byte..current_byte + 4]);
-                    record.asn = Some(u);
-                    value = u.to_string();
-                    record.columns.push(Column {
-                        name: column.name.clone(),
-                        record_type: BinaryOption {
-                            data: flag::INT_DATA,
-                        },
-                        value,
-                    });
+                    if fields.contains(Fields::ASN) {
+                        let u = utility::four_byte_int(&raw[current_byte..current_byte + 4]);
+                        record.asn = Some(u);
+                        value = u.to_string().into();
+                        record.columns.push(Column {
+                            name: column.name.clone(),
+                            record_type: BinaryOption {
+                                data: flag::INT_DATA,
+                            },
+                            value,
+                        });
+                    }
                     current_byte += 4;
                 }
                 "Latitude" => {
-                    let f = utility::four_byte_float(&raw[current_byte..current_byte + 4]);
-                    record.latitude = Some(f);
-                    value = f.to_string();
-                    record.columns.push(Column {
-                        name: column.name.clone(),
-                        record_type: BinaryOption {
-                            data: flag::FLOAT_DATA,
-                        },
-                        value,
-                    });
+                    if fields.contains(Fields::LATITUDE) {
+                        let f = utility::four_byte_float(&raw[current_byte..current_byte + 4]);
+                        record.latitude = Some(f);
+                        value = f.to_string().into();
+                        record.columns.push(Column {
+                            name: column.name.clone(),
+                            record_type: BinaryOption {
+                                data: flag::FLOAT_DATA,
+                            },
+                            value,
+                        });
+                    }
                     current_byte += 4;
                 }
                 "Longitude" => {
-                    let f = utility::four_byte_float(&raw[current_byte..current_byte + 4]);
-                    record.longitude = Some(f);
-                    value = f.to_string();
-                    record.columns.push(Column {
-                        name: column.name.clone(),
-                        record_type: BinaryOption {
-                            data: flag::FLOAT_DATA,
-                        },
-                        value,
-                    });
+                    if fields.contains(Fields::LONGITUDE) {
+                        let f = utility::four_byte_float(&raw[current_byte..current_byte + 4]);
+                        record.longitude = Some(f);
+                        value = f.to_string().into();
+                        record.columns.push(Column {
+                            name: column.name.clone(),
+                            record_type: BinaryOption {
+                                data: flag::FLOAT_DATA,
+                            },
+                            value,
+                        });
+                    }
                     current_byte += 4;
                 }
                 "ZeroFraudScore" => {
-                    let u = u32::from(raw[current_byte]);
-                    record.fraud_score.strictness[0] = Some(u);
-                    value = u.to_string();
-                    record.columns.push(Column {
-                        name: column.name.clone(),
-                        record_type: BinaryOption {
-                            data: flag::SMALL_INT_DATA,
-                        },
-                        value,
-                    });
+                    if fields.contains(Fields::FRAUD_SCORE_ZERO) {
+                        let u = u32::from(raw[current_byte]);
+                        record.fraud_score.strictness[0] = Some(u);
+                        value = u.to_string().into();
+                        record.columns.push(Column {
+                            name: column.name.clone(),
+                            record_type: BinaryOption {
+                                data: flag::SMALL_INT_DATA,
+                            },
+                            value,
+                        });
+                    }
                     current_byte += 1;
                 }
                 "OneFraudScore" => {
-                    let u = u32::from(raw[current_byte]);
-                    record.fraud_score.strictness[1] = Some(u);
-                    value = u.to_string();
-                    record.columns.push(Column {
-                        name: column.name.clone(),
-                        record_type: BinaryOption {
-                            data: flag::SMALL_INT_DATA,
-                        },
-                        value,
-                    });
+                    if fields.contains(Fields::FRAUD_SCORE_ONE) {
+                        let u = u32::from(raw[current_byte]);
+                        record.fraud_score.strictness[1] = Some(u);
+                        value = u.to_string().into();
+                        record.columns.push(Column {
+                            name: column.name.clone(),
+                            record_type: BinaryOption {
+                                data: flag::SMALL_INT_DATA,
+                            },
+                            value,
+                        });
+                    }
                     current_byte += 1;
                 }
                 "TwoFraudScore" => {
-                    let u = u32::from(raw[current_byte]);
-                    record.fraud_score.strictness[2] = Some(u);
-                    value = u.to_string();
-                    record.columns.push(Column {
-                        name: column.name.clone(),
-                        record_type: BinaryOption {
-                            data: flag::SMALL_INT_DATA,
-                        },
-                        value,
-                    });
+                    if fields.contains(Fields::FRAUD_SCORE_TWO) {
+                        let u = u32::from(raw[current_byte]);
+                        record.fraud_score.strictness[2] = Some(u);
+                        value = u.to_string().into();
+                        record.columns.push(Column {
+                            name: column.name.clone(),
+                            record_type: BinaryOption {
+                                data: flag::SMALL_INT_DATA,
+                            },
+                            value,
+                        });
+                    }
                     current_byte += 1;
                 }
                 "ThreeFraudScore" => {
-                    let u = u32::from(raw[current_byte]);
-                    record.fraud_score.strictness[3] = Some(u);
-                    value = u.to_string();
-                    record.columns.push(Column {
-                        name: column.name.clone(),
-                        record_type: BinaryOption {
-                            data: flag::SMALL_INT_DATA,
-                        },
-                        value,
-                    });
+                    if fields.contains(Fields::FRAUD_SCORE_THREE) {
+                        let u = u32::from(raw[current_byte]);
+                        record.fraud_score.strictness[3] = Some(u);
+                        value = u.to_string().into();
+                        record.columns.push(Column {
+                            name: column.name.clone(),
+                            record_type: BinaryOption {
+                                data: flag::SMALL_INT_DATA,
+                            },
+                            value,
+                        });
+                    }
                     current_byte += 1;
                 }
                 _ => {
+                    let field = match column.name.as_str() {
+                        "Country" => Fields::COUNTRY,
+                        "City" => Fields::CITY,
+                        "Region" => Fields::REGION,
+                        "ISP" => Fields::ISP,
+                        "Organization" => Fields::ORGANIZATION,
+                        "Timezone" => Fields::TIMEZONE,
+                        _ => {
+                            return Err(Error::InvalidString.into());
+                        }
+                    };
+                    let is_string = column.record_type.has(flag::STRING_DATA);
+                    if !fields.contains(field) {
+                        // skip the column without reading the string pool
+                        if is_string {
+                            current_byte += 4;
+                        }
+                        continue;
+                    }
                     let mut value = Default::default();
-                    if column.record_type.has(flag::STRING_DATA) {
+                    if is_string {
                         let offset = utility::four_byte_int(&raw[current_byte..current_byte + 4]);
-                        value = FileReader::get_ranged_string_value(&mut file.reader, offset)?;
+                        value = string_value(offset)?;
                         record.columns.push(Column {
                             name: column.name.clone(),
                             record_type: BinaryOption {
@@ -270,28 +390,13 @@ impl Record {
                         });
                         current_byte += 4;
                     };
-                    match column.name.as_str() {
-                        "Country" => {
-                            record.country = Some(value);
-                        }
-                        "City" => {
-                            record.city = Some(value);
-                        }
-                        "Region" => {
-                            record.region = Some(value);
-                        }
-                        "ISP" => {
-                            record.isp = Some(value);
-                        }
-                        "Organization" => {
-                            record.organization = Some(value);
-                        }
-                        "Timezone" => {
-                            record.timezone = Some(value);
-                        }
-                        _ => {
-                            return Err("failed to parse string data (EID 13)".into());
-                        }
+                    match field {
+                        Fields::COUNTRY => record.country = Some(value),
+                        Fields::CITY => record.city = Some(value),
+                        Fields::REGION => record.region = Some(value),
+                        Fields::ISP => record.isp = Some(value),
+                        Fields::ORGANIZATION => record.organization = Some(value),
+                        _ => record.timezone = Some(value),
                     }
                 }
             }
@@ -428,7 +533,8 @@ impl Record {
 }
 
 /// Returns one of: Residential, Mobile, Corporate, Data Center, Education, or Unknown
-fn connection_type(byte: u8) -> &'static str {
+#[doc(hidden)]
+pub fn connection_type(byte: u8) -> &'static str {
     match byte & flag::CONNECTION_MASK {
         flag::CONNECTION_TYPE_THREE => "Residential", // 001
         flag::CONNECTION_TYPE_TWO => "Mobile",        // 010
@@ -441,7 +547,8 @@ fn connection_type(byte: u8) -> &'static str {
 
 /// How frequently the IP address is engaging in abuse across the IPQS threat network.
 /// Values can be "high", "medium", "low", or "none".
-fn abuse_velocity(byte: u8) -> &'static str {
+#[doc(hidden)]
+pub fn abuse_velocity(byte: u8) -> &'static str {
     match byte & flag::ABUSE_VELOCITY_MASK {
         flag::ABUSE_VELOCITY_TWO => "low",    // 01
         flag::ABUSE_VELOCITY_ONE => "medium", // 10
@@ -450,8 +557,8 @@ fn abuse_velocity(byte: u8) -> &'static str {
     }
 }
 
-#[derive(Clone, Default, Debug)]
-#[cfg_attr(feature = "json", derive(serde::Serialize))]
+#[derive(Clone, Default, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 struct FraudScore {
     pub strictness: [Option<u32>; 4],
 }
@@ -0,0 +1,235 @@
+// Copyright 2023 IPQualityScore LLC
+use alloc::borrow::ToOwned;
+use alloc::string::String;
+use alloc::sync::Arc;
+
+use crate::record::{Record, Strictness};
+
+/// A record in the shape of a response from the IPQS
+/// [Proxy & VPN Detection API](https://www.ipqualityscore.com/documentation/proxy-detection-api/overview),
+/// so that systems already consuming the API can be served from the flat file database.
+///
+/// The API returns a single fraud score for the strictness it was called with, so converting
+/// a [Record] takes the [Strictness] to report. Fields missing from the file are left out of
+/// the JSON. The API fields without a flat file equivalent (`host`, `zip_code`, ...) are never
+/// written, and ignored when reading a response.
+/// ```
+/// use ipqs_db_core::{ApiResponse, RecordBuilder, Strictness};
+/// use std::error;
+/// let record = RecordBuilder::new()
+///     .country("US")
+///     .fraud_score(Strictness::One, 85)
+///     .build();
+/// let response = ApiResponse::from_record(&record, Strictness::One);
+/// println!("{}", serde_json::to_string(&response)?);
+/// # Ok::<(), Box <dyn error::Error>>(())
+/// ```
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ApiResponse {
+    #[serde(default = "success")]
+    pub success: bool,
+    #[serde(default)]
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fraud_score: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub country_code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub city: Option<String>,
+    #[serde(rename = "ISP", skip_serializing_if = "Option::is_none")]
+    pub isp: Option<String>,
+    #[serde(rename = "ASN", skip_serializing_if = "Option::is_none")]
+    pub asn: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub organization: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_crawler: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mobile: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vpn: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tor: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active_vpn: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active_tor: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recent_abuse: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bot_status: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connection_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub abuse_velocity: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latitude: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub longitude: Option<f32>,
+}
+
+fn success() -> bool {
+    true
+}
+
+impl ApiResponse {
+    /// Converts `record`, reporting its fraud score for `strictness`
+    pub fn from_record(record: &Record, strictness: Strictness) -> ApiResponse {
+        let string = |value: Option<&str>| value.map(str::to_owned);
+        ApiResponse {
+            success: true,
+            message: "Success".to_owned(),
+            fraud_score: record.fraud_score(strictness),
+            country_code: string(record.country()),
+            region: string(record.region()),
+            city: string(record.city()),
+            isp: string(record.isp()),
+            asn: record.asn(),
+            organization: string(record.organization()),
+            is_crawler: record.is_crawler(),
+            timezone: string(record.timezone()),
+            mobile: record.is_mobile(),
+            proxy: record.is_proxy(),
+            vpn: record.is_vpn(),
+            tor: record.is_tor(),
+            active_vpn: record.active_vpn(),
+            active_tor: record.active_tor(),
+            recent_abuse: record.recent_abuse(),
+            bot_status: record.is_bot(),
+            connection_type: Some(record.connection_type().to_owned()),
+            abuse_velocity: Some(record.abuse_velocity().to_owned()),
+            latitude: record.latitude(),
+            longitude: record.longitude(),
+        }
+    }
+
+    /// Converts the response into a [Record], with the fraud score set for the `strictness`
+    /// the API was called with. Fields the API does not return (such as `is_hosting_provider`)
+    /// are None.
+    pub fn into_record(self, strictness: Strictness) -> Record {
+        let string = |value: Option<String>| value.map(Arc::from);
+        let mut record = Record {
+            connection_type: self.connection_type.unwrap_or_else(|| "Unknown".to_owned()),
+            abuse_velocity: self.abuse_velocity.unwrap_or_else(|| "none".to_owned()),
+            country: string(self.country_code),
+            city: string(self.city),
+            region: string(self.region),
+            isp: string(self.isp),
+            organization: string(self.organization),
+            asn: self.asn,
+            timezone: string(self.timezone),
+            latitude: self.latitude,
+            longitude: self.longitude,
+            is_proxy: self.proxy,
+            is_vpn: self.vpn,
+            is_tor: self.tor,
+            is_crawler: self.is_crawler,
+            is_bot: self.bot_status,
+            recent_abuse: self.recent_abuse,
+            is_mobile: self.mobile,
+            active_vpn: self.active_vpn,
+            active_tor: self.active_tor,
+            ..Default::default()
+        };
+        let level = match strictness {
+            Strictness::Zero => 0,
+            Strictness::One => 1,
+            Strictness::Two => 2,
+            Strictness::Three => 3,
+        };
+        record.fraud_score.strictness[level] = self.fraud_score;
+        record
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RecordBuilder;
+    use std::error::Error;
+
+    #[test]
+    fn api_field_names() -> Result<(), Box<dyn Error>> {
+        let record = RecordBuilder::new()
+            .is_proxy(true)
+            .is_vpn(true)
+            .is_tor(false)
+            .is_bot(true)
+            .country("DE")
+            .isp("Deutsche Telekom")
+            .asn(3320)
+            .fraud_score(Strictness::Zero, 60)
+            .fraud_score(Strictness::One, 90)
+            .connection_type("Residential")
+            .build();
+        let json = serde_json::to_value(ApiResponse::from_record(&record, Strictness::One))?;
+        assert_eq!(json["success"], true);
+        assert_eq!(json["fraud_score"], 90);
+        assert_eq!(json["country_code"], "DE");
+        assert_eq!(json["ISP"], "Deutsche Telekom");
+        assert_eq!(json["ASN"], 3320);
+        assert_eq!(json["proxy"], true);
+        assert_eq!(json["vpn"], true);
+        assert_eq!(json["tor"], false);
+        assert_eq!(json["bot_status"], true);
+        assert_eq!(json["connection_type"], "Residential");
+        // missing from the record
+        assert!(json.get("city").is_none());
+        assert!(json.get("mobile").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn reads_api_response() -> Result<(), Box<dyn Error>> {
+        let response: ApiResponse = serde_json::from_str(
+            r#"{
+                "success": true,
+                "message": "Success",
+                "fraud_score": 75,
+                "country_code": "US",
+                "region": "California",
+                "city": "Mountain View",
+                "ISP": "Google",
+                "ASN": 15169,
+                "organization": "Google",
+                "is_crawler": false,
+                "timezone": "America/Los_Angeles",
+                "mobile": false,
+                "host": "dns.google",
+                "proxy": true,
+                "vpn": true,
+                "tor": false,
+                "active_vpn": false,
+                "active_tor": false,
+                "recent_abuse": false,
+                "bot_status": false,
+                "connection_type": "Data Center",
+                "abuse_velocity": "none",
+                "zip_code": "N/A",
+                "latitude": 37.39,
+                "longitude": -122.07,
+                "request_id": "4DcV8ZJ1s7"
+            }"#,
+        )?;
+        let record = response.clone().into_record(Strictness::Two);
+        assert_eq!(record.fraud_score(Strictness::Two), Some(75));
+        assert_eq!(record.fraud_score(Strictness::Zero), None);
+        assert_eq!(record.country(), Some("US"));
+        assert_eq!(record.isp(), Some("Google"));
+        assert_eq!(record.asn(), Some(15169));
+        assert_eq!(record.is_vpn(), Some(true));
+        assert_eq!(record.connection_type(), "Data Center");
+        assert_eq!(record.latitude(), Some(37.39));
+        assert_eq!(record.is_hosting_provider(), None);
+
+        // and back again
+        assert_eq!(ApiResponse::from_record(&record, Strictness::Two), response);
+        Ok(())
+    }
+}
@@ -0,0 +1,225 @@
+// Copyright 2023 IPQualityScore LLC
+use alloc::borrow::ToOwned;
+use alloc::sync::Arc;
+
+use crate::record::{Record, Strictness};
+
+/// Builds a [Record] field by field, e.g. for tests and mocks of code using the reader.
+/// Fields that are not set are None, like those missing from a file: a record built without
+/// any of the `is_*` flags mirrors a file without binary data.
+/// ```
+/// use ipqs_db_core::{RecordBuilder, Strictness};
+/// let record = RecordBuilder::new()
+///     .is_vpn(true)
+///     .fraud_score(Strictness::Zero, 90)
+///     .country("DE")
+///     .build();
+/// assert_eq!(record.is_vpn(), Some(true));
+/// assert_eq!(record.is_proxy(), None);
+/// assert_eq!(record.fraud_score(Strictness::Zero), Some(90));
+/// assert_eq!(record.country(), Some("DE"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct RecordBuilder {
+    record: Record,
+}
+
+impl RecordBuilder {
+    /// Starts an empty record. The connection type is "Unknown" and the abuse velocity "none",
+    /// as they are for records in a file that has no value for them.
+    pub fn new() -> RecordBuilder {
+        RecordBuilder {
+            record: Record {
+                connection_type: "Unknown".to_owned(),
+                abuse_velocity: "none".to_owned(),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Returns the record
+    pub fn build(self) -> Record {
+        self.record
+    }
+
+    pub fn is_proxy(mut self, value: bool) -> RecordBuilder {
+        self.record.is_proxy = Some(value);
+        self
+    }
+
+    pub fn is_vpn(mut self, value: bool) -> RecordBuilder {
+        self.record.is_vpn = Some(value);
+        self
+    }
+
+    pub fn is_tor(mut self, value: bool) -> RecordBuilder {
+        self.record.is_tor = Some(value);
+        self
+    }
+
+    pub fn is_crawler(mut self, value: bool) -> RecordBuilder {
+        self.record.is_crawler = Some(value);
+        self
+    }
+
+    pub fn is_bot(mut self, value: bool) -> RecordBuilder {
+        self.record.is_bot = Some(value);
+        self
+    }
+
+    pub fn recent_abuse(mut self, value: bool) -> RecordBuilder {
+        self.record.recent_abuse = Some(value);
+        self
+    }
+
+    pub fn is_blacklisted(mut self, value: bool) -> RecordBuilder {
+        self.record.is_blacklisted = Some(value);
+        self
+    }
+
+    pub fn is_private(mut self, value: bool) -> RecordBuilder {
+        self.record.is_private = Some(value);
+        self
+    }
+
+    pub fn is_mobile(mut self, value: bool) -> RecordBuilder {
+        self.record.is_mobile = Some(value);
+        self
+    }
+
+    pub fn has_open_ports(mut self, value: bool) -> RecordBuilder {
+        self.record.has_open_ports = Some(value);
+        self
+    }
+
+    pub fn is_hosting_provider(mut self, value: bool) -> RecordBuilder {
+        self.record.is_hosting_provider = Some(value);
+        self
+    }
+
+    pub fn active_vpn(mut self, value: bool) -> RecordBuilder {
+        self.record.active_vpn = Some(value);
+        self
+    }
+
+    pub fn active_tor(mut self, value: bool) -> RecordBuilder {
+        self.record.active_tor = Some(value);
+        self
+    }
+
+    pub fn public_access_point(mut self, value: bool) -> RecordBuilder {
+        self.record.public_access_point = Some(value);
+        self
+    }
+
+    /// One of: Residential, Mobile, Corporate, Data Center, Education, or Unknown
+    pub fn connection_type(mut self, value: &str) -> RecordBuilder {
+        self.record.connection_type = value.to_owned();
+        self
+    }
+
+    /// One of: high, medium, low, or none
+    pub fn abuse_velocity(mut self, value: &str) -> RecordBuilder {
+        self.record.abuse_velocity = value.to_owned();
+        self
+    }
+
+    pub fn country(mut self, value: impl Into<Arc<str>>) -> RecordBuilder {
+        self.record.country = Some(value.into());
+        self
+    }
+
+    pub fn city(mut self, value: impl Into<Arc<str>>) -> RecordBuilder {
+        self.record.city = Some(value.into());
+        self
+    }
+
+    pub fn region(mut self, value: impl Into<Arc<str>>) -> RecordBuilder {
+        self.record.region = Some(value.into());
+        self
+    }
+
+    pub fn isp(mut self, value: impl Into<Arc<str>>) -> RecordBuilder {
+        self.record.isp = Some(value.into());
+        self
+    }
+
+    pub fn organization(mut self, value: impl Into<Arc<str>>) -> RecordBuilder {
+        self.record.organization = Some(value.into());
+        self
+    }
+
+    pub fn asn(mut self, value: u64) -> RecordBuilder {
+        self.record.asn = Some(value);
+        self
+    }
+
+    pub fn timezone(mut self, value: impl Into<Arc<str>>) -> RecordBuilder {
+        self.record.timezone = Some(value.into());
+        self
+    }
+
+    pub fn latitude(mut self, value: f32) -> RecordBuilder {
+        self.record.latitude = Some(value);
+        self
+    }
+
+    pub fn longitude(mut self, value: f32) -> RecordBuilder {
+        self.record.longitude = Some(value);
+        self
+    }
+
+    pub fn fraud_score(mut self, strictness: Strictness, value: u32) -> RecordBuilder {
+        let level = match strictness {
+            Strictness::Zero => 0,
+            Strictness::One => 1,
+            Strictness::Two => 2,
+            Strictness::Three => 3,
+        };
+        self.record.fraud_score.strictness[level] = Some(value);
+        self
+    }
+}
+
+impl Default for RecordBuilder {
+    fn default() -> RecordBuilder {
+        RecordBuilder::new()
+    }
+}
+
+impl From<Record> for RecordBuilder {
+    /// Starts from an existing record, e.g. one returned by a lookup, to change some of its fields
+    fn from(record: Record) -> RecordBuilder {
+        RecordBuilder { record }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partially_populated() {
+        let record = RecordBuilder::new()
+            .asn(3320)
+            .latitude(52.5)
+            .fraud_score(Strictness::Two, 75)
+            .build();
+        assert_eq!(record.asn(), Some(3320));
+        assert_eq!(record.latitude(), Some(52.5));
+        assert_eq!(record.longitude(), None);
+        assert_eq!(record.fraud_score(Strictness::Two), Some(75));
+        assert_eq!(record.fraud_score(Strictness::Three), None);
+        assert_eq!(record.is_proxy(), None);
+        assert_eq!(record.connection_type(), "Unknown");
+        assert_eq!(record.abuse_velocity(), "none");
+
+        let record = RecordBuilder::from(record)
+            .connection_type("Mobile")
+            .is_mobile(true)
+            .build();
+        assert_eq!(record.asn(), Some(3320));
+        assert_eq!(record.connection_type(), "Mobile");
+        assert_eq!(record.is_mobile(), Some(true));
+    }
+}
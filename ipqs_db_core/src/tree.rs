@@ -0,0 +1,218 @@
+// Copyright 2023 IPQualityScore LLC
+//! Walks of the binary tree of a database. Nodes are read through a callback, so that readers
+//! can keep the tree wherever they like: in memory, in a file or behind an index.
+use alloc::vec;
+use core::net::IpAddr;
+
+use crate::{utility, Error};
+
+// an IPv6 address is 128 bits long, so no path through the tree can be deeper than that
+const MAX_DEPTH: usize = 128;
+
+/// The bits of an IP address, packed into an integer (most significant bit first)
+pub struct Address {
+    pub bits: u128,
+    pub width: usize,
+}
+
+impl Address {
+    pub fn new(ip: &IpAddr) -> Address {
+        match ip {
+            IpAddr::V4(ipv4) => Address {
+                bits: u128::from(u32::from(*ipv4)),
+                width: 32,
+            },
+            IpAddr::V6(ipv6) => Address {
+                bits: u128::from(*ipv6),
+                width: 128,
+            },
+        }
+    }
+
+    /// Returns true if the bit at `position` (counting from the most significant bit) is 1
+    pub fn bit(&self, position: usize) -> bool {
+        (self.bits >> (self.width - 1 - position)) & 1 == 1
+    }
+
+    /// Finds the last 1 bit at or before `position`, clears it and sets every bit after it.
+    /// Returns the position of the cleared bit, or None if every bit up to `position` is 0.
+    fn step_back(&mut self, position: usize) -> Option<usize> {
+        let shift = self.width - 1 - position;
+        let prefix = self.bits >> shift;
+        if prefix == 0 {
+            return None;
+        }
+        let distance = prefix.trailing_zeros() as usize;
+        let cleared = 1u128 << (shift + distance);
+        self.bits = (self.bits & !cleared) | (cleared - 1);
+
+        Some(position - distance)
+    }
+}
+
+/// Walks the tree from the root, following the bits of `ip`, and returns the file position of the
+/// record it leads to. `read_node` is called with the file position of every node visited and must
+/// fill the buffer with the node's two ("left" and "right") 4-byte integer "pointers". Errors of
+/// `read_node` are returned as they are.
+///
+/// If the address is not in the tree (and the file is not a blacklist file), the walk falls back
+/// to the nearest record before it: go back up the tree until we reach a 1, take the 0 path, and
+/// follow all right children until we reach a record or another 0.
+///
+/// The second value returned is the number of leading bits of `ip` that decided the result:
+/// every address sharing those bits leads to the same record.
+pub fn find_record<F, E>(
+    ip: &IpAddr,
+    tree_start: u64,
+    tree_end: u64,
+    is_blacklist: bool,
+    mut read_node: F,
+) -> Result<(u64, usize), E>
+where
+    F: FnMut(u64, &mut [u8; 8]) -> Result<(), E>,
+    E: From<Error>,
+{
+    let mut address = Address::new(ip);
+    let mut prefix_len = None; // bits of ip followed before the first step back
+    let mut position: usize = 0; // bit within binary representation of ip address
+    let mut previous = [0u64; MAX_DEPTH]; // node positions within tree, indexed by bit
+    let mut file_position = tree_start + 5; // start traversing tree just after tree header
+    let mut node = [0u8; 8];
+
+    // loop over tree, aborting after too many iterations
+    for _ in 0..257 {
+        if address.width <= position {
+            // somehow we went through the whole binary representation without finding a record
+            return Err(Error::NoRecordBefore.into());
+        }
+        previous[position] = file_position;
+        read_node(file_position, &mut node)?;
+        if address.bit(position) {
+            // bit is 1 - go right
+            file_position = utility::four_byte_int(&node[4..8]);
+        } else {
+            // bit is 0 - go left
+            file_position = utility::four_byte_int(&node[0..4]);
+        }
+
+        if !is_blacklist && file_position == 0 {
+            // specified ip is not in the file, back up to the last right turn and go left instead
+            prefix_len.get_or_insert(position + 1);
+            match address.step_back(position) {
+                Some(turn) => {
+                    position = turn;
+                    file_position = previous[position];
+                }
+                None => {
+                    // there is nothing to the left of this address
+                    return Err(Error::NoRecordBefore.into());
+                }
+            }
+            continue;
+        }
+
+        if file_position < tree_end {
+            // there is still more tree left
+            if file_position == 0 {
+                break;
+            }
+            position += 1;
+            continue;
+        }
+
+        return Ok((file_position, prefix_len.unwrap_or(position + 1)));
+    }
+    Err(Error::NotInFile.into())
+}
+
+/// Visits every node of the tree and calls `found` with the file position of each record
+/// a node points to. Records pointed to by more than one node are reported more than once.
+pub fn for_each_record<F, G, E>(
+    tree_start: u64,
+    tree_end: u64,
+    mut read_node: F,
+    mut found: G,
+) -> Result<(), E>
+where
+    F: FnMut(u64, &mut [u8; 8]) -> Result<(), E>,
+    G: FnMut(u64),
+    E: From<Error>,
+{
+    let first_node = tree_start.checked_add(5).ok_or(Error::TreeTooSmall)?;
+    // guards against loops in a corrupt tree
+    let mut remaining_nodes = tree_end
+        .checked_sub(first_node)
+        .ok_or(Error::TreeTooSmall)?
+        / 8;
+    let mut pending = vec![first_node];
+    let mut node = [0u8; 8];
+    while let Some(position) = pending.pop() {
+        if remaining_nodes == 0 {
+            return Err(Error::InvalidTree.into());
+        }
+        remaining_nodes -= 1;
+        read_node(position, &mut node)?;
+        for pointer in [
+            utility::four_byte_int(&node[0..4]),
+            utility::four_byte_int(&node[4..8]),
+        ] {
+            if pointer == 0 {
+                continue;
+            }
+            if pointer < first_node {
+                return Err(Error::InvalidTree.into());
+            }
+            if pointer < tree_end {
+                pending.push(pointer);
+            } else {
+                found(pointer);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::net::{Ipv4Addr, Ipv6Addr};
+
+    #[test]
+    fn bits() {
+        let address = Address::new(&IpAddr::V4(Ipv4Addr::new(128, 0, 0, 1)));
+        assert!(address.bit(0));
+        assert!(!address.bit(1));
+        assert!(address.bit(31));
+
+        let address = Address::new(&IpAddr::V6(Ipv6Addr::new(0x8000, 0, 0, 0, 0, 0, 0, 1)));
+        assert!(address.bit(0));
+        assert!(!address.bit(64));
+        assert!(address.bit(127));
+    }
+
+    #[test]
+    fn tree_too_small() {
+        let mut records = 0;
+        let result = for_each_record::<_, _, Error>(100, 102, |_, _| Ok(()), |_| records += 1);
+        assert_eq!(result, Err(Error::TreeTooSmall));
+        assert_eq!(records, 0);
+    }
+
+    #[test]
+    fn step_back() {
+        // 0b1010_0000 ... -> last 1 at or before bit 4 is bit 2
+        let mut address = Address::new(&IpAddr::V4(Ipv4Addr::new(0b1010_0000, 0, 0, 0)));
+        assert_eq!(address.step_back(4), Some(2));
+        assert_eq!(
+            address.bits,
+            u128::from(u32::from(Ipv4Addr::new(0b1001_1111, 255, 255, 255)))
+        );
+
+        let mut address = Address::new(&IpAddr::V4(Ipv4Addr::new(0, 0, 0, 255)));
+        assert_eq!(address.step_back(8), None);
+
+        let mut address = Address::new(&IpAddr::V6(Ipv6Addr::new(0x8000, 0, 0, 0, 0, 0, 0, 0)));
+        assert_eq!(address.step_back(127), Some(0));
+        assert_eq!(address.bits, u128::MAX >> 1);
+    }
+}
@@ -7,15 +7,15 @@
 // Use of this source code is governed by a BSD-style
 // license that can be found in the LICENSE file.
 
-use std::error::Error;
+use crate::Error;
 
 const MAX_VAR_INT_LEN_64: usize = 10;
 
 /// takes a slice of bytes encoded according to [Base 128 Varints](https://protobuf.dev/programming-guides/encoding/#varints)
 /// and returns either the decoded u64 integer or an error
-pub fn uvarint64(bytes: &[u8]) -> Result<u64, Box<dyn Error>> {
+pub fn uvarint64(bytes: &[u8]) -> Result<u64, Error> {
     if bytes.len() > MAX_VAR_INT_LEN_64 {
-        return Err("catch byte reads past MaxVarIntLen64".into()); // overflow
+        return Err(Error::VarintOverflow); // catch byte reads past MaxVarIntLen64
     }
     let mut x: u64 = 0;
     let mut s: u32 = 0;
@@ -23,7 +23,7 @@ pub fn uvarint64(bytes: &[u8]) -> Result<u64, Box<dyn Error>> {
         if *byte < 0x80 {
             // 128, or 1000 0000
             if (i == MAX_VAR_INT_LEN_64 - 1) && (*byte > 1) {
-                return Err("overflow".into());
+                return Err(Error::VarintOverflow);
             }
             x |= (*byte as u64) << s;
         }
@@ -38,7 +38,7 @@ pub fn uvarint64(bytes: &[u8]) -> Result<u64, Box<dyn Error>> {
 mod tests {
     use super::*;
     #[test]
-    fn it_works() -> Result<(), Box<dyn Error>> {
+    fn it_works() -> Result<(), Error> {
         let bytes: [u8; 3] = [0x93, 0x02, 0x00];
         let answer = uvarint64(&bytes)?;
         assert_eq!(answer, 275);
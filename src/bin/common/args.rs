@@ -0,0 +1,114 @@
+// Copyright 2023 IPQualityScore LLC
+use std::process;
+use std::time::Duration;
+
+/// Command line options given as `--name value` pairs
+#[derive(Debug, Default)]
+pub struct Args {
+    values: Vec<(String, String)>,
+}
+
+impl Args {
+    /// Parses the command line, printing `usage` and exiting on `--help` or a malformed option
+    pub fn parse(usage: &str) -> Args {
+        match Args::from_iter(std::env::args().skip(1)) {
+            Ok(args) => args,
+            Err(message) => {
+                if !message.is_empty() {
+                    eprintln!("{}\n", message);
+                }
+                eprintln!("{}", usage);
+                process::exit(if message.is_empty() { 0 } else { 2 });
+            }
+        }
+    }
+
+    /// Returns an empty message for `--help`
+    fn from_iter(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+        let mut values = Vec::new();
+        while let Some(arg) = args.next() {
+            if arg == "--help" || arg == "-h" {
+                return Err(String::new());
+            }
+            let name = match arg.strip_prefix("--") {
+                Some(name) => name.to_owned(),
+                None => return Err(format!("unexpected argument {}", arg)),
+            };
+            match args.next() {
+                Some(value) => values.push((name, value)),
+                None => return Err(format!("missing value for --{}", name)),
+            }
+        }
+        Ok(Args { values })
+    }
+
+    /// Returns the last value given for `--name`
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values
+            .iter()
+            .rev()
+            .find(|(option, _)| option == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn get_or<'a>(&'a self, name: &str, default: &'a str) -> &'a str {
+        self.get(name).unwrap_or(default)
+    }
+
+    /// Parses the value of `--name`, exiting with a message if it is malformed
+    pub fn parse_or<T: std::str::FromStr>(&self, name: &str, default: T) -> T {
+        match self.get(name) {
+            None => default,
+            Some(value) => value.parse().unwrap_or_else(|_| invalid(name, value)),
+        }
+    }
+
+    /// Parses the value of `--name` as a positive number of seconds, exiting with a message if
+    /// it is malformed
+    pub fn seconds_or(&self, name: &str, default: Duration) -> Duration {
+        match self.get(name) {
+            None => default,
+            Some(value) => seconds(value).unwrap_or_else(|| invalid(name, value)),
+        }
+    }
+}
+
+fn seconds(value: &str) -> Option<Duration> {
+    let seconds = value.parse().ok()?;
+    Duration::try_from_secs_f64(seconds)
+        .ok()
+        .filter(|duration| !duration.is_zero())
+}
+
+fn invalid(name: &str, value: &str) -> ! {
+    eprintln!("invalid value for --{}: {}", name, value);
+    process::exit(2);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Result<Args, String> {
+        Args::from_iter(line.split_whitespace().map(str::to_owned))
+    }
+
+    #[test]
+    fn options() {
+        let parsed = args("--ipv4 a.ipqs --listen 0.0.0.0:80 --ipv4 b.ipqs").unwrap();
+        assert_eq!(parsed.get("ipv4"), Some("b.ipqs"));
+        assert_eq!(parsed.get_or("ipv6", "none"), "none");
+        assert_eq!(parsed.parse_or("threads", 4), 4);
+        assert!(args("--ipv4").is_err());
+        assert!(args("ipv4 a.ipqs").is_err());
+        assert_eq!(args("--help").unwrap_err(), "");
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(seconds("1.5"), Some(Duration::from_millis(1500)));
+        for value in ["0", "-1", "NaN", "inf", "1e300", "soon"] {
+            assert_eq!(seconds(value), None, "{}", value);
+        }
+    }
+}
@@ -0,0 +1,105 @@
+// Copyright 2023 IPQualityScore LLC
+use std::error::Error;
+use std::net::IpAddr;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use ipqs_db_reader::{FileReader, IpReputationSource, ReaderPool, Record};
+
+use super::args::Args;
+
+pub const DATABASE_USAGE: &str = "  --ipv4 PATH         IPv4 database file
+  --ipv6 PATH         IPv6 database file (at least one of --ipv4 and --ipv6 is required)
+  --readers N         readers per database, the most lookups made at the same time [16]
+  --reload-interval S seconds between checks for a replaced database file [1]";
+
+/// The IPv4 and IPv6 databases given on the command line. Each is read into memory once and
+/// indexed, and shared by the pooled readers. A database file that is replaced is loaded again
+/// by a thread of its own, so requests don't wait for it, while lookups already running finish
+/// with the previous one.
+#[derive(Clone, Debug)]
+pub struct Databases {
+    pub ipv4: Option<ReaderPool>,
+    pub ipv6: Option<ReaderPool>,
+}
+
+impl Databases {
+    pub fn open(args: &Args) -> Result<Databases, Box<dyn Error>> {
+        let readers = args.parse_or("readers", 16);
+        let reload_interval = args.seconds_or("reload-interval", Duration::from_secs(1));
+        let open = |name: &str, ipv6: bool| -> Result<Option<ReaderPool>, Box<dyn Error>> {
+            let path = match args.get(name) {
+                Some(path) => Path::new(path),
+                None => return Ok(None),
+            };
+            let mut reader = FileReader::open_in_memory(path)?;
+            if reader.is_ipv6() != ipv6 {
+                return Err(format!("{} is not an {} database", path.display(), name).into());
+            }
+            reader.build_index()?;
+            reader.preload_strings()?;
+            let pool = ReaderPool::new(reader, readers)?;
+            pool.set_reload_interval(Duration::MAX);
+            let watched = pool.clone();
+            let path = path.to_path_buf();
+            thread::spawn(move || watch(&watched, &path, reload_interval));
+            Ok(Some(pool))
+        };
+        let databases = Databases {
+            ipv4: open("ipv4", false)?,
+            ipv6: open("ipv6", true)?,
+        };
+        if databases.ipv4.is_none() && databases.ipv6.is_none() {
+            return Err("at least one of --ipv4 and --ipv6 is required".into());
+        }
+        Ok(databases)
+    }
+
+    /// Returns the pool for the IP version of `ip`
+    pub fn pool(&self, ip: &IpAddr) -> Result<&ReaderPool, Box<dyn Error + Send + Sync>> {
+        let pool = match ip {
+            IpAddr::V4(_) => self.ipv4.as_ref(),
+            IpAddr::V6(_) => self.ipv6.as_ref(),
+        };
+        match pool {
+            Some(pool) => Ok(pool),
+            None if ip.is_ipv4() => Err("no IPv4 database is loaded".into()),
+            None => Err("no IPv6 database is loaded".into()),
+        }
+    }
+}
+
+impl IpReputationSource for Databases {
+    fn lookup(&self, ip: &IpAddr) -> Result<Option<Record>, Box<dyn Error + Send + Sync>> {
+        self.pool(ip)?.lookup(ip)
+    }
+}
+
+/// Checks the file of `pool` for changes every `interval`, logging when it is loaded again or
+/// cannot be. The same error is only logged once.
+fn watch(pool: &ReaderPool, path: &Path, interval: Duration) {
+    let mut last_error = None;
+    loop {
+        thread::sleep(interval);
+        match pool.reload() {
+            Ok(reloaded) => {
+                if reloaded {
+                    eprintln!("loaded {} again", path.display());
+                }
+                last_error = None;
+            }
+            Err(error) => {
+                let error = error.to_string();
+                if last_error.as_ref() != Some(&error) {
+                    eprintln!(
+                        "failed to load {} again, still using the previous file: {}",
+                        path.display(),
+                        error
+                    );
+                    last_error = Some(error);
+                }
+            }
+        }
+    }
+}
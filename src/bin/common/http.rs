@@ -0,0 +1,312 @@
+// Copyright 2023 IPQualityScore LLC
+//! Just enough HTTP/1.1 for the lookup servers: requests with a Content-Length body,
+//! keep-alive connections and one thread per connection.
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+const MAX_LINE: u64 = 8 * 1024;
+const MAX_HEADERS: usize = 100;
+const MAX_BODY: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Default)]
+pub struct Request {
+    pub method: String,
+    /// without the query string
+    pub path: String,
+    pub query: String,
+    pub version: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Returns the value of the header `name`, ignoring case
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the decoded segments of the path, e.g. `["lookup", "8.8.8.8"]` for `/lookup/8.8.8.8`
+    pub fn segments(&self) -> Vec<String> {
+        self.path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(percent_decode)
+            .collect()
+    }
+
+    /// Returns the decoded value of the query parameter `name`
+    pub fn query_param(&self, name: &str) -> Option<String> {
+        self.query.split('&').find_map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key) == name).then(|| percent_decode(value))
+        })
+    }
+
+    pub fn keep_alive(&self) -> bool {
+        match self.header("Connection") {
+            Some(value) if value.eq_ignore_ascii_case("close") => false,
+            Some(value) if value.eq_ignore_ascii_case("keep-alive") => true,
+            _ => self.version == "HTTP/1.1",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn json<T: serde::Serialize + ?Sized>(status: u16, value: &T) -> Response {
+        match serde_json::to_vec(value) {
+            Ok(body) => Response {
+                status,
+                content_type: "application/json",
+                body,
+            },
+            Err(error) => Response::text(500, &error.to_string()),
+        }
+    }
+
+    pub fn text(status: u16, body: &str) -> Response {
+        Response {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: body.as_bytes().to_vec(),
+        }
+    }
+}
+
+/// Reads the next request from a connection, or None once the client has closed it
+pub fn read_request(reader: &mut impl BufRead) -> io::Result<Option<Request>> {
+    let line = match read_line(reader)? {
+        Some(line) => line,
+        None => return Ok(None),
+    };
+    let mut parts = line.split(' ');
+    let (method, target, version) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(target), Some(version)) if parts.next().is_none() => {
+            (method, target, version)
+        }
+        _ => return Err(invalid("malformed request line")),
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let mut request = Request {
+        method: method.to_owned(),
+        path: path.to_owned(),
+        query: query.to_owned(),
+        version: version.to_owned(),
+        ..Default::default()
+    };
+
+    loop {
+        let line = read_line(reader)?.ok_or_else(|| invalid("connection closed in headers"))?;
+        if line.is_empty() {
+            break;
+        }
+        if request.headers.len() == MAX_HEADERS {
+            return Err(invalid("too many headers"));
+        }
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| invalid("malformed header"))?;
+        request
+            .headers
+            .push((name.trim().to_owned(), value.trim().to_owned()));
+    }
+
+    if request.header("Transfer-Encoding").is_some() {
+        return Err(invalid("chunked requests are not supported"));
+    }
+    if let Some(length) = request.header("Content-Length") {
+        let length: usize = length
+            .parse()
+            .map_err(|_| invalid("malformed Content-Length"))?;
+        if length > MAX_BODY {
+            return Err(invalid("request body is too large"));
+        }
+        request.body = vec![0; length];
+        reader.read_exact(&mut request.body)?;
+    }
+    Ok(Some(request))
+}
+
+pub fn write_response(
+    writer: &mut impl Write,
+    response: &Response,
+    keep_alive: bool,
+) -> io::Result<()> {
+    write!(
+        writer,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: {}\r\n\r\n",
+        response.status,
+        reason(response.status),
+        response.content_type,
+        response.body.len(),
+        if keep_alive { "keep-alive" } else { "close" },
+    )?;
+    writer.write_all(&response.body)?;
+    writer.flush()
+}
+
+/// Accepts connections until the listener fails, answering each request of a connection
+/// with `handler` on a thread of its own
+pub fn serve<H>(listener: TcpListener, handler: H) -> io::Result<()>
+where
+    H: FnMut(&Request) -> Response + Clone + Send + 'static,
+{
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(error) => {
+                eprintln!("failed to accept a connection: {}", error);
+                continue;
+            }
+        };
+        let handler = handler.clone();
+        thread::spawn(move || {
+            if let Err(error) = handle_connection(stream, handler) {
+                if error.kind() != io::ErrorKind::UnexpectedEof {
+                    eprintln!("connection failed: {}", error);
+                }
+            }
+        });
+    }
+    Ok(())
+}
+
+fn handle_connection<H>(stream: TcpStream, mut handler: H) -> io::Result<()>
+where
+    H: FnMut(&Request) -> Response,
+{
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    loop {
+        let request = match read_request(&mut reader) {
+            Ok(Some(request)) => request,
+            Ok(None) => return Ok(()),
+            Err(error) if error.kind() == io::ErrorKind::InvalidData => {
+                write_response(&mut writer, &Response::text(400, &error.to_string()), false)?;
+                return Ok(());
+            }
+            Err(error) => return Err(error),
+        };
+        let keep_alive = request.keep_alive();
+        write_response(&mut writer, &handler(&request), keep_alive)?;
+        if !keep_alive {
+            return Ok(());
+        }
+    }
+}
+
+/// Reads a line without its line ending, or None at the end of the stream
+fn read_line(reader: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut line = Vec::new();
+    reader
+        .by_ref()
+        .take(MAX_LINE)
+        .read_until(b'\n', &mut line)?;
+    if line.is_empty() {
+        return Ok(None);
+    }
+    if line.pop() != Some(b'\n') {
+        return Err(invalid("line is too long"));
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|_| invalid("request is not valid UTF-8"))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Decodes `%XX` escapes, and `+` as a space
+pub fn percent_decode(value: &str) -> String {
+    let hex = |byte: u8| (byte as char).to_digit(16).map(|digit| digit as u8);
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => match (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                (Some(high), Some(low)) => {
+                    decoded.push(high << 4 | low);
+                    i += 2;
+                }
+                _ => decoded.push(b'%'),
+            },
+            b'+' => decoded.push(b' '),
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_requests() -> io::Result<()> {
+        let mut input: &[u8] =
+            b"GET /api/json/ip/key/2001%3Adb8%3A%3A1?strictness=1&x HTTP/1.1\r\n\
+            Host: localhost\r\n\r\n\
+            POST /lookup HTTP/1.0\r\ncontent-length: 5\r\n\r\nhello";
+        let request = read_request(&mut input)?.unwrap();
+        assert_eq!(request.method, "GET");
+        assert_eq!(
+            request.segments(),
+            ["api", "json", "ip", "key", "2001:db8::1"]
+        );
+        assert_eq!(request.query_param("strictness").as_deref(), Some("1"));
+        assert_eq!(request.query_param("x").as_deref(), Some(""));
+        assert_eq!(request.query_param("y"), None);
+        assert_eq!(request.header("host"), Some("localhost"));
+        assert!(request.keep_alive());
+
+        let request = read_request(&mut input)?.unwrap();
+        assert_eq!(request.body, b"hello");
+        assert!(!request.keep_alive());
+        assert!(read_request(&mut input)?.is_none());
+
+        let mut input: &[u8] = b"GET /\r\n\r\n";
+        assert!(read_request(&mut input).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn writes_responses() -> io::Result<()> {
+        let mut output = Vec::new();
+        write_response(&mut output, &Response::text(404, "not found"), true)?;
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "HTTP/1.1 404 Not Found\r\nContent-Type: text/plain; charset=utf-8\r\n\
+             Content-Length: 9\r\nConnection: keep-alive\r\n\r\nnot found"
+        );
+        assert_eq!(percent_decode("a%20b+c%zz%4"), "a b c%zz%4");
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+// Copyright 2023 IPQualityScore LLC
+//! Reading requests line by line from a connection and flushing the answers
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+/// The longest line a client may send, so that a client that never ends its line cannot make
+/// the server buffer it without bound
+pub const MAX_LINE: u64 = 64 * 1024;
+
+/// Reads the next line without its `\n` or `\r\n`, or None once the client has closed the
+/// connection. The last line may end without a `\n`. Lines longer than [MAX_LINE] are an
+/// `InvalidData` error.
+pub fn read_line(input: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    input.by_ref().take(MAX_LINE).read_until(b'\n', &mut line)?;
+    if line.is_empty() {
+        return Ok(None);
+    }
+    if line.last() == Some(&b'\n') {
+        line.pop();
+    } else if line.len() as u64 == MAX_LINE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "line is too long",
+        ));
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    Ok(Some(line))
+}
+
+/// Flushes the answers written so far unless more requests are already buffered, so that a
+/// client sending many requests at once gets the answers in few writes, while a client waiting
+/// for its answer gets it at once. Only the buffer is looked at: reading from the connection
+/// would wait for requests the client may never send.
+pub fn flush_unless_pending<R: Read>(
+    input: &BufReader<R>,
+    output: &mut impl Write,
+) -> io::Result<()> {
+    if input.buffer().is_empty() {
+        output.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines() -> io::Result<()> {
+        let mut input: &[u8] = b"first\r\nsecond\nlast";
+        assert_eq!(read_line(&mut input)?, Some(b"first".to_vec()));
+        assert_eq!(read_line(&mut input)?, Some(b"second".to_vec()));
+        assert_eq!(read_line(&mut input)?, Some(b"last".to_vec()));
+        assert_eq!(read_line(&mut input)?, None);
+
+        let long = vec![b'1'; MAX_LINE as usize + 1];
+        let error = read_line(&mut long.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        Ok(())
+    }
+}
@@ -0,0 +1,8 @@
+// Copyright 2023 IPQualityScore LLC
+//! Code shared by the server binaries. Each binary only uses part of it.
+#![allow(dead_code)]
+
+pub mod args;
+pub mod databases;
+pub mod http;
+pub mod lines;
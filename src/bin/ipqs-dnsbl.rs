@@ -0,0 +1,557 @@
+// Copyright 2023 IPQualityScore LLC
+//! A DNS blocklist (DNSBL) server for mail servers and appliances that can only query
+//! reputation through DNS. An address is looked up by querying its reversed octets (IPv4) or
+//! reversed nibbles (IPv6) under the zone of the server:
+//!
+//! ```text
+//! ipqs-dnsbl --ipv4 IPv4.ipqs --ipv6 IPv6.ipqs --zone dnsbl.example.com --listen 127.0.0.1:5353
+//! dig -p 5353 @127.0.0.1 4.3.2.1.dnsbl.example.com A
+//! dig -p 5353 @127.0.0.1 4.3.2.1.dnsbl.example.com TXT
+//! ```
+//!
+//! A listed address answers `A` queries with `127.0.0.x`, where x adds up the reasons for the
+//! listing, and `TXT` queries with a summary of its record. Addresses that are not listed, or
+//! that have no record, answer NXDOMAIN. As RFC 5782 asks of DNS blocklists, 127.0.0.2 is
+//! always listed and 127.0.0.1 never is, so clients can check their configuration.
+//!
+//! | Code | Reason |
+//! |------|--------|
+//! | 2    | proxy |
+//! | 4    | VPN |
+//! | 8    | Tor |
+//! | 16   | blacklisted |
+//! | 32   | fraud score of at least `--suspicious` |
+//! | 64   | fraud score of at least `--high-risk` |
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, TcpListener, TcpStream, UdpSocket};
+use std::{process, thread};
+
+use ipqs_db_reader::{IpReputationSource, Record, Strictness};
+
+use common::args::Args;
+use common::databases::{Databases, DATABASE_USAGE};
+
+mod common;
+
+const TYPE_A: u16 = 1;
+const TYPE_TXT: u16 = 16;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+const CLASS_ANY: u16 = 255;
+
+const NO_ERROR: u8 = 0;
+const FORMAT_ERROR: u8 = 1;
+const SERVER_FAILURE: u8 = 2;
+const NAME_ERROR: u8 = 3;
+const NOT_IMPLEMENTED: u8 = 4;
+const REFUSED: u8 = 5;
+
+const PROXY: u8 = 2;
+const VPN: u8 = 4;
+const TOR: u8 = 8;
+const BLACKLISTED: u8 = 16;
+const SUSPICIOUS: u8 = 32;
+const HIGH_RISK: u8 = 64;
+
+fn usage() -> String {
+    format!(
+        "Usage: ipqs-dnsbl [OPTIONS]
+
+Answers DNS blocklist queries over UDP and TCP.
+
+Options:
+{}
+  --zone NAME         zone the addresses are queried under [dnsbl.localhost]
+  --listen ADDR       address to listen on, for both UDP and TCP [127.0.0.1:5353]
+  --ttl S             time to live of the answers, in seconds [300]
+  --strictness N      strictness of the fraud scores, from 0 to 3 [0]
+  --suspicious N      lists addresses with at least this fraud score [75]
+  --high-risk N       fraud score of high risk addresses [90]",
+        DATABASE_USAGE
+    )
+}
+
+fn main() {
+    let args = Args::parse(&usage());
+    let databases = Databases::open(&args).unwrap_or_else(|error| {
+        eprintln!("failed to open the databases: {}", error);
+        process::exit(1);
+    });
+    let zone = Zone {
+        labels: split_name(args.get_or("zone", "dnsbl.localhost")),
+        ttl: args.parse_or("ttl", 300),
+        strictness: match args.parse_or("strictness", 0u8) {
+            0 => Strictness::Zero,
+            1 => Strictness::One,
+            2 => Strictness::Two,
+            3 => Strictness::Three,
+            _ => {
+                eprintln!("invalid value for --strictness, expected a value from 0 to 3");
+                process::exit(2);
+            }
+        },
+        suspicious: args.parse_or("suspicious", 75),
+        high_risk: args.parse_or("high-risk", 90),
+    };
+    let listen = args.get_or("listen", "127.0.0.1:5353");
+    let (udp, tcp) = match (UdpSocket::bind(listen), TcpListener::bind(listen)) {
+        (Ok(udp), Ok(tcp)) => (udp, tcp),
+        (Err(error), _) | (_, Err(error)) => {
+            eprintln!("failed to listen on {}: {}", listen, error);
+            process::exit(1);
+        }
+    };
+    eprintln!("listening on {} (UDP and TCP)", listen);
+
+    let threads = thread::available_parallelism().map_or(4, usize::from);
+    for _ in 0..threads {
+        let socket = udp.try_clone().unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            process::exit(1);
+        });
+        let (zone, source) = (zone.clone(), databases.clone());
+        thread::spawn(move || serve_udp(socket, &zone, &source));
+    }
+
+    for stream in tcp.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(error) => {
+                eprintln!("failed to accept a connection: {}", error);
+                continue;
+            }
+        };
+        let (zone, source) = (zone.clone(), databases.clone());
+        thread::spawn(move || {
+            if let Err(error) = serve_tcp(stream, &zone, &source) {
+                if error.kind() != io::ErrorKind::UnexpectedEof {
+                    eprintln!("connection failed: {}", error);
+                }
+            }
+        });
+    }
+}
+
+/// The zone served and how records are listed
+#[derive(Clone, Debug)]
+struct Zone {
+    /// lowercase labels of the zone name
+    labels: Vec<String>,
+    ttl: u32,
+    strictness: Strictness,
+    suspicious: u32,
+    high_risk: u32,
+}
+
+fn serve_udp(socket: UdpSocket, zone: &Zone, source: &impl IpReputationSource) {
+    let mut query = [0; 512];
+    loop {
+        let (length, peer) = match socket.recv_from(&mut query) {
+            Ok(received) => received,
+            Err(error) => {
+                eprintln!("failed to receive a query: {}", error);
+                continue;
+            }
+        };
+        if let Some(response) = answer(&query[..length], zone, source) {
+            if let Err(error) = socket.send_to(&response, peer) {
+                eprintln!("failed to answer {}: {}", peer, error);
+            }
+        }
+    }
+}
+
+/// Answers the queries of one connection, each prefixed by its length as over UDP
+fn serve_tcp(stream: TcpStream, zone: &Zone, source: &impl IpReputationSource) -> io::Result<()> {
+    let mut input = BufReader::new(stream.try_clone()?);
+    let mut output = BufWriter::new(stream);
+    loop {
+        let mut length = [0; 2];
+        match input.read_exact(&mut length) {
+            Ok(()) => {}
+            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(error) => return Err(error),
+        }
+        let mut query = vec![0; usize::from(u16::from_be_bytes(length))];
+        input.read_exact(&mut query)?;
+        let response = match answer(&query, zone, source) {
+            Some(response) => response,
+            None => return Ok(()),
+        };
+        output.write_all(&(response.len() as u16).to_be_bytes())?;
+        output.write_all(&response)?;
+        output.flush()?;
+    }
+}
+
+/// A question of a query: the name as it was sent, its lowercase labels, its type and class
+struct Question<'a> {
+    wire: &'a [u8],
+    labels: Vec<String>,
+    qtype: u16,
+    qclass: u16,
+}
+
+/// Returns the response to a query, or None for messages that are not worth answering
+fn answer(query: &[u8], zone: &Zone, source: &impl IpReputationSource) -> Option<Vec<u8>> {
+    // responses are ignored so that two servers cannot keep answering each other
+    if query.len() < 12 || query[2] & 0x80 != 0 {
+        return None;
+    }
+    let opcode = (query[2] >> 3) & 0x0f;
+    let question_count = u16::from_be_bytes([query[4], query[5]]);
+    let question = match read_question(&query[12..]) {
+        Some(question) if opcode == 0 && question_count == 1 => question,
+        _ => {
+            let rcode = if opcode == 0 {
+                FORMAT_ERROR
+            } else {
+                NOT_IMPLEMENTED
+            };
+            return Some(response(query, None, rcode, &[], 0));
+        }
+    };
+
+    let (rcode, answers) = resolve(&question, zone, source);
+    Some(response(query, Some(&question), rcode, &answers, zone.ttl))
+}
+
+fn read_question(message: &[u8]) -> Option<Question<'_>> {
+    let mut labels = Vec::new();
+    let mut position = 0;
+    loop {
+        let length = usize::from(*message.get(position)?);
+        position += 1;
+        if length == 0 {
+            break;
+        }
+        // compression pointers and extended labels have no place in a question
+        if length > 63 {
+            return None;
+        }
+        let label = message.get(position..position + length)?;
+        labels.push(String::from_utf8_lossy(label).to_ascii_lowercase());
+        position += length;
+    }
+    let fixed = message.get(position..position + 4)?;
+    Some(Question {
+        wire: &message[..position + 4],
+        labels,
+        qtype: u16::from_be_bytes([fixed[0], fixed[1]]),
+        qclass: u16::from_be_bytes([fixed[2], fixed[3]]),
+    })
+}
+
+/// Returns the response code and the answers (type and data) to a question
+fn resolve(
+    question: &Question,
+    zone: &Zone,
+    source: &impl IpReputationSource,
+) -> (u8, Vec<(u16, Vec<u8>)>) {
+    let prefix = match question.labels.strip_suffix(zone.labels.as_slice()) {
+        Some(prefix) if question.qclass == CLASS_IN || question.qclass == CLASS_ANY => prefix,
+        _ => return (REFUSED, Vec::new()),
+    };
+    // the zone itself exists, without any data we serve
+    if prefix.is_empty() {
+        return (NO_ERROR, Vec::new());
+    }
+    let ip = match parse_reversed(prefix) {
+        Some(ip) => ip,
+        None => return (NAME_ERROR, Vec::new()),
+    };
+
+    let (code, summary) = match ip {
+        IpAddr::V4(ip) if ip == Ipv4Addr::new(127, 0, 0, 2) => {
+            (PROXY, "test address, always listed".to_owned())
+        }
+        IpAddr::V4(ip) if ip == Ipv4Addr::new(127, 0, 0, 1) => return (NAME_ERROR, Vec::new()),
+        _ => match source.lookup(&ip) {
+            Ok(Some(record)) => (listing(&record, zone), summary(&record, zone)),
+            Ok(None) => return (NAME_ERROR, Vec::new()),
+            Err(error) => {
+                eprintln!("failed to look up {}: {}", ip, error);
+                return (SERVER_FAILURE, Vec::new());
+            }
+        },
+    };
+    if code == 0 {
+        return (NAME_ERROR, Vec::new());
+    }
+
+    let mut answers = Vec::new();
+    if question.qtype == TYPE_A || question.qtype == TYPE_ANY {
+        answers.push((TYPE_A, vec![127, 0, 0, code]));
+    }
+    if question.qtype == TYPE_TXT || question.qtype == TYPE_ANY {
+        // a character string holds at most 255 bytes
+        let mut text = summary.into_bytes();
+        text.truncate(255);
+        text.insert(0, text.len() as u8);
+        answers.push((TYPE_TXT, text));
+    }
+    (NO_ERROR, answers)
+}
+
+/// Parses `d.c.b.a` as `a.b.c.d`, or the 32 nibbles of an IPv6 address in reverse order
+fn parse_reversed(labels: &[String]) -> Option<IpAddr> {
+    match labels.len() {
+        4 => {
+            let mut octets = [0; 4];
+            for (octet, label) in octets.iter_mut().zip(labels.iter().rev()) {
+                // leading zeros are ambiguous, some parsers read them as octal
+                if label.len() > 1 && label.starts_with('0') {
+                    return None;
+                }
+                *octet = label.parse().ok()?;
+            }
+            Some(IpAddr::V4(Ipv4Addr::from(octets)))
+        }
+        32 => {
+            let mut address = 0u128;
+            for label in labels.iter().rev() {
+                let nibble = match label.as_bytes() {
+                    [digit] => (*digit as char).to_digit(16)?,
+                    _ => return None,
+                };
+                address = address << 4 | u128::from(nibble);
+            }
+            Some(IpAddr::V6(Ipv6Addr::from(address)))
+        }
+        _ => None,
+    }
+}
+
+/// Adds up the codes of the reasons to list a record, 0 if it is not listed
+fn listing(record: &Record, zone: &Zone) -> u8 {
+    let mut code = 0;
+    for (flag, reason) in [
+        (record.is_proxy(), PROXY),
+        (record.is_vpn(), VPN),
+        (record.is_tor(), TOR),
+        (record.is_blacklisted(), BLACKLISTED),
+    ] {
+        if flag == Some(true) {
+            code |= reason;
+        }
+    }
+    if let Some(score) = record.fraud_score(zone.strictness) {
+        if score >= zone.suspicious {
+            code |= SUSPICIOUS;
+        }
+        if score >= zone.high_risk {
+            code |= HIGH_RISK;
+        }
+    }
+    code
+}
+
+/// Describes a record in a line, e.g. `proxy vpn; fraud score 90; country US; ASN 15169`
+fn summary(record: &Record, zone: &Zone) -> String {
+    let flags: Vec<&str> = [
+        (record.is_proxy(), "proxy"),
+        (record.is_vpn(), "vpn"),
+        (record.is_tor(), "tor"),
+        (record.is_blacklisted(), "blacklisted"),
+        (record.recent_abuse(), "recent-abuse"),
+        (record.is_bot(), "bot"),
+    ]
+    .into_iter()
+    .filter(|(flag, _)| *flag == Some(true))
+    .map(|(_, name)| name)
+    .collect();
+
+    let mut parts = Vec::new();
+    if !flags.is_empty() {
+        parts.push(flags.join(" "));
+    }
+    if let Some(score) = record.fraud_score(zone.strictness) {
+        parts.push(format!("fraud score {}", score));
+    }
+    if let Some(country) = record.country() {
+        parts.push(format!("country {}", country));
+    }
+    if let Some(asn) = record.asn() {
+        parts.push(format!("ASN {}", asn));
+    }
+    parts.join("; ")
+}
+
+/// Builds a response to `query`, repeating its ID, flags and question
+fn response(
+    query: &[u8],
+    question: Option<&Question>,
+    rcode: u8,
+    answers: &[(u16, Vec<u8>)],
+    ttl: u32,
+) -> Vec<u8> {
+    let mut message = Vec::with_capacity(512);
+    message.extend_from_slice(&query[..2]);
+    // QR and AA, with the opcode and RD of the query
+    message.push(0x84 | (query[2] & 0x79));
+    message.push(rcode);
+    message.extend_from_slice(&u16::from(question.is_some()).to_be_bytes());
+    message.extend_from_slice(&(answers.len() as u16).to_be_bytes());
+    message.extend_from_slice(&[0, 0, 0, 0]);
+
+    let question = match question {
+        Some(question) => question,
+        None => return message,
+    };
+    message.extend_from_slice(question.wire);
+    for (rtype, data) in answers {
+        // a pointer to the name of the question, right after the 12 byte header
+        message.extend_from_slice(&[0xc0, 12]);
+        message.extend_from_slice(&rtype.to_be_bytes());
+        message.extend_from_slice(&CLASS_IN.to_be_bytes());
+        message.extend_from_slice(&ttl.to_be_bytes());
+        message.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        message.extend_from_slice(data);
+    }
+    message
+}
+
+fn split_name(name: &str) -> Vec<String> {
+    name.trim_end_matches('.')
+        .split('.')
+        .filter(|label| !label.is_empty())
+        .map(str::to_ascii_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ipqs_db_reader::{MemorySource, RecordBuilder};
+
+    fn zone() -> Zone {
+        Zone {
+            labels: split_name("DNSBL.example.com."),
+            ttl: 60,
+            strictness: Strictness::One,
+            suspicious: 75,
+            high_risk: 90,
+        }
+    }
+
+    fn source() -> MemorySource {
+        let mut source = MemorySource::new();
+        let record = RecordBuilder::new()
+            .is_vpn(true)
+            .is_tor(true)
+            .country("FR")
+            .asn(1)
+            .fraud_score(Strictness::One, 80)
+            .build();
+        source.insert("10.0.0.0".parse().unwrap(), 8, record);
+        source.insert("11.0.0.0".parse().unwrap(), 8, RecordBuilder::new().build());
+        source.insert(
+            "2001:db8::".parse().unwrap(),
+            32,
+            RecordBuilder::new().is_proxy(true).build(),
+        );
+        source
+    }
+
+    fn query(name: &str, qtype: u16) -> Vec<u8> {
+        let mut query = vec![0x12, 0x34, 0x01, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+        for label in split_name(name) {
+            query.push(label.len() as u8);
+            query.extend_from_slice(label.as_bytes());
+        }
+        query.push(0);
+        query.extend_from_slice(&qtype.to_be_bytes());
+        query.extend_from_slice(&CLASS_IN.to_be_bytes());
+        query
+    }
+
+    /// Returns the response code and the data of each answer
+    fn ask(name: &str, qtype: u16) -> (u8, Vec<Vec<u8>>) {
+        let query = query(name, qtype);
+        let response = answer(&query, &zone(), &source()).unwrap();
+        assert_eq!(response[..2], [0x12, 0x34]);
+        assert_eq!(response[2], 0x85);
+        // the question is repeated as it was asked
+        assert_eq!(response[12..query.len()], query[12..]);
+
+        let count = u16::from_be_bytes([response[6], response[7]]);
+        let mut position = query.len();
+        let mut answers = Vec::new();
+        for _ in 0..count {
+            let ttl = &response[position + 6..position + 10];
+            assert_eq!(u32::from_be_bytes(ttl.try_into().unwrap()), 60);
+            let length = usize::from(u16::from_be_bytes([
+                response[position + 10],
+                response[position + 11],
+            ]));
+            position += 12;
+            answers.push(response[position..position + length].to_vec());
+            position += length;
+        }
+        assert_eq!(position, response.len());
+        (response[3] & 0x0f, answers)
+    }
+
+    #[test]
+    fn answers_listed_addresses() {
+        assert_eq!(
+            ask("1.0.0.10.dnsbl.example.com", TYPE_A),
+            (NO_ERROR, vec![vec![127, 0, 0, VPN | TOR | SUSPICIOUS]])
+        );
+        let (rcode, answers) = ask("1.0.0.10.Dnsbl.Example.Com", TYPE_TXT);
+        assert_eq!(rcode, NO_ERROR);
+        assert_eq!(
+            answers[0][1..],
+            *b"vpn tor; fraud score 80; country FR; ASN 1"
+        );
+        assert_eq!(ask("1.0.0.10.dnsbl.example.com", TYPE_ANY).1.len(), 2);
+        // listed, but without data of the type asked for
+        assert_eq!(ask("1.0.0.10.dnsbl.example.com", 28), (NO_ERROR, vec![]));
+
+        let ipv6 = "1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2";
+        assert_eq!(
+            ask(&format!("{}.dnsbl.example.com", ipv6), TYPE_A),
+            (NO_ERROR, vec![vec![127, 0, 0, PROXY]])
+        );
+        assert_eq!(
+            ask("2.0.0.127.dnsbl.example.com", TYPE_A),
+            (NO_ERROR, vec![vec![127, 0, 0, PROXY]])
+        );
+    }
+
+    #[test]
+    fn refuses_unlisted_and_malformed_names() {
+        assert_eq!(
+            ask("1.0.0.11.dnsbl.example.com", TYPE_A),
+            (NAME_ERROR, vec![])
+        );
+        assert_eq!(
+            ask("1.0.0.12.dnsbl.example.com", TYPE_A),
+            (NAME_ERROR, vec![])
+        );
+        assert_eq!(
+            ask("1.0.0.127.dnsbl.example.com", TYPE_A),
+            (NAME_ERROR, vec![])
+        );
+        assert_eq!(
+            ask("01.0.0.10.dnsbl.example.com", TYPE_A),
+            (NAME_ERROR, vec![])
+        );
+        assert_eq!(
+            ask("0.0.10.dnsbl.example.com", TYPE_A),
+            (NAME_ERROR, vec![])
+        );
+        assert_eq!(ask("dnsbl.example.com", TYPE_A), (NO_ERROR, vec![]));
+        assert_eq!(ask("1.0.0.10.example.com", TYPE_A), (REFUSED, vec![]));
+
+        let mut truncated = query("1.0.0.10.dnsbl.example.com", TYPE_A);
+        truncated.truncate(truncated.len() - 2);
+        let response = answer(&truncated, &zone(), &source()).unwrap();
+        assert_eq!(response.len(), 12);
+        assert_eq!(response[3], FORMAT_ERROR);
+
+        let mut response = query("1.0.0.10.dnsbl.example.com", TYPE_A);
+        response[2] |= 0x80;
+        assert!(answer(&response, &zone(), &source()).is_none());
+    }
+}
@@ -0,0 +1,160 @@
+// Copyright 2023 IPQualityScore LLC
+//! Answers requests to the IPQS Proxy & VPN Detection API from local database files, for
+//! integration tests and deployments without network access:
+//!
+//! ```text
+//! ipqs-mock-api --ipv4 IPv4.ipqs --ipv6 IPv6.ipqs --listen 127.0.0.1:8080
+//! curl 'http://127.0.0.1:8080/api/json/ip/KEY/8.8.8.8?strictness=1'
+//! ```
+use std::net::{IpAddr, TcpListener};
+use std::process;
+
+use ipqs_db_reader::{ApiResponse, IpReputationSource, Strictness};
+use serde_json::json;
+
+use common::args::Args;
+use common::databases::{Databases, DATABASE_USAGE};
+use common::http::{self, Request, Response};
+
+mod common;
+
+fn usage() -> String {
+    format!(
+        "Usage: ipqs-mock-api [OPTIONS]
+
+Serves GET /api/json/ip/{{key}}/{{ip}}?strictness=N like the IPQS API.
+
+Options:
+{}
+  --listen ADDR       address to listen on [127.0.0.1:8080]
+  --key KEY           only accept this API key, any key is accepted by default",
+        DATABASE_USAGE
+    )
+}
+
+fn main() {
+    let args = Args::parse(&usage());
+    let databases = Databases::open(&args).unwrap_or_else(|error| {
+        eprintln!("failed to open the databases: {}", error);
+        process::exit(1);
+    });
+    let key = args.get("key").map(str::to_owned);
+    let listen = args.get_or("listen", "127.0.0.1:8080");
+    let listener = TcpListener::bind(listen).unwrap_or_else(|error| {
+        eprintln!("failed to listen on {}: {}", listen, error);
+        process::exit(1);
+    });
+    eprintln!("listening on {}", listen);
+
+    let source = databases;
+    let result = http::serve(listener, move |request| {
+        respond(request, &source, key.as_deref())
+    });
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
+
+/// Answers one request. Like the API, failures are reported with `"success": false` and a
+/// message rather than through the HTTP status, except for unknown paths.
+fn respond(request: &Request, source: &impl IpReputationSource, key: Option<&str>) -> Response {
+    let segments = request.segments();
+    let (request_key, ip) = match segments.as_slice() {
+        [api, format, ip_segment, request_key, ip]
+            if api == "api" && format == "json" && ip_segment == "ip" =>
+        {
+            (request_key, ip)
+        }
+        _ => return Response::text(404, "not found"),
+    };
+    if request.method != "GET" && request.method != "POST" {
+        return Response::text(405, "method not allowed");
+    }
+    if key.is_some_and(|key| key != request_key) {
+        return failure("Invalid or unauthorized key. Please check the API key and try again.");
+    }
+    let strictness = match request.query_param("strictness").as_deref() {
+        None | Some("") | Some("0") => Strictness::Zero,
+        Some("1") => Strictness::One,
+        Some("2") => Strictness::Two,
+        Some("3") => Strictness::Three,
+        Some(_) => return failure("Invalid strictness, expected a value from 0 to 3."),
+    };
+    let ip: IpAddr = match ip.parse() {
+        Ok(ip) => ip,
+        Err(_) => return failure("Invalid IPv4 address, IPv6 address or hostname."),
+    };
+
+    match source.lookup(&ip) {
+        Ok(Some(record)) => Response::json(200, &ApiResponse::from_record(&record, strictness)),
+        Ok(None) => failure("No data is available for this IP address."),
+        Err(error) => failure(&error.to_string()),
+    }
+}
+
+fn failure(message: &str) -> Response {
+    Response::json(200, &json!({ "success": false, "message": message }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ipqs_db_reader::{MemorySource, RecordBuilder};
+
+    fn get(source: &MemorySource, target: &str, key: Option<&str>) -> (u16, serde_json::Value) {
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let request = Request {
+            method: "GET".to_owned(),
+            path: path.to_owned(),
+            query: query.to_owned(),
+            ..Default::default()
+        };
+        let response = respond(&request, source, key);
+        let body = serde_json::from_slice(&response.body).unwrap_or_default();
+        (response.status, body)
+    }
+
+    #[test]
+    fn api_requests() {
+        let mut source = MemorySource::new();
+        let record = RecordBuilder::new()
+            .is_vpn(true)
+            .fraud_score(Strictness::Zero, 40)
+            .fraud_score(Strictness::Two, 85)
+            .country("NL")
+            .build();
+        source.insert("10.0.0.0".parse().unwrap(), 8, record);
+
+        let (status, body) = get(&source, "/api/json/ip/KEY/10.1.2.3", None);
+        assert_eq!(status, 200);
+        assert_eq!(body["success"], true);
+        assert_eq!(body["fraud_score"], 40);
+        assert_eq!(body["vpn"], true);
+        assert_eq!(body["country_code"], "NL");
+
+        let (_, body) = get(&source, "/api/json/ip/KEY/10.1.2.3?strictness=2", None);
+        assert_eq!(body["fraud_score"], 85);
+
+        for target in [
+            "/api/json/ip/KEY/10.1.2.3?strictness=9",
+            "/api/json/ip/KEY/not-an-ip",
+            "/api/json/ip/KEY/9.0.0.1",
+        ] {
+            let (status, body) = get(&source, target, None);
+            assert_eq!(
+                (status, &body["success"]),
+                (200, &json!(false)),
+                "{}",
+                target
+            );
+        }
+
+        let (_, body) = get(&source, "/api/json/ip/WRONG/10.1.2.3", Some("KEY"));
+        assert_eq!(body["success"], false);
+        let (_, body) = get(&source, "/api/json/ip/KEY/10.1.2.3", Some("KEY"));
+        assert_eq!(body["success"], true);
+
+        assert_eq!(get(&source, "/api/json/email/KEY/x", None).0, 404);
+    }
+}
@@ -0,0 +1,404 @@
+// Copyright 2023 IPQualityScore LLC
+//! A server speaking a small part of the Redis protocol (RESP), so that existing Redis clients
+//! can look up addresses:
+//!
+//! ```text
+//! ipqs-resp --ipv4 IPv4.ipqs --ipv6 IPv6.ipqs --listen 127.0.0.1:6380
+//! redis-cli -p 6380 GET 8.8.8.8
+//! redis-cli -p 6380 HGETALL 8.8.8.8
+//! ```
+//!
+//! Supported commands: `PING [message]`, `GET ip` (the record as JSON, nil without a record),
+//! `MGET ip [ip ...]`, `HGETALL ip` (the fields of the record, empty without a record),
+//! `INFO` (the header of each database), `COMMAND` and `QUIT`.
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::net::{IpAddr, TcpListener};
+use std::{process, thread};
+
+use ipqs_db_reader::{IpReputationSource, Metadata, Record};
+use serde_json::Value;
+
+use common::args::Args;
+use common::databases::{Databases, DATABASE_USAGE};
+use common::lines::{flush_unless_pending, read_line};
+
+mod common;
+
+const MAX_ARGUMENTS: usize = 100_000;
+const MAX_BULK: usize = 512 * 1024;
+
+fn usage() -> String {
+    format!(
+        "Usage: ipqs-resp [OPTIONS]
+
+Answers PING, GET, MGET, HGETALL, INFO, COMMAND and QUIT like a Redis server.
+
+Options:
+{}
+  --listen ADDR       address to listen on [127.0.0.1:6380]",
+        DATABASE_USAGE
+    )
+}
+
+fn main() {
+    let args = Args::parse(&usage());
+    let databases = Databases::open(&args).unwrap_or_else(|error| {
+        eprintln!("failed to open the databases: {}", error);
+        process::exit(1);
+    });
+    let listen = args.get_or("listen", "127.0.0.1:6380");
+    let listener = TcpListener::bind(listen).unwrap_or_else(|error| {
+        eprintln!("failed to listen on {}: {}", listen, error);
+        process::exit(1);
+    });
+    eprintln!("listening on {}", listen);
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(error) => {
+                eprintln!("failed to accept a connection: {}", error);
+                continue;
+            }
+        };
+        let databases = databases.clone();
+        thread::spawn(move || {
+            let database_info = || info(&databases);
+            let result = stream.try_clone().and_then(|reader| {
+                serve(
+                    BufReader::new(reader),
+                    BufWriter::new(stream),
+                    &databases,
+                    database_info,
+                )
+            });
+            if let Err(error) = result {
+                if error.kind() != io::ErrorKind::UnexpectedEof {
+                    eprintln!("connection failed: {}", error);
+                }
+            }
+        });
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Reply {
+    Simple(String),
+    Error(String),
+    Bulk(Option<Vec<u8>>),
+    Array(Vec<Reply>),
+}
+
+impl Reply {
+    fn error(message: &str) -> Reply {
+        Reply::Error(format!("ERR {}", message))
+    }
+
+    fn bulk(value: impl Into<Vec<u8>>) -> Reply {
+        Reply::Bulk(Some(value.into()))
+    }
+
+    fn write_to(&self, output: &mut impl Write) -> io::Result<()> {
+        match self {
+            Reply::Simple(value) => write!(output, "+{}\r\n", value),
+            Reply::Error(message) => write!(output, "-{}\r\n", message),
+            Reply::Bulk(None) => output.write_all(b"$-1\r\n"),
+            Reply::Bulk(Some(value)) => {
+                write!(output, "${}\r\n", value.len())?;
+                output.write_all(value)?;
+                output.write_all(b"\r\n")
+            }
+            Reply::Array(replies) => {
+                write!(output, "*{}\r\n", replies.len())?;
+                replies.iter().try_for_each(|reply| reply.write_to(output))
+            }
+        }
+    }
+}
+
+/// Answers the commands of one connection until the client closes it or sends QUIT. Clients
+/// may pipeline commands; the replies to commands that arrived together are sent together,
+/// see [flush_unless_pending].
+fn serve<R: Read>(
+    mut input: BufReader<R>,
+    mut output: impl Write,
+    source: &impl IpReputationSource,
+    info: impl Fn() -> String,
+) -> io::Result<()> {
+    loop {
+        let command = match read_command(&mut input) {
+            Ok(Some(command)) => command,
+            Ok(None) => return output.flush(),
+            Err(error) if error.kind() == io::ErrorKind::InvalidData => {
+                Reply::error(&format!("Protocol error: {}", error)).write_to(&mut output)?;
+                return output.flush();
+            }
+            Err(error) => return Err(error),
+        };
+        if command.is_empty() {
+            continue;
+        }
+        let quit = command[0].eq_ignore_ascii_case(b"QUIT");
+        execute(&command, source, &info).write_to(&mut output)?;
+        if quit {
+            return output.flush();
+        }
+        flush_unless_pending(&input, &mut output)?;
+    }
+}
+
+fn execute(
+    command: &[Vec<u8>],
+    source: &impl IpReputationSource,
+    info: &impl Fn() -> String,
+) -> Reply {
+    let name = String::from_utf8_lossy(&command[0]).to_ascii_uppercase();
+    let arguments = &command[1..];
+    match (name.as_str(), arguments.len()) {
+        ("PING", 0) => Reply::Simple("PONG".to_owned()),
+        ("PING", 1) => Reply::bulk(arguments[0].clone()),
+        ("GET", 1) => get(&arguments[0], source),
+        ("MGET", n) if n > 0 => Reply::Array(
+            arguments
+                .iter()
+                .map(|argument| match get(argument, source) {
+                    // MGET answers nil for keys it cannot get
+                    Reply::Error(_) => Reply::Bulk(None),
+                    reply => reply,
+                })
+                .collect(),
+        ),
+        ("HGETALL", 1) => hgetall(&arguments[0], source),
+        ("INFO", _) => Reply::bulk(info()),
+        // clients ask for the command table when they connect
+        ("COMMAND", _) => Reply::Array(Vec::new()),
+        ("QUIT", _) => Reply::Simple("OK".to_owned()),
+        ("PING" | "GET" | "MGET" | "HGETALL", _) => Reply::error(&format!(
+            "wrong number of arguments for '{}' command",
+            name.to_ascii_lowercase()
+        )),
+        _ => Reply::error(&format!("unknown command '{}'", name.to_ascii_lowercase())),
+    }
+}
+
+fn lookup(key: &[u8], source: &impl IpReputationSource) -> Result<Option<Record>, String> {
+    let ip: IpAddr = std::str::from_utf8(key)
+        .ok()
+        .and_then(|key| key.parse().ok())
+        .ok_or("invalid IP address")?;
+    source.lookup(&ip).map_err(|error| error.to_string())
+}
+
+fn get(key: &[u8], source: &impl IpReputationSource) -> Reply {
+    match lookup(key, source) {
+        Ok(Some(record)) => match serde_json::to_vec(&record) {
+            Ok(json) => Reply::bulk(json),
+            Err(error) => Reply::error(&error.to_string()),
+        },
+        Ok(None) => Reply::Bulk(None),
+        Err(message) => Reply::error(&message),
+    }
+}
+
+/// Lists the fields of the record that have a value, with the fraud scores as
+/// `fraud_score_0` to `fraud_score_3`
+fn hgetall(key: &[u8], source: &impl IpReputationSource) -> Reply {
+    let record = match lookup(key, source) {
+        Ok(Some(record)) => record,
+        Ok(None) => return Reply::Array(Vec::new()),
+        Err(message) => return Reply::error(&message),
+    };
+    let fields = match serde_json::to_value(&record) {
+        Ok(Value::Object(fields)) => fields,
+        Ok(_) => return Reply::error("record is not an object"),
+        Err(error) => return Reply::error(&error.to_string()),
+    };
+
+    let mut replies = Vec::new();
+    let mut push = |name: String, value: &Value| {
+        let value = match value {
+            Value::String(value) => value.clone(),
+            Value::Bool(_) | Value::Number(_) => value.to_string(),
+            _ => return,
+        };
+        replies.push(Reply::bulk(name));
+        replies.push(Reply::bulk(value));
+    };
+    for (name, value) in &fields {
+        match name.as_str() {
+            "fraud_score" => {
+                if let Some(Value::Array(scores)) = value.get("strictness") {
+                    for (strictness, score) in scores.iter().enumerate() {
+                        push(format!("fraud_score_{}", strictness), score);
+                    }
+                }
+            }
+            "columns" => {}
+            _ => push(name.clone(), value),
+        }
+    }
+    Reply::Array(replies)
+}
+
+fn info(databases: &Databases) -> String {
+    let mut info = format!(
+        "# Server\r\nipqs_db_reader_version:{}\r\n",
+        env!("CARGO_PKG_VERSION")
+    );
+    for (name, pool) in [("IPv4", &databases.ipv4), ("IPv6", &databases.ipv6)] {
+        info.push_str(&format!("\r\n# {}\r\n", name));
+        let metadata: Option<Metadata> = pool
+            .as_ref()
+            .and_then(|pool| pool.get().ok())
+            .map(|reader| reader.metadata());
+        match metadata {
+            None => info.push_str("loaded:0\r\n"),
+            Some(metadata) => info.push_str(&format!(
+                "loaded:1\r\nblacklist:{}\r\nbinary_data:{}\r\ncolumns:{}\r\nrecord_bytes:{}\r\ntree_bytes:{}\r\n",
+                u8::from(metadata.is_blacklist),
+                u8::from(metadata.binary_data),
+                metadata.columns.join(","),
+                metadata.record_bytes,
+                metadata.tree_bytes,
+            )),
+        }
+    }
+    info
+}
+
+/// Reads a command sent as an array of bulk strings, or as an inline command (words
+/// separated by spaces, as typed into telnet). Returns None once the client has disconnected.
+fn read_command(input: &mut impl BufRead) -> io::Result<Option<Vec<Vec<u8>>>> {
+    let line = match read_line(input)? {
+        Some(line) => line,
+        None => return Ok(None),
+    };
+    let count = match line.strip_prefix(b"*") {
+        Some(count) => parse_length(count, MAX_ARGUMENTS)?,
+        None => {
+            let words = line
+                .split(|byte| *byte == b' ')
+                .filter(|word| !word.is_empty())
+                .map(<[u8]>::to_vec)
+                .collect();
+            return Ok(Some(words));
+        }
+    };
+
+    let mut command = Vec::with_capacity(count);
+    for _ in 0..count {
+        let line =
+            read_line(input)?.ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+        let length = match line.strip_prefix(b"$") {
+            Some(length) => parse_length(length, MAX_BULK)?,
+            None => return Err(invalid("expected '$'")),
+        };
+        let mut argument = vec![0; length + 2];
+        input.read_exact(&mut argument)?;
+        if !argument.ends_with(b"\r\n") {
+            return Err(invalid("bulk string is not terminated"));
+        }
+        argument.truncate(length);
+        command.push(argument);
+    }
+    Ok(Some(command))
+}
+
+fn parse_length(bytes: &[u8], max: usize) -> io::Result<usize> {
+    std::str::from_utf8(bytes)
+        .ok()
+        .and_then(|length| length.parse().ok())
+        .filter(|length| *length <= max)
+        .ok_or_else(|| invalid("invalid length"))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ipqs_db_reader::{MemorySource, RecordBuilder, Strictness};
+
+    fn run(input: &[u8]) -> String {
+        let mut source = MemorySource::new();
+        let record = RecordBuilder::new()
+            .is_vpn(true)
+            .country("FR")
+            .fraud_score(Strictness::One, 80)
+            .build();
+        source.insert("10.0.0.0".parse().unwrap(), 8, record);
+        let mut output = Vec::new();
+        serve(BufReader::new(input), &mut output, &source, || {
+            "# Server\r\n".to_owned()
+        })
+        .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!(run(b"*1\r\n$4\r\nPING\r\n"), "+PONG\r\n");
+        assert_eq!(run(b"PING hello\r\n"), "$5\r\nhello\r\n");
+        assert_eq!(run(b"GET 9.0.0.1\r\n"), "$-1\r\n");
+        assert!(run(b"GET 10.0.0.1\r\n").contains("\"country\":\"FR\""));
+        assert!(run(b"GET nonsense\r\n").starts_with("-ERR invalid IP address"));
+        assert!(run(b"GET\r\n").starts_with("-ERR wrong number of arguments"));
+        assert!(run(b"FLUSHALL\r\n").starts_with("-ERR unknown command 'flushall'"));
+        assert_eq!(run(b"INFO\r\n"), "$10\r\n# Server\r\n\r\n");
+
+        let reply = run(b"*3\r\n$4\r\nMGET\r\n$8\r\n10.0.0.1\r\n$7\r\nbad key\r\n");
+        assert!(reply.starts_with("*2\r\n$"));
+        assert!(reply.ends_with("$-1\r\n"));
+
+        // the connection is closed after QUIT
+        assert_eq!(run(b"QUIT\r\nPING\r\n"), "+OK\r\n");
+        assert!(run(b"*1\r\n$x\r\nPING\r\n").starts_with("-ERR Protocol error"));
+    }
+
+    #[test]
+    fn hgetall_lists_fields() {
+        let reply = run(b"HGETALL 10.0.0.1\r\n");
+        let lines: Vec<&str> = reply.split("\r\n").collect();
+        let field = |name: &str| {
+            let position = lines.iter().position(|line| *line == name)?;
+            Some(lines[position + 2])
+        };
+        assert_eq!(field("country"), Some("FR"));
+        assert_eq!(field("is_vpn"), Some("true"));
+        assert_eq!(field("fraud_score_1"), Some("80"));
+        assert_eq!(field("fraud_score_0"), None);
+        assert_eq!(field("city"), None);
+        assert_eq!(run(b"HGETALL 9.0.0.1\r\n"), "*0\r\n");
+    }
+
+    #[test]
+    fn answers_before_the_client_is_done() -> io::Result<()> {
+        use std::net::TcpStream;
+        use std::time::Duration;
+
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let client = TcpStream::connect(listener.local_addr()?)?;
+        let (server, _) = listener.accept()?;
+        let server = thread::spawn(move || {
+            let input = BufReader::new(server.try_clone()?);
+            serve(
+                input,
+                BufWriter::new(server),
+                &MemorySource::new(),
+                String::new,
+            )
+        });
+
+        // the write side stays open while the reply is read
+        client.set_read_timeout(Some(Duration::from_secs(5)))?;
+        (&client).write_all(b"*1\r\n$4\r\nPING\r\n")?;
+        let mut reply = [0; 7];
+        (&client).read_exact(&mut reply)?;
+        assert_eq!(&reply, b"+PONG\r\n");
+
+        client.shutdown(std::net::Shutdown::Write)?;
+        server.join().unwrap()
+    }
+}
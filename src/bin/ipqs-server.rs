@@ -0,0 +1,191 @@
+// Copyright 2023 IPQualityScore LLC
+//! An HTTP server answering lookups from IPv4 and IPv6 database files with JSON records:
+//!
+//! ```text
+//! ipqs-server --ipv4 IPv4.ipqs --ipv6 IPv6.ipqs --listen 127.0.0.1:8080
+//! curl http://127.0.0.1:8080/lookup/8.8.8.8
+//! curl -d '["8.8.8.8", "2001:4860:4860::8844"]' http://127.0.0.1:8080/lookup
+//! ```
+use std::error::Error;
+use std::net::{IpAddr, TcpListener};
+use std::process;
+
+use ipqs_db_reader::{IpReputationSource, Metadata};
+use serde_json::{json, Value};
+
+use common::args::Args;
+use common::databases::{Databases, DATABASE_USAGE};
+use common::http::{self, Request, Response};
+
+mod common;
+
+// the most addresses a single POST /lookup may ask for
+const MAX_BATCH: usize = 10_000;
+
+fn usage() -> String {
+    format!(
+        "Usage: ipqs-server [OPTIONS]
+
+Endpoints:
+  GET  /lookup/{{ip}}   the record of one address
+  POST /lookup        the records of a JSON array of addresses
+  GET  /metadata      the header of each database
+  GET  /health        200 while every database can be read
+
+Options:
+{}
+  --listen ADDR       address to listen on [127.0.0.1:8080]",
+        DATABASE_USAGE
+    )
+}
+
+fn main() {
+    let args = Args::parse(&usage());
+    let databases = Databases::open(&args).unwrap_or_else(|error| {
+        eprintln!("failed to open the databases: {}", error);
+        process::exit(1);
+    });
+    let listen = args.get_or("listen", "127.0.0.1:8080");
+    let listener = TcpListener::bind(listen).unwrap_or_else(|error| {
+        eprintln!("failed to listen on {}: {}", listen, error);
+        process::exit(1);
+    });
+    eprintln!("listening on {}", listen);
+
+    if let Err(error) = http::serve(listener, move |request| respond(request, &databases)) {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
+
+fn respond(request: &Request, databases: &Databases) -> Response {
+    let segments = request.segments();
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+    match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["lookup", ip]) => lookup(databases, ip),
+        ("POST", ["lookup"]) => batch(databases, &request.body),
+        ("GET", ["metadata"]) => metadata(databases),
+        ("GET", ["health"]) => health(databases),
+        (_, ["lookup", ..] | ["metadata"] | ["health"]) => {
+            Response::text(405, "method not allowed")
+        }
+        _ => Response::text(404, "not found"),
+    }
+}
+
+fn lookup(source: &impl IpReputationSource, ip: &str) -> Response {
+    let ip: IpAddr = match ip.parse() {
+        Ok(ip) => ip,
+        Err(_) => return Response::json(400, &json!({ "error": "invalid IP address" })),
+    };
+    match source.lookup(&ip) {
+        Ok(Some(record)) => Response::json(200, &record),
+        Ok(None) => Response::json(404, &json!({ "error": "no record for this IP address" })),
+        Err(error) => Response::json(400, &json!({ "error": error.to_string() })),
+    }
+}
+
+/// Answers a JSON array of addresses with an array of `{"ip", "record"}` objects in the same
+/// order. The record is null for addresses without one, and an `"error"` replaces it when an
+/// address cannot be looked up.
+fn batch(source: &impl IpReputationSource, body: &[u8]) -> Response {
+    let ips: Vec<String> = match serde_json::from_slice(body) {
+        Ok(ips) => ips,
+        Err(error) => {
+            let message = format!("expected a JSON array of IP addresses: {}", error);
+            return Response::json(400, &json!({ "error": message }));
+        }
+    };
+    if ips.len() > MAX_BATCH {
+        let message = format!("at most {} addresses can be looked up at once", MAX_BATCH);
+        return Response::json(400, &json!({ "error": message }));
+    }
+
+    let results: Vec<Value> = ips
+        .into_iter()
+        .map(|ip| {
+            let result = ip
+                .parse::<IpAddr>()
+                .map_err(|_| "invalid IP address".into())
+                .and_then(|address| source.lookup(&address));
+            match result {
+                Ok(record) => json!({ "ip": ip, "record": record }),
+                Err(error) => json!({ "ip": ip, "error": error.to_string() }),
+            }
+        })
+        .collect();
+    Response::json(200, &results)
+}
+
+fn metadata(databases: &Databases) -> Response {
+    let metadata = |ipv6: bool| -> Result<Option<Metadata>, Box<dyn Error>> {
+        let pool = if ipv6 {
+            &databases.ipv6
+        } else {
+            &databases.ipv4
+        };
+        match pool {
+            Some(pool) => Ok(Some(pool.get()?.metadata())),
+            None => Ok(None),
+        }
+    };
+    match (metadata(false), metadata(true)) {
+        (Ok(ipv4), Ok(ipv6)) => Response::json(200, &json!({ "ipv4": ipv4, "ipv6": ipv6 })),
+        (Err(error), _) | (_, Err(error)) => {
+            Response::json(500, &json!({ "error": error.to_string() }))
+        }
+    }
+}
+
+fn health(databases: &Databases) -> Response {
+    for pool in [&databases.ipv4, &databases.ipv6].into_iter().flatten() {
+        if let Err(error) = pool.get() {
+            let body = json!({ "status": "unavailable", "error": error.to_string() });
+            return Response::json(503, &body);
+        }
+    }
+    Response::json(200, &json!({ "status": "ok" }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ipqs_db_reader::{MemorySource, RecordBuilder, Strictness};
+
+    fn source() -> MemorySource {
+        let mut source = MemorySource::new();
+        let record = RecordBuilder::new()
+            .is_proxy(true)
+            .fraud_score(Strictness::Zero, 75)
+            .build();
+        source.insert("10.0.0.0".parse().unwrap(), 8, record);
+        source
+    }
+
+    fn body(response: &Response) -> Value {
+        serde_json::from_slice(&response.body).unwrap()
+    }
+
+    #[test]
+    fn lookups() {
+        let response = lookup(&source(), "10.0.0.1");
+        assert_eq!(response.status, 200);
+        assert_eq!(body(&response)["is_proxy"], true);
+        assert_eq!(body(&response)["fraud_score"]["strictness"][0], 75);
+        assert_eq!(lookup(&source(), "9.0.0.1").status, 404);
+        assert_eq!(lookup(&source(), "10.0.0").status, 400);
+    }
+
+    #[test]
+    fn batches() {
+        let response = batch(&source(), br#"["10.0.0.1", "9.0.0.1", "nope"]"#);
+        assert_eq!(response.status, 200);
+        let results = body(&response);
+        assert_eq!(results[0]["ip"], "10.0.0.1");
+        assert_eq!(results[0]["record"]["is_proxy"], true);
+        assert_eq!(results[1]["record"], Value::Null);
+        assert_eq!(results[2]["error"], "invalid IP address");
+
+        assert_eq!(batch(&source(), b"{}").status, 400);
+    }
+}
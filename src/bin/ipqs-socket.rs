@@ -0,0 +1,196 @@
+// Copyright 2023 IPQualityScore LLC
+//! A daemon answering lookups over a Unix domain socket, so that local processes in any
+//! language share one in-memory copy of the databases. Each line sent is an IP address, and
+//! each line returned is its record as JSON, `null` if it has no record, or `{"error": ...}`.
+//! Responses come back in the order of the requests, so a client may send many addresses
+//! before reading the answers.
+//!
+//! ```text
+//! ipqs-socket --ipv4 IPv4.ipqs --ipv6 IPv6.ipqs --socket /run/ipqs.sock
+//! printf '8.8.8.8\n2001:4860:4860::8844\n' | nc -U /run/ipqs.sock
+//! ```
+use std::io::{self, BufReader, Read, Write};
+use std::net::IpAddr;
+
+use ipqs_db_reader::IpReputationSource;
+use serde_json::json;
+
+use common::lines::{flush_unless_pending, read_line};
+
+mod common;
+
+fn usage() -> String {
+    format!(
+        "Usage: ipqs-socket [OPTIONS]
+
+Answers one IP address per line with one JSON record per line.
+
+Options:
+{}
+  --socket PATH       path of the Unix domain socket [/tmp/ipqs.sock]",
+        common::databases::DATABASE_USAGE
+    )
+}
+
+#[cfg(unix)]
+fn main() {
+    use std::io::BufWriter;
+    use std::os::unix::net::UnixListener;
+    use std::path::Path;
+    use std::{fs, process, thread};
+
+    use common::args::Args;
+    use common::databases::Databases;
+
+    let args = Args::parse(&usage());
+    let databases = Databases::open(&args).unwrap_or_else(|error| {
+        eprintln!("failed to open the databases: {}", error);
+        process::exit(1);
+    });
+    let path = Path::new(args.get_or("socket", "/tmp/ipqs.sock"));
+    // a socket left behind by a previous run would make bind fail
+    if fs::symlink_metadata(path).is_ok_and(|metadata| {
+        use std::os::unix::fs::FileTypeExt;
+        metadata.file_type().is_socket()
+    }) {
+        let _ = fs::remove_file(path);
+    }
+    let listener = UnixListener::bind(path).unwrap_or_else(|error| {
+        eprintln!("failed to listen on {}: {}", path.display(), error);
+        process::exit(1);
+    });
+    eprintln!("listening on {}", path.display());
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(error) => {
+                eprintln!("failed to accept a connection: {}", error);
+                continue;
+            }
+        };
+        let source = databases.clone();
+        thread::spawn(move || {
+            let result = stream
+                .try_clone()
+                .and_then(|reader| answer(BufReader::new(reader), BufWriter::new(stream), &source));
+            if let Err(error) = result {
+                eprintln!("connection failed: {}", error);
+            }
+        });
+    }
+}
+
+#[cfg(not(unix))]
+fn main() {
+    eprintln!(
+        "{}\n\nUnix domain sockets are not available on this platform",
+        usage()
+    );
+    std::process::exit(1);
+}
+
+/// Answers every line of `input` until the client closes the connection, or sends a line too
+/// long to be an address. Responses are written as soon as no more requests are waiting in the
+/// buffer, see [flush_unless_pending].
+fn answer<R: Read>(
+    mut input: BufReader<R>,
+    mut output: impl Write,
+    source: &impl IpReputationSource,
+) -> io::Result<()> {
+    loop {
+        let line = match read_line(&mut input) {
+            Ok(Some(line)) => line,
+            Ok(None) => return output.flush(),
+            Err(error) if error.kind() == io::ErrorKind::InvalidData => {
+                writeln!(output, "{}", json!({ "error": error.to_string() }))?;
+                return output.flush();
+            }
+            Err(error) => return Err(error),
+        };
+        let response = match String::from_utf8_lossy(&line).trim().parse::<IpAddr>() {
+            Ok(ip) => match source.lookup(&ip) {
+                Ok(record) => serde_json::to_string(&record)?,
+                Err(error) => json!({ "error": error.to_string() }).to_string(),
+            },
+            Err(_) => json!({ "error": "invalid IP address" }).to_string(),
+        };
+        output.write_all(response.as_bytes())?;
+        output.write_all(b"\n")?;
+        flush_unless_pending(&input, &mut output)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ipqs_db_reader::{MemorySource, RecordBuilder};
+    use serde_json::Value;
+
+    #[test]
+    fn answers_each_line_in_order() -> io::Result<()> {
+        let mut source = MemorySource::new();
+        source.insert(
+            "10.0.0.0".parse().unwrap(),
+            8,
+            RecordBuilder::new().asn(1).build(),
+        );
+        source.insert(
+            "2001:db8::".parse().unwrap(),
+            32,
+            RecordBuilder::new().asn(2).build(),
+        );
+
+        let input: &[u8] = b"10.0.0.1\n2001:db8::1\r\n9.0.0.1\nnonsense\n10.0.0.2";
+        let mut output = Vec::new();
+        answer(BufReader::new(input), &mut output, &source)?;
+
+        let lines: Vec<Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0]["asn"], 1);
+        assert_eq!(lines[1]["asn"], 2);
+        assert_eq!(lines[2], Value::Null);
+        assert_eq!(lines[3]["error"], "invalid IP address");
+        assert_eq!(lines[4]["asn"], 1);
+
+        let long = vec![b'1'; 100_000];
+        let mut output = Vec::new();
+        answer(BufReader::new(long.as_slice()), &mut output, &source)?;
+        assert_eq!(output, b"{\"error\":\"line is too long\"}\n");
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn answers_before_the_client_is_done() -> io::Result<()> {
+        use std::io::BufRead;
+        use std::os::unix::net::UnixStream;
+        use std::time::Duration;
+
+        let mut source = MemorySource::new();
+        source.insert(
+            "10.0.0.0".parse().unwrap(),
+            8,
+            RecordBuilder::new().asn(1).build(),
+        );
+        let (client, server) = UnixStream::pair()?;
+        let server = std::thread::spawn(move || {
+            let input = BufReader::new(server.try_clone()?);
+            answer(input, io::BufWriter::new(server), &source)
+        });
+
+        // the write side stays open while the answer is read
+        client.set_read_timeout(Some(Duration::from_secs(5)))?;
+        (&client).write_all(b"10.0.0.1\n")?;
+        let mut line = String::new();
+        BufReader::new(&client).read_line(&mut line)?;
+        assert_eq!(serde_json::from_str::<Value>(&line).unwrap()["asn"], 1);
+
+        client.shutdown(std::net::Shutdown::Write)?;
+        server.join().unwrap()
+    }
+}
@@ -0,0 +1,460 @@
+// Copyright 2023 IPQualityScore LLC
+//! Finds the address of the client of a request that went through proxies, so that the client
+//! is looked up rather than the load balancer in front of it.
+//!
+//! The forwarding headers of a request can be written by anyone, so they are only read when
+//! the request comes from a trusted proxy, and only up to the first address that is not a
+//! trusted proxy: addresses before it were written by the client and may be spoofed.
+//! ```
+//! use ipqs_db_reader::client_ip::{ProxyHeaders, TrustedProxies};
+//! use std::{error, net::IpAddr};
+//! let proxies = TrustedProxies::new(["10.0.0.0/8".parse()?, "fd00::/8".parse()?]);
+//!
+//! // the client sent a spoofed address, the load balancers added the real one and their own
+//! let headers = ProxyHeaders::new().x_forwarded_for("192.0.2.1, 198.51.100.7, 10.1.2.3");
+//! let client = proxies.client_ip("10.0.0.1".parse()?, &headers)?;
+//! assert_eq!(client, "198.51.100.7".parse::<IpAddr>()?);
+//!
+//! // requests that do not come from a trusted proxy are from the peer, whatever their headers
+//! let client = proxies.client_ip("203.0.113.9".parse()?, &headers)?;
+//! assert_eq!(client, "203.0.113.9".parse::<IpAddr>()?);
+//! # Ok::<(), Box <dyn error::Error>>(())
+//! ```
+use std::error::Error;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+
+/// A network of IPv4 or IPv6 addresses, such as `10.0.0.0/8` or `2001:db8::/32`. A single
+/// address without a prefix length is a network of one address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct IpNetwork {
+    address: IpAddr,
+    prefix_len: u32,
+}
+
+impl IpNetwork {
+    /// The network of `prefix_len` bits starting at `address`. The prefix length is capped at
+    /// 32 (IPv4) or 128 (IPv6) bits, and the bits of `address` after it are cleared.
+    pub fn new(address: IpAddr, prefix_len: u32) -> IpNetwork {
+        let address = address.to_canonical();
+        let prefix_len = prefix_len.min(width(&address));
+        let address = match address {
+            IpAddr::V4(ipv4) => IpAddr::V4((u32::from(ipv4) & mask(32, prefix_len) as u32).into()),
+            IpAddr::V6(ipv6) => IpAddr::V6((u128::from(ipv6) & mask(128, prefix_len)).into()),
+        };
+        IpNetwork {
+            address,
+            prefix_len,
+        }
+    }
+
+    /// Returns true if `ip` is in the network. IPv4-mapped IPv6 addresses (`::ffff:a.b.c.d`)
+    /// are in the IPv4 networks of their IPv4 address.
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (ip.to_canonical(), self.address) {
+            (IpAddr::V4(ip), IpAddr::V4(network)) => {
+                u32::from(ip) & mask(32, self.prefix_len) as u32 == u32::from(network)
+            }
+            (IpAddr::V6(ip), IpAddr::V6(network)) => {
+                u128::from(ip) & mask(128, self.prefix_len) == u128::from(network)
+            }
+            _ => false,
+        }
+    }
+
+    pub fn address(&self) -> IpAddr {
+        self.address
+    }
+
+    pub fn prefix_len(&self) -> u32 {
+        self.prefix_len
+    }
+}
+
+impl FromStr for IpNetwork {
+    type Err = Box<dyn Error>;
+
+    fn from_str(network: &str) -> Result<IpNetwork, Box<dyn Error>> {
+        let invalid = || format!("invalid network: {}", network);
+        let (address, prefix_len) = match network.split_once('/') {
+            Some((address, prefix_len)) => (address, Some(prefix_len)),
+            None => (network, None),
+        };
+        let address: IpAddr = address.parse().map_err(|_| invalid())?;
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len.parse().map_err(|_| invalid())?,
+            None => width(&address),
+        };
+        if prefix_len > width(&address) {
+            return Err(invalid().into());
+        }
+        Ok(IpNetwork::new(address, prefix_len))
+    }
+}
+
+impl fmt::Display for IpNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.address, self.prefix_len)
+    }
+}
+
+fn width(ip: &IpAddr) -> u32 {
+    if ip.is_ipv4() {
+        32
+    } else {
+        128
+    }
+}
+
+// the first `prefix_len` of `width` bits set
+fn mask(width: u32, prefix_len: u32) -> u128 {
+    let all = u128::MAX >> (128 - width);
+    all & !(all.checked_shr(prefix_len).unwrap_or(0))
+}
+
+/// The header that trusted proxies write the address of their peer in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ForwardingHeader {
+    /// `Forwarded: for=192.0.2.1, for="[2001:db8::1]:443"` (RFC 7239)
+    Forwarded,
+    /// `X-Forwarded-For: 192.0.2.1, 2001:db8::1`
+    #[default]
+    XForwardedFor,
+    /// `X-Real-IP: 192.0.2.1`, written by a single proxy
+    XRealIp,
+}
+
+impl ForwardingHeader {
+    fn name(&self) -> &'static str {
+        match self {
+            ForwardingHeader::Forwarded => "Forwarded",
+            ForwardingHeader::XForwardedFor => "X-Forwarded-For",
+            ForwardingHeader::XRealIp => "X-Real-IP",
+        }
+    }
+}
+
+/// The raw values of the forwarding headers of a request. Headers sent more than once are
+/// given once per line, in the order they were received.
+#[derive(Clone, Debug, Default)]
+pub struct ProxyHeaders<'a> {
+    forwarded: Vec<&'a str>,
+    x_forwarded_for: Vec<&'a str>,
+    x_real_ip: Vec<&'a str>,
+}
+
+impl<'a> ProxyHeaders<'a> {
+    pub fn new() -> ProxyHeaders<'a> {
+        ProxyHeaders::default()
+    }
+
+    pub fn forwarded(mut self, value: &'a str) -> ProxyHeaders<'a> {
+        self.forwarded.push(value);
+        self
+    }
+
+    pub fn x_forwarded_for(mut self, value: &'a str) -> ProxyHeaders<'a> {
+        self.x_forwarded_for.push(value);
+        self
+    }
+
+    pub fn x_real_ip(mut self, value: &'a str) -> ProxyHeaders<'a> {
+        self.x_real_ip.push(value);
+        self
+    }
+
+    /// Returns the addresses of `header`, from the client to the last proxy. Hops without an
+    /// address, such as `for=unknown`, are None.
+    fn hops(&self, header: ForwardingHeader) -> Result<Vec<Option<IpAddr>>, Box<dyn Error>> {
+        match header {
+            ForwardingHeader::Forwarded => self
+                .forwarded
+                .iter()
+                .map(|value| forwarded_for(value))
+                .collect::<Result<Vec<_>, _>>()
+                .map(|lines| lines.concat()),
+            ForwardingHeader::XForwardedFor => Ok(self
+                .x_forwarded_for
+                .iter()
+                .flat_map(|value| value.split(','))
+                .map(parse_node)
+                .collect()),
+            ForwardingHeader::XRealIp => match self.x_real_ip.as_slice() {
+                [] => Ok(Vec::new()),
+                [value] => Ok(vec![parse_node(value)]),
+                _ => Err("X-Real-IP header was sent more than once".into()),
+            },
+        }
+    }
+}
+
+/// The proxies whose forwarding headers are trusted, e.g. the subnet of the load balancers,
+/// and the header they write. The header defaults to `X-Forwarded-For`.
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies {
+    networks: Vec<IpNetwork>,
+    header: ForwardingHeader,
+}
+
+impl TrustedProxies {
+    pub fn new(networks: impl IntoIterator<Item = IpNetwork>) -> TrustedProxies {
+        TrustedProxies {
+            networks: networks.into_iter().collect(),
+            header: ForwardingHeader::default(),
+        }
+    }
+
+    /// Sets the header the proxies write. Only that header is read, since the client can
+    /// send the others.
+    pub fn header(mut self, header: ForwardingHeader) -> TrustedProxies {
+        self.header = header;
+        self
+    }
+
+    pub fn push(&mut self, network: IpNetwork) {
+        self.networks.push(network);
+    }
+
+    /// Returns true if `ip` is a trusted proxy
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        self.networks.iter().any(|network| network.contains(ip))
+    }
+
+    /// Returns the address of the client of a request received from `peer`, the address of
+    /// the other end of the connection:
+    /// - if `peer` is not a trusted proxy, `peer` itself, whatever the headers say
+    /// - otherwise, the last address of the forwarding header that is not a trusted proxy, or
+    ///   the first address if they all are
+    ///
+    /// IPv4-mapped IPv6 addresses are returned as IPv4 addresses. Returns an error when the
+    /// client cannot be told: a trusted proxy did not send the header, or the header has an
+    /// address that cannot be parsed (or is hidden, like `for=unknown`) after the client's.
+    pub fn client_ip(
+        &self,
+        peer: IpAddr,
+        headers: &ProxyHeaders<'_>,
+    ) -> Result<IpAddr, Box<dyn Error>> {
+        let peer = peer.to_canonical();
+        if !self.contains(&peer) {
+            return Ok(peer);
+        }
+        let name = self.header.name();
+        let hops = headers.hops(self.header)?;
+        let mut client = None;
+        for hop in hops.iter().rev() {
+            match hop {
+                Some(ip) if self.contains(ip) => client = Some(*ip),
+                Some(ip) => return Ok(*ip),
+                None => {
+                    return Err(format!("{} header has an invalid or hidden address", name).into())
+                }
+            }
+        }
+        client.ok_or_else(|| format!("request from proxy {} has no {} header", peer, name).into())
+    }
+}
+
+impl FromIterator<IpNetwork> for TrustedProxies {
+    fn from_iter<I: IntoIterator<Item = IpNetwork>>(networks: I) -> TrustedProxies {
+        TrustedProxies::new(networks)
+    }
+}
+
+/// Parses an address, optionally with a port: `192.0.2.1`, `192.0.2.1:80`, `2001:db8::1` or
+/// `[2001:db8::1]:80`
+pub fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim();
+    let ip = match node.parse::<IpAddr>() {
+        Ok(ip) => ip,
+        Err(_) if node.starts_with('[') && node.ends_with(']') => node[1..node.len() - 1]
+            .parse::<std::net::Ipv6Addr>()
+            .ok()?
+            .into(),
+        Err(_) => node.parse::<SocketAddr>().ok()?.ip(),
+    };
+    Some(ip.to_canonical())
+}
+
+/// Returns the `for` addresses of the elements of a `Forwarded` header line. Elements
+/// without a `for` parameter, or with an obfuscated or unknown one, are None.
+fn forwarded_for(value: &str) -> Result<Vec<Option<IpAddr>>, Box<dyn Error>> {
+    let malformed = || format!("malformed Forwarded header: {}", value);
+    let mut hops = Vec::new();
+    for element in split_unquoted(value, ',').ok_or_else(malformed)? {
+        let mut node = None;
+        for pair in split_unquoted(element, ';').ok_or_else(malformed)? {
+            if let Some((name, token)) = pair.split_once('=') {
+                if name.trim().eq_ignore_ascii_case("for") {
+                    node = parse_node(&unquote(token.trim()).ok_or_else(malformed)?);
+                }
+            }
+        }
+        hops.push(node);
+    }
+    Ok(hops)
+}
+
+/// Splits `value` on `separator`, except within quoted strings. Returns None if a quoted
+/// string is not closed.
+fn split_unquoted(value: &str, separator: char) -> Option<Vec<&str>> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    let mut chars = value.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => quoted = !quoted,
+            '\\' if quoted => {
+                chars.next();
+            }
+            c if c == separator && !quoted => {
+                parts.push(&value[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&value[start..]);
+    (!quoted).then_some(parts)
+}
+
+/// Returns the content of a quoted string, without its backslash escapes, or `token` itself
+/// if it is not quoted
+fn unquote(token: &str) -> Option<String> {
+    let Some(quoted) = token.strip_prefix('"') else {
+        return Some(token.to_owned());
+    };
+    let mut content = String::new();
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return chars.as_str().is_empty().then_some(content),
+            '\\' => content.push(chars.next()?),
+            c => content.push(c),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn networks() -> Result<(), Box<dyn Error>> {
+        let network: IpNetwork = "10.1.2.3/8".parse()?;
+        assert_eq!(network.to_string(), "10.0.0.0/8");
+        assert!(network.contains(&ip("10.255.0.1")));
+        assert!(network.contains(&ip("::ffff:10.0.0.1")));
+        assert!(!network.contains(&ip("11.0.0.1")));
+        assert!(!network.contains(&ip("::a00:1")));
+
+        let network: IpNetwork = "2001:db8::/32".parse()?;
+        assert!(network.contains(&ip("2001:db8:ffff::1")));
+        assert!(!network.contains(&ip("2001:db9::1")));
+
+        let single: IpNetwork = "192.0.2.1".parse()?;
+        assert_eq!(single.prefix_len(), 32);
+        assert!(single.contains(&ip("192.0.2.1")) && !single.contains(&ip("192.0.2.2")));
+        assert!("0.0.0.0/0".parse::<IpNetwork>()?.contains(&ip("192.0.2.1")));
+
+        for invalid in ["10.0.0.0/33", "::/129", "10.0.0.0/", "10.0.0/8", "fd00::/x"] {
+            assert!(invalid.parse::<IpNetwork>().is_err(), "{}", invalid);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn nodes() {
+        for (node, expected) in [
+            ("192.0.2.1", "192.0.2.1"),
+            (" 192.0.2.1:8080 ", "192.0.2.1"),
+            ("2001:db8::1", "2001:db8::1"),
+            ("[2001:db8::1]", "2001:db8::1"),
+            ("[2001:db8::1]:443", "2001:db8::1"),
+            ("::ffff:192.0.2.1", "192.0.2.1"),
+        ] {
+            assert_eq!(parse_node(node), Some(ip(expected)), "{}", node);
+        }
+        for node in [
+            "",
+            "unknown",
+            "_hidden",
+            "[192.0.2.1]",
+            "[2001:db8::1",
+            "192.0.2.1:x",
+        ] {
+            assert_eq!(parse_node(node), None, "{}", node);
+        }
+    }
+
+    #[test]
+    fn forwarded_header() -> Result<(), Box<dyn Error>> {
+        assert_eq!(
+            forwarded_for(r#"for=192.0.2.1;proto=https, For="[2001:db8::1]:443";by=_lb"#)?,
+            [Some(ip("192.0.2.1")), Some(ip("2001:db8::1"))]
+        );
+        // separators and escapes within quoted strings
+        assert_eq!(
+            forwarded_for(r#"host="a,b;c";for="192.0.2.\1", proto=http, for=unknown"#)?,
+            [Some(ip("192.0.2.1")), None, None]
+        );
+        assert!(forwarded_for(r#"for="192.0.2.1"#).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn client_ips() -> Result<(), Box<dyn Error>> {
+        let proxies = TrustedProxies::new(["10.0.0.0/8".parse()?, "fd00::/8".parse()?]);
+        let client = |peer: &str, headers: ProxyHeaders| proxies.client_ip(ip(peer), &headers);
+
+        // the headers of peers that are not trusted are ignored
+        let spoofed = ProxyHeaders::new().x_forwarded_for("192.0.2.1");
+        assert_eq!(client("203.0.113.9", spoofed.clone())?, ip("203.0.113.9"));
+        assert_eq!(client("::ffff:203.0.113.9", spoofed)?, ip("203.0.113.9"));
+
+        // the last address that is not a trusted proxy, over every header line
+        let headers = ProxyHeaders::new()
+            .x_forwarded_for("garbage, 192.0.2.1")
+            .x_forwarded_for("[2001:db8::1]:443 , 10.1.1.1");
+        assert_eq!(client("10.0.0.1", headers)?, ip("2001:db8::1"));
+        let headers = ProxyHeaders::new().x_forwarded_for("10.2.2.2, fd00::1");
+        assert_eq!(client("::ffff:10.0.0.1", headers)?, ip("10.2.2.2"));
+
+        // other headers are not read
+        let headers = ProxyHeaders::new()
+            .forwarded("for=192.0.2.1")
+            .x_real_ip("192.0.2.1");
+        assert!(client("10.0.0.1", headers).is_err());
+        // hops that cannot be trusted
+        let headers = ProxyHeaders::new().x_forwarded_for("192.0.2.1, unknown, 10.1.1.1");
+        assert!(client("10.0.0.1", headers).is_err());
+        let headers = ProxyHeaders::new().x_forwarded_for("192.0.2.1,");
+        assert!(client("10.0.0.1", headers).is_err());
+
+        let proxies = proxies.header(ForwardingHeader::Forwarded);
+        let client = |peer: &str, headers: ProxyHeaders| proxies.client_ip(ip(peer), &headers);
+        let headers = ProxyHeaders::new()
+            .forwarded(r#"for=192.0.2.1, for="[2001:db8::1]:443""#)
+            .forwarded("for=10.1.1.1;proto=https")
+            .x_forwarded_for("198.51.100.7");
+        assert_eq!(client("10.0.0.1", headers)?, ip("2001:db8::1"));
+        let headers = ProxyHeaders::new().forwarded("for=192.0.2.1, for=_hidden");
+        assert!(client("10.0.0.1", headers).is_err());
+
+        let proxies = proxies.header(ForwardingHeader::XRealIp);
+        let client = |peer: &str, headers: ProxyHeaders| proxies.client_ip(ip(peer), &headers);
+        let headers = ProxyHeaders::new().x_real_ip("192.0.2.1");
+        assert_eq!(client("10.0.0.1", headers)?, ip("192.0.2.1"));
+        let headers = ProxyHeaders::new()
+            .x_real_ip("192.0.2.1")
+            .x_real_ip("192.0.2.2");
+        assert!(client("10.0.0.1", headers).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,788 @@
+// Copyright 2023 IPQualityScore LLC
+//! C bindings, built into the `cdylib` and `staticlib` libraries of the crate with the `ffi`
+//! feature. The header is `include/ipqs_db_reader.h`, generated from this module by
+//! `cbindgen --config cbindgen.toml --output include/ipqs_db_reader.h`.
+//!
+//! Functions that can fail return an [IpqsStatus], whose positive values are the EIDs of the
+//! errors of the Rust API; [ipqs_last_error] describes the last failure of the calling thread.
+//! Lookups of addresses without a record fail with `IPQS_STATUS_NO_RECORD_BEFORE` (EID 9) or
+//! `IPQS_STATUS_NOT_IN_FILE` (EID 10).
+//!
+//! Handles returned through an `out` pointer are owned by the caller, and freed with
+//! [ipqs_reader_close] or [ipqs_record_free]. A reader must not be used by two threads at the
+//! same time, [ipqs_reader_clone] opens another one sharing the same index and strings.
+//! Strings returned by the accessors live as long as the handle they were read from.
+//!
+//! Every pointer given to these functions must be null or valid for its type, and strings
+//! must be terminated by a nul byte. Null handles and `out` pointers are reported as
+//! `IPQS_STATUS_INVALID_ARGUMENT`, or as a missing value by the record accessors.
+#![allow(clippy::missing_safety_doc)]
+
+use std::cell::RefCell;
+use std::error::Error;
+use std::ffi::{c_char, CStr, CString};
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::ptr;
+
+use crate::{FileReader, Record, Strictness};
+
+/// Result of the functions that can fail: 0 on success, the EID of the error for invalid
+/// files and failed lookups, and a negative value for other failures
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IpqsStatus {
+    Ok = 0,
+    InvalidFirstByte = 1,
+    InvalidVersion = 2,
+    InvalidHeaderBytes = 3,
+    NoColumnData = 4,
+    InvalidColumnData = 5,
+    InvalidRecordBytes = 6,
+    BadBinaryTree = 7,
+    TreeTooSmall = 8,
+    /// no record at or before the address
+    NoRecordBefore = 9,
+    /// the address is not in the blacklist file
+    NotInFile = 10,
+    InvalidStringData = 13,
+    /// a null pointer, or a string that is not UTF-8
+    InvalidArgument = -1,
+    /// not an IPv4 or IPv6 address, or not of the IP version of the file
+    InvalidAddress = -2,
+    /// the file could not be opened or read
+    Io = -3,
+    /// a panic in the library, which is a bug
+    Panic = -4,
+    Other = -5,
+}
+
+impl IpqsStatus {
+    fn from_error(error: &(dyn Error + 'static)) -> IpqsStatus {
+        if let Some(error) = error.downcast_ref::<ArgumentError>() {
+            return error.status;
+        }
+        if let Some(error) = error.downcast_ref::<ipqs_db_core::Error>() {
+            return IpqsStatus::from_core_error(*error);
+        }
+        if error.is::<io::Error>() {
+            return IpqsStatus::Io;
+        }
+        // errors that were turned into a message on the way, which still ends with the EID
+        let eid = error
+            .to_string()
+            .rsplit_once("(EID ")
+            .and_then(|(_, eid)| eid.strip_suffix(')'))
+            .and_then(|eid| eid.parse::<i32>().ok());
+        match eid {
+            Some(1) => IpqsStatus::InvalidFirstByte,
+            Some(2) => IpqsStatus::InvalidVersion,
+            Some(3) => IpqsStatus::InvalidHeaderBytes,
+            Some(4) => IpqsStatus::NoColumnData,
+            Some(5) => IpqsStatus::InvalidColumnData,
+            Some(6) => IpqsStatus::InvalidRecordBytes,
+            Some(7) => IpqsStatus::BadBinaryTree,
+            Some(8) => IpqsStatus::TreeTooSmall,
+            Some(9) => IpqsStatus::NoRecordBefore,
+            Some(10) => IpqsStatus::NotInFile,
+            Some(13) => IpqsStatus::InvalidStringData,
+            _ => IpqsStatus::Other,
+        }
+    }
+
+    fn from_core_error(error: ipqs_db_core::Error) -> IpqsStatus {
+        use ipqs_db_core::Error;
+        match error {
+            Error::InvalidFirstByte => IpqsStatus::InvalidFirstByte,
+            Error::InvalidVersion => IpqsStatus::InvalidVersion,
+            Error::InvalidHeader => IpqsStatus::InvalidHeaderBytes,
+            Error::NoColumns => IpqsStatus::NoColumnData,
+            Error::InvalidColumns => IpqsStatus::InvalidColumnData,
+            Error::InvalidRecordBytes => IpqsStatus::InvalidRecordBytes,
+            Error::InvalidTree => IpqsStatus::BadBinaryTree,
+            Error::TreeTooSmall => IpqsStatus::TreeTooSmall,
+            Error::NoRecordBefore => IpqsStatus::NoRecordBefore,
+            Error::NotInFile => IpqsStatus::NotInFile,
+            Error::InvalidString => IpqsStatus::InvalidStringData,
+            Error::Ipv4InIpv6File | Error::Ipv6InIpv4File => IpqsStatus::InvalidAddress,
+            Error::UnexpectedEnd | Error::VarintOverflow => IpqsStatus::Other,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ArgumentError {
+    status: IpqsStatus,
+    message: &'static str,
+}
+
+impl fmt::Display for ArgumentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message)
+    }
+}
+
+impl Error for ArgumentError {}
+
+fn invalid_argument(message: &'static str) -> Box<dyn Error> {
+    Box::new(ArgumentError {
+        status: IpqsStatus::InvalidArgument,
+        message,
+    })
+}
+
+fn invalid_address() -> Box<dyn Error> {
+    Box::new(ArgumentError {
+        status: IpqsStatus::InvalidAddress,
+        message: "invalid IP address",
+    })
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = message);
+}
+
+/// Runs `call`, turning its errors and panics into a status
+fn status(call: impl FnOnce() -> Result<(), Box<dyn Error>>) -> IpqsStatus {
+    match panic::catch_unwind(AssertUnwindSafe(call)) {
+        Ok(Ok(())) => IpqsStatus::Ok,
+        Ok(Err(error)) => {
+            set_last_error(error.to_string());
+            IpqsStatus::from_error(error.as_ref())
+        }
+        Err(_) => {
+            set_last_error("panic in ipqs_db_reader".to_owned());
+            IpqsStatus::Panic
+        }
+    }
+}
+
+/// Moves `value` into `out` as a handle owned by the caller
+unsafe fn hand_over<T>(out: *mut *mut T, value: T) {
+    *out = Box::into_raw(Box::new(value));
+}
+
+unsafe fn path<'a>(path: *const c_char) -> Result<&'a Path, Box<dyn Error>> {
+    if path.is_null() {
+        return Err(invalid_argument("path is null"));
+    }
+    CStr::from_ptr(path)
+        .to_str()
+        .map(Path::new)
+        .map_err(|_| invalid_argument("path is not valid UTF-8"))
+}
+
+/// An open database file
+pub struct IpqsReader {
+    reader: FileReader,
+    columns: Vec<CString>,
+}
+
+impl IpqsReader {
+    fn new(reader: FileReader) -> IpqsReader {
+        let columns = reader
+            .metadata()
+            .columns
+            .into_iter()
+            .map(c_string)
+            .collect();
+        IpqsReader { reader, columns }
+    }
+}
+
+/// The record of an address, with its fields read by the `ipqs_record_` functions
+pub struct IpqsRecord {
+    record: Record,
+    connection_type: CString,
+    abuse_velocity: CString,
+    country: Option<CString>,
+    city: Option<CString>,
+    region: Option<CString>,
+    isp: Option<CString>,
+    organization: Option<CString>,
+    timezone: Option<CString>,
+}
+
+impl IpqsRecord {
+    fn new(record: Record) -> IpqsRecord {
+        let owned = |value: Option<&str>| value.map(|value| c_string(value.to_owned()));
+        IpqsRecord {
+            connection_type: c_string(record.connection_type().to_owned()),
+            abuse_velocity: c_string(record.abuse_velocity().to_owned()),
+            country: owned(record.country()),
+            city: owned(record.city()),
+            region: owned(record.region()),
+            isp: owned(record.isp()),
+            organization: owned(record.organization()),
+            timezone: owned(record.timezone()),
+            record,
+        }
+    }
+}
+
+fn c_string(value: String) -> CString {
+    CString::new(value.replace('\0', "")).unwrap_or_default()
+}
+
+/// What the header of a database file describes, see [ipqs_reader_column_name] for the columns
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct IpqsMetadata {
+    pub is_ipv6: bool,
+    pub is_blacklist: bool,
+    pub binary_data: bool,
+    pub column_count: usize,
+    pub record_bytes: usize,
+    pub tree_bytes: u64,
+}
+
+/// Returns the message of the last failure on the calling thread, empty if there was none.
+/// The string is valid until the next call that fails on this thread.
+#[no_mangle]
+pub extern "C" fn ipqs_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| last_error.borrow().as_ptr())
+}
+
+/// Opens the file at `path`, reading records from the file as they are looked up
+#[no_mangle]
+pub unsafe extern "C" fn ipqs_reader_open(
+    path: *const c_char,
+    out: *mut *mut IpqsReader,
+) -> IpqsStatus {
+    status(|| {
+        if out.is_null() {
+            return Err(invalid_argument("out is null"));
+        }
+        let reader = FileReader::open(self::path(path)?)?;
+        hand_over(out, IpqsReader::new(reader));
+        Ok(())
+    })
+}
+
+/// Reads the whole file at `path` into memory and builds its index, for the fastest lookups
+#[no_mangle]
+pub unsafe extern "C" fn ipqs_reader_open_in_memory(
+    path: *const c_char,
+    out: *mut *mut IpqsReader,
+) -> IpqsStatus {
+    status(|| {
+        if out.is_null() {
+            return Err(invalid_argument("out is null"));
+        }
+        let mut reader = FileReader::open_in_memory(self::path(path)?)?;
+        reader.build_index()?;
+        hand_over(out, IpqsReader::new(reader));
+        Ok(())
+    })
+}
+
+/// Copies the `length` bytes of a database file at `bytes`, e.g. one embedded in the program
+#[no_mangle]
+pub unsafe extern "C" fn ipqs_reader_from_bytes(
+    bytes: *const u8,
+    length: usize,
+    out: *mut *mut IpqsReader,
+) -> IpqsStatus {
+    status(|| {
+        if bytes.is_null() || out.is_null() {
+            return Err(invalid_argument("bytes or out is null"));
+        }
+        let bytes = std::slice::from_raw_parts(bytes, length).to_vec();
+        let reader = FileReader::from_bytes(bytes)?;
+        hand_over(out, IpqsReader::new(reader));
+        Ok(())
+    })
+}
+
+/// Opens another reader of the same file, for another thread, sharing the index and strings
+/// already loaded by `reader`
+#[no_mangle]
+pub unsafe extern "C" fn ipqs_reader_clone(
+    reader: *const IpqsReader,
+    out: *mut *mut IpqsReader,
+) -> IpqsStatus {
+    status(|| {
+        let reader = reader
+            .as_ref()
+            .ok_or_else(|| invalid_argument("reader is null"))?;
+        if out.is_null() {
+            return Err(invalid_argument("out is null"));
+        }
+        let clone = reader.reader.try_clone()?;
+        hand_over(
+            out,
+            IpqsReader {
+                reader: clone,
+                columns: reader.columns.clone(),
+            },
+        );
+        Ok(())
+    })
+}
+
+/// Closes a reader opened by one of the `ipqs_reader_` functions. Records looked up with it
+/// stay valid.
+#[no_mangle]
+pub unsafe extern "C" fn ipqs_reader_close(reader: *mut IpqsReader) {
+    if !reader.is_null() {
+        drop(Box::from_raw(reader));
+    }
+}
+
+/// Fills `out` with the details of the file given in its header
+#[no_mangle]
+pub unsafe extern "C" fn ipqs_reader_metadata(
+    reader: *const IpqsReader,
+    out: *mut IpqsMetadata,
+) -> IpqsStatus {
+    status(|| {
+        let (reader, out) = match (reader.as_ref(), out.as_mut()) {
+            (Some(reader), Some(out)) => (reader, out),
+            _ => return Err(invalid_argument("reader or out is null")),
+        };
+        let metadata = reader.reader.metadata();
+        *out = IpqsMetadata {
+            is_ipv6: metadata.is_ipv6,
+            is_blacklist: metadata.is_blacklist,
+            binary_data: metadata.binary_data,
+            column_count: metadata.columns.len(),
+            record_bytes: metadata.record_bytes,
+            tree_bytes: metadata.tree_bytes,
+        };
+        Ok(())
+    })
+}
+
+/// Returns the name of the column at `index`, in file order, or null past the last column
+#[no_mangle]
+pub unsafe extern "C" fn ipqs_reader_column_name(
+    reader: *const IpqsReader,
+    index: usize,
+) -> *const c_char {
+    reader
+        .as_ref()
+        .and_then(|reader| reader.columns.get(index))
+        .map_or(ptr::null(), |name| name.as_ptr())
+}
+
+/// Looks up an address written as text, e.g. `"8.8.8.8"` or `"2001:4860:4860::8888"`
+#[no_mangle]
+pub unsafe extern "C" fn ipqs_reader_lookup(
+    reader: *mut IpqsReader,
+    ip: *const c_char,
+    out: *mut *mut IpqsRecord,
+) -> IpqsStatus {
+    status(|| {
+        if ip.is_null() {
+            return Err(invalid_argument("ip is null"));
+        }
+        let ip: IpAddr = CStr::from_ptr(ip)
+            .to_str()
+            .ok()
+            .and_then(|ip| ip.parse().ok())
+            .ok_or_else(invalid_address)?;
+        lookup(reader, &ip, out)
+    })
+}
+
+/// Looks up an address given as 4 (IPv4) or 16 (IPv6) bytes in network order, such as the
+/// `sin_addr` of a `sockaddr_in` or the `sin6_addr` of a `sockaddr_in6`
+#[no_mangle]
+pub unsafe extern "C" fn ipqs_reader_lookup_binary(
+    reader: *mut IpqsReader,
+    address: *const u8,
+    length: usize,
+    out: *mut *mut IpqsRecord,
+) -> IpqsStatus {
+    status(|| {
+        if address.is_null() {
+            return Err(invalid_argument("address is null"));
+        }
+        let ip = match std::slice::from_raw_parts(address, length) {
+            &[a, b, c, d] => IpAddr::V4(Ipv4Addr::new(a, b, c, d)),
+            bytes if bytes.len() == 16 => {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(bytes);
+                IpAddr::V6(Ipv6Addr::from(octets))
+            }
+            _ => return Err(invalid_address()),
+        };
+        lookup(reader, &ip, out)
+    })
+}
+
+unsafe fn lookup(
+    reader: *mut IpqsReader,
+    ip: &IpAddr,
+    out: *mut *mut IpqsRecord,
+) -> Result<(), Box<dyn Error>> {
+    let reader = reader
+        .as_mut()
+        .ok_or_else(|| invalid_argument("reader is null"))?;
+    if out.is_null() {
+        return Err(invalid_argument("out is null"));
+    }
+    if ip.is_ipv6() != reader.reader.is_ipv6() {
+        return Err(Box::new(ArgumentError {
+            status: IpqsStatus::InvalidAddress,
+            message: "the IP version of the address does not match the file",
+        }));
+    }
+    let record = reader.reader.fetch(ip)?;
+    hand_over(out, IpqsRecord::new(record));
+    Ok(())
+}
+
+/// Frees a record returned by a lookup
+#[no_mangle]
+pub unsafe extern "C" fn ipqs_record_free(record: *mut IpqsRecord) {
+    if !record.is_null() {
+        drop(Box::from_raw(record));
+    }
+}
+
+/// Writes the field read by `get` to `out`, returning false if the record does not have it
+unsafe fn optional<T>(
+    record: *const IpqsRecord,
+    out: *mut T,
+    get: impl FnOnce(&Record) -> Option<T>,
+) -> bool {
+    match (
+        record.as_ref().and_then(|record| get(&record.record)),
+        out.as_mut(),
+    ) {
+        (Some(value), Some(out)) => {
+            *out = value;
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Writes whether the address is a proxy to `out`, returning false if the file does not have
+/// the field. The other accessors of optional fields work the same way.
+#[no_mangle]
+pub unsafe extern "C" fn ipqs_record_is_proxy(record: *const IpqsRecord, out: *mut bool) -> bool {
+    optional(record, out, Record::is_proxy)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ipqs_record_is_vpn(record: *const IpqsRecord, out: *mut bool) -> bool {
+    optional(record, out, Record::is_vpn)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ipqs_record_is_tor(record: *const IpqsRecord, out: *mut bool) -> bool {
+    optional(record, out, Record::is_tor)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ipqs_record_is_crawler(record: *const IpqsRecord, out: *mut bool) -> bool {
+    optional(record, out, Record::is_crawler)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ipqs_record_is_bot(record: *const IpqsRecord, out: *mut bool) -> bool {
+    optional(record, out, Record::is_bot)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ipqs_record_recent_abuse(
+    record: *const IpqsRecord,
+    out: *mut bool,
+) -> bool {
+    optional(record, out, Record::recent_abuse)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ipqs_record_is_blacklisted(
+    record: *const IpqsRecord,
+    out: *mut bool,
+) -> bool {
+    optional(record, out, Record::is_blacklisted)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ipqs_record_is_private(record: *const IpqsRecord, out: *mut bool) -> bool {
+    optional(record, out, Record::is_private)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ipqs_record_is_mobile(record: *const IpqsRecord, out: *mut bool) -> bool {
+    optional(record, out, Record::is_mobile)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ipqs_record_has_open_ports(
+    record: *const IpqsRecord,
+    out: *mut bool,
+) -> bool {
+    optional(record, out, Record::has_open_ports)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ipqs_record_is_hosting_provider(
+    record: *const IpqsRecord,
+    out: *mut bool,
+) -> bool {
+    optional(record, out, Record::is_hosting_provider)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ipqs_record_active_vpn(record: *const IpqsRecord, out: *mut bool) -> bool {
+    optional(record, out, Record::active_vpn)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ipqs_record_active_tor(record: *const IpqsRecord, out: *mut bool) -> bool {
+    optional(record, out, Record::active_tor)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ipqs_record_public_access_point(
+    record: *const IpqsRecord,
+    out: *mut bool,
+) -> bool {
+    optional(record, out, Record::public_access_point)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ipqs_record_asn(record: *const IpqsRecord, out: *mut u64) -> bool {
+    optional(record, out, Record::asn)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ipqs_record_latitude(record: *const IpqsRecord, out: *mut f32) -> bool {
+    optional(record, out, Record::latitude)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ipqs_record_longitude(record: *const IpqsRecord, out: *mut f32) -> bool {
+    optional(record, out, Record::longitude)
+}
+
+/// Writes the fraud score for `strictness` (0 to 3) to `out` and returns true if the record
+/// has one
+#[no_mangle]
+pub unsafe extern "C" fn ipqs_record_fraud_score(
+    record: *const IpqsRecord,
+    strictness: u32,
+    out: *mut u32,
+) -> bool {
+    let strictness = match strictness {
+        0 => Strictness::Zero,
+        1 => Strictness::One,
+        2 => Strictness::Two,
+        3 => Strictness::Three,
+        _ => return false,
+    };
+    optional(record, out, |record| record.fraud_score(strictness))
+}
+
+unsafe fn string(
+    record: *const IpqsRecord,
+    get: impl FnOnce(&IpqsRecord) -> Option<&CString>,
+) -> *const c_char {
+    record
+        .as_ref()
+        .and_then(get)
+        .map_or(ptr::null(), |value| value.as_ptr())
+}
+
+/// Returns the two letter country code, or null if the file does not have it. The other
+/// string accessors work the same way.
+#[no_mangle]
+pub unsafe extern "C" fn ipqs_record_country(record: *const IpqsRecord) -> *const c_char {
+    string(record, |record| record.country.as_ref())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ipqs_record_city(record: *const IpqsRecord) -> *const c_char {
+    string(record, |record| record.city.as_ref())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ipqs_record_region(record: *const IpqsRecord) -> *const c_char {
+    string(record, |record| record.region.as_ref())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ipqs_record_isp(record: *const IpqsRecord) -> *const c_char {
+    string(record, |record| record.isp.as_ref())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ipqs_record_organization(record: *const IpqsRecord) -> *const c_char {
+    string(record, |record| record.organization.as_ref())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ipqs_record_timezone(record: *const IpqsRecord) -> *const c_char {
+    string(record, |record| record.timezone.as_ref())
+}
+
+/// Returns the connection type, e.g. `"Residential"`, `"Unknown"` if the file does not have it
+#[no_mangle]
+pub unsafe extern "C" fn ipqs_record_connection_type(record: *const IpqsRecord) -> *const c_char {
+    string(record, |record| Some(&record.connection_type))
+}
+
+/// Returns the abuse velocity, e.g. `"high"`, `"none"` if the file does not have it
+#[no_mangle]
+pub unsafe extern "C" fn ipqs_record_abuse_velocity(record: *const IpqsRecord) -> *const c_char {
+    string(record, |record| Some(&record.abuse_velocity))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_reader::test_database::{TestDatabase, TestRecord};
+
+    fn open(database: &TestDatabase) -> *mut IpqsReader {
+        let bytes = database.bytes();
+        let mut reader = ptr::null_mut();
+        let status = unsafe { ipqs_reader_from_bytes(bytes.as_ptr(), bytes.len(), &mut reader) };
+        assert_eq!(status, IpqsStatus::Ok);
+        reader
+    }
+
+    fn last_error() -> String {
+        unsafe { CStr::from_ptr(ipqs_last_error()) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn lookups() {
+        let mut test_record = TestRecord::new(15169, "US");
+        test_record.flags[0] = crate::binary_option::IS_PROXY;
+        test_record.fraud_score = 85;
+        let reader =
+            open(&TestDatabase::ipv4().insert("10.0.0.0".parse().unwrap(), 8, test_record));
+
+        unsafe {
+            let mut record = ptr::null_mut();
+            let ip = CString::new("10.1.2.3").unwrap();
+            assert_eq!(
+                ipqs_reader_lookup(reader, ip.as_ptr(), &mut record),
+                IpqsStatus::Ok
+            );
+
+            let (mut asn, mut is_proxy, mut score) = (0, false, 0);
+            assert!(ipqs_record_asn(record, &mut asn));
+            assert_eq!(asn, 15169);
+            assert!(ipqs_record_is_proxy(record, &mut is_proxy));
+            assert!(is_proxy);
+            assert!(ipqs_record_fraud_score(record, 0, &mut score));
+            assert_eq!(score, 85);
+            assert!(!ipqs_record_fraud_score(record, 1, &mut score));
+            assert!(!ipqs_record_latitude(record, &mut 0.0));
+            assert_eq!(
+                CStr::from_ptr(ipqs_record_country(record)).to_str(),
+                Ok("US")
+            );
+            assert!(ipqs_record_city(record).is_null());
+            assert_eq!(
+                CStr::from_ptr(ipqs_record_connection_type(record)).to_str(),
+                Ok("Unknown")
+            );
+            ipqs_record_free(record);
+
+            let mut record = ptr::null_mut();
+            assert_eq!(
+                ipqs_reader_lookup_binary(reader, [10, 0, 0, 1].as_ptr(), 4, &mut record),
+                IpqsStatus::Ok
+            );
+            ipqs_record_free(record);
+
+            let mut record = ptr::null_mut();
+            assert_eq!(
+                ipqs_reader_lookup_binary(reader, [9, 0, 0, 1].as_ptr(), 4, &mut record),
+                IpqsStatus::NoRecordBefore
+            );
+            assert!(record.is_null());
+            assert!(last_error().contains("EID 9"));
+
+            let ip = CString::new("2001:db8::1").unwrap();
+            assert_eq!(
+                ipqs_reader_lookup(reader, ip.as_ptr(), &mut record),
+                IpqsStatus::InvalidAddress
+            );
+            let ip = CString::new("nonsense").unwrap();
+            assert_eq!(
+                ipqs_reader_lookup(reader, ip.as_ptr(), &mut record),
+                IpqsStatus::InvalidAddress
+            );
+            let ip = CString::new("10.0.0.1").unwrap();
+            assert_eq!(
+                ipqs_reader_lookup(ptr::null_mut(), ip.as_ptr(), &mut record),
+                IpqsStatus::InvalidArgument
+            );
+            ipqs_reader_close(reader);
+        }
+    }
+
+    #[test]
+    fn metadata_and_errors() {
+        let reader = open(&TestDatabase::ipv6().blacklist());
+        unsafe {
+            let mut metadata = IpqsMetadata::default();
+            assert_eq!(ipqs_reader_metadata(reader, &mut metadata), IpqsStatus::Ok);
+            assert!(metadata.is_ipv6 && metadata.is_blacklist);
+            assert_eq!(metadata.column_count, 3);
+            assert_eq!(
+                CStr::from_ptr(ipqs_reader_column_name(reader, 0)).to_str(),
+                Ok("ASN")
+            );
+            assert!(ipqs_reader_column_name(reader, 3).is_null());
+
+            let mut clone = ptr::null_mut();
+            assert_eq!(ipqs_reader_clone(reader, &mut clone), IpqsStatus::Ok);
+            let mut record = ptr::null_mut();
+            assert_eq!(
+                ipqs_reader_lookup_binary(clone, [0x20; 16].as_ptr(), 16, &mut record),
+                IpqsStatus::NotInFile
+            );
+            ipqs_reader_close(clone);
+            ipqs_reader_close(reader);
+
+            let mut reader = ptr::null_mut();
+            assert_eq!(
+                ipqs_reader_from_bytes(b"not a database".as_ptr(), 14, &mut reader),
+                IpqsStatus::InvalidVersion
+            );
+            let path = CString::new("/nonexistent.ipqs").unwrap();
+            assert_eq!(ipqs_reader_open(path.as_ptr(), &mut reader), IpqsStatus::Io);
+            assert!(reader.is_null());
+        }
+    }
+
+    #[test]
+    fn statuses_of_errors() {
+        let status = |error: Box<dyn Error>| IpqsStatus::from_error(error.as_ref());
+        assert_eq!(
+            status(ipqs_db_core::Error::InvalidTree.into()),
+            IpqsStatus::BadBinaryTree
+        );
+        assert_eq!(
+            status(ipqs_db_core::Error::Ipv6InIpv4File.into()),
+            IpqsStatus::InvalidAddress
+        );
+        assert_eq!(
+            status(ipqs_db_core::Error::UnexpectedEnd.into()),
+            IpqsStatus::Other
+        );
+        // only the message is left of errors formatted into another one
+        let error = format!("index: {}", ipqs_db_core::Error::NotInFile);
+        assert_eq!(status(error.into()), IpqsStatus::NotInFile);
+        assert_eq!(status("(EID 99)".into()), IpqsStatus::Other);
+    }
+}
@@ -1,44 +1,55 @@
 // Copyright IPQualityScore LLC 2023
-use std::collections::HashMap;
 use std::error::Error;
 use std::fs::File;
-use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::io::{self, BufReader, Read};
 use std::net::IpAddr;
 use std::path::Path;
+use std::sync::Arc;
 
-use crate::binary_option as flag;
-use crate::binary_option::BinaryOption;
 use crate::column::Column;
-use crate::utility;
-
-mod variable_length_int;
-
-pub mod record;
-
-const RUST_IPQS_READER_VERSION: u8 = 0x01;
+use ipqs_db_core::{tree, Header};
+
+use index::TreeIndex;
+pub use networks::Networks;
+use origin::Origin;
+#[cfg(feature = "rayon")]
+pub use parallel::ParallelResult;
+use source::Source;
+use string_cache::StringCache;
+pub use string_cache::DEFAULT_STRING_CACHE_CAPACITY;
+
+mod index;
+mod networks;
+mod origin;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod snapshot;
+mod source;
+mod string_cache;
+#[cfg(test)]
+pub(crate) mod test_database;
 
-const IPV4_MAP: u8 = 0b0000_0001;
-const IPV6_MAP: u8 = 0b0000_0010;
-const BLACKLIST_FILE: u8 = 0b0000_0100;
-// const RESERVED_SEVEN: u8 = 0b0000_1000;
-// const RESERVED_EIGHT: u8 = 0b0001_0000;
-// const RESERVED_NINE: u8 = 0b0010_0000;
-// const RESERVED_TEN: u8 = 0b0100_0000;
-const BINARY_DATA: u8 = 0b1000_0000;
+pub mod cached_reader;
+pub mod reader_pool;
+pub mod record_ref;
+pub use ipqs_db_core::{fields, metadata, record};
 
 /// The FileReader struct provides the interface for interacting with the flat file database.
 /// For details, please reference the official
 /// [IPQualityScore Flat File Database documentation](https://www.ipqualityscore.com/documentation/ip-reputation-database/overview)
 #[derive(Debug)]
 pub struct FileReader {
-    reader: BufReader<File>,
-    record_bytes: usize,
+    source: Source,
     tree_start: u64,
     tree_end: u64,
     is_v6: bool,
     binary_data: bool,
-    columns: Vec<Column>,
+    columns: Arc<[Column]>,
     is_blacklist: bool,
+    record_buffer: Vec<u8>,
+    strings: Arc<StringCache>,
+    index: Option<Arc<TreeIndex>>,
+    origin: Option<Origin>,
 }
 
 impl FileReader {
@@ -53,111 +64,54 @@ impl FileReader {
     /// ```
     pub fn open(file_path: &Path) -> Result<FileReader, Box<dyn Error>> {
         let file = File::open(file_path)?;
-        let mut reader = BufReader::new(file);
-
-        //---------------- METADATA BEGIN
-
-        // first 11 bytes reserved for file metadata
-        let mut header = [0; 11];
-        reader.read_exact(&mut header)?;
-
-        // first byte of header holds file option details
-        let binary_option = BinaryOption { data: header[0] };
-
-        let binary_data = binary_option.has(BINARY_DATA);
-
-        let is_v6 = binary_option.has(IPV6_MAP);
-
-        // file is only valid if IPv6 XOR IPv4
-        let is_valid = is_v6 ^ binary_option.has(IPV4_MAP);
-        if !is_valid {
-            return Err("invalid file format, invalid first byte (EID 1)".into());
-        }
-
-        let is_blacklist = binary_option.has(BLACKLIST_FILE);
-
-        // flat file db and library crate version must match
-        if header[1] != RUST_IPQS_READER_VERSION {
-            return Err("invalid file version (EID 2)".into());
-        }
-
-        // column pairs
-        // header bytes 2,3,4 give the length of the header in bytes
-        // the tree begins at the end of the header
-        // after the first 11 bytes, the remaining bytes in the header are column headers
-        // each header is 24 bytes long
-        let tree_start = variable_length_int::uvarint64(&header[2..5])?;
-        if tree_start == 0 {
-            return Err("invalid file format, invalid header bytes (EID 3)".into());
-        }
-        let header_size: usize = tree_start.try_into()?;
-        let column_bytes_length = header_size - 11;
-        if column_bytes_length == 0 {
-            return Err("file appears to be invalid, no column data found (EID 4)".into());
-        }
-        if column_bytes_length % 24 != 0 {
-            return Err("invalid column data, too many or too few bytes (EID 5)".into());
-        }
+        let origin = Origin::new(file_path, &file, false)?;
+        let mut reader = FileReader::new(Source::File(BufReader::new(file)))?;
+        reader.origin = Some(origin);
+        Ok(reader)
+    }
 
-        let record_bytes: usize = variable_length_int::uvarint64(&header[5..7])?.try_into()?;
-        if record_bytes == 0 {
-            return Err("invalid file format, invalid record bytes (EID 6)".into());
-        }
+    /// Reads the whole file at `Path` into memory and returns a FileReader interface.
+    /// Lookups never touch the file again, and [FileReader::fetch_ref] can be used to
+    /// borrow records without copying them.
+    pub fn open_in_memory(file_path: &Path) -> Result<FileReader, Box<dyn Error>> {
+        let mut file = File::open(file_path)?;
+        let origin = Origin::new(file_path, &file, true)?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        let mut reader = FileReader::from_bytes(bytes)?;
+        reader.origin = Some(origin);
+        Ok(reader)
+    }
 
-        // total bytes - should match file size in bytes
-        //let total_bytes = utility::four_byte_int(&header[7..11]);
-
-        //---------------- METADATA END
-
-        // consume column headers
-        let number_of_columns = column_bytes_length / 24;
-        let mut column_bytes: Vec<u8> = vec![0; column_bytes_length];
-        reader.read_exact(&mut column_bytes)?;
-        let mut columns = Vec::new();
-        // insert column name + record type pairs into column vector
-        for column in 0..number_of_columns {
-            // first 23 bytes of column header are 0-padded character strings
-            let b = column * 24;
-            let e = (column + 1) * 24 - 1;
-            // interpret the slice of bytes from 0-23 as UTF-8, trim the end, and take ownership
-            let name = std::str::from_utf8(&column_bytes[b..e])?
-                .trim_end_matches(char::from(0x00))
-                .to_owned();
-            // 24th byte is record type
-            let record_type = column_bytes[e];
-            columns.push(Column {
-                name,
-                record_type: BinaryOption { data: record_type },
-                value: Default::default(), // empty String
-            });
-        }
+    /// Returns a FileReader interface for a database that is already in memory, such as a
+    /// `Vec<u8>` or a memory-mapped file
+    pub fn from_bytes<B>(bytes: B) -> Result<FileReader, Box<dyn Error>>
+    where
+        B: AsRef<[u8]> + Send + Sync + 'static,
+    {
+        FileReader::new(Source::Memory(Arc::new(bytes)))
+    }
 
-        // Tree Metadata
-        let mut tree_header: Vec<u8> = vec![0; 5];
-        reader.read_exact(&mut tree_header)?;
-        let tree_type = BinaryOption {
-            data: tree_header[0],
-        };
-        if !tree_type.has(flag::TREE_DATA) {
-            return Err("file does not appear to be valid, bad binary tree (EID 7)".into());
-        }
-        let total_tree = utility::four_byte_int(&tree_header[1..5]);
-        if total_tree == 0 {
-            return Err("File does not appear to be valid, tree size is too small (EID 8)".into());
-        }
-        let tree_end: u64 = tree_start + total_tree;
+    fn new(mut source: Source) -> Result<FileReader, Box<dyn Error>> {
+        // the first bytes of the header give the size of the rest of it
+        let mut prefix = [0; Header::PREFIX_BYTES];
+        source.read_exact_at(0, &mut prefix)?;
+        let mut bytes = vec![0; Header::size(&prefix)?];
+        source.read_exact_at(0, &mut bytes)?;
+        let header = Header::parse(&bytes)?;
 
         Ok(FileReader {
-            reader,
-            binary_data,
-            is_v6,
-            // is_valid,
-            is_blacklist,
-            //total_bytes,
-            record_bytes,
-            tree_start,
-            tree_end,
-            columns,
+            source,
+            binary_data: header.binary_data,
+            is_v6: header.is_v6,
+            is_blacklist: header.is_blacklist,
+            tree_start: header.tree_start,
+            tree_end: header.tree_end,
+            columns: header.columns.into(),
+            record_buffer: vec![0; header.record_bytes],
+            strings: Arc::new(StringCache::new(DEFAULT_STRING_CACHE_CAPACITY)),
+            index: None,
+            origin: None,
         })
     }
 
@@ -177,107 +131,322 @@ impl FileReader {
     /// # Ok::<(), Box <dyn error::Error>>(())
     /// ```
     pub fn fetch(&mut self, ip: &IpAddr) -> Result<record::Record, Box<dyn Error>> {
+        self.fetch_fields(ip, fields::Fields::ALL)
+    }
+
+    /// Retrieve the record associated with `IpAddr`, decoding only the selected `fields`.
+    /// Every other field of the returned record is None, and string columns that are not selected
+    /// are skipped without reading the string pool.
+    /// ```no_run
+    /// # use std::path::PathBuf;
+    /// use ipqs_db_reader::{Fields, FileReader, Strictness};
+    /// use std::{
+    ///     error,
+    ///     net::{IpAddr, Ipv4Addr},
+    ///     str::FromStr};
+    /// let ip: IpAddr = IpAddr::V4(Ipv4Addr::from_str("8.8.0.0")?);
+    /// # let mut path_buf = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    /// # path_buf.push("resources/IPQualityScore-IP-Reputation-Database-IPv4.ipqs");
+    /// # let mut reader = FileReader::open(&path_buf)?;
+    /// let fields = Fields::IS_PROXY | Fields::IS_VPN | Fields::FRAUD_SCORE_ONE;
+    /// let record = reader.fetch_fields(&ip, fields)?;
+    /// assert!(record.country().is_none());
+    /// let fraud_score = record.fraud_score(Strictness::One);
+    /// # Ok::<(), Box <dyn error::Error>>(())
+    /// ```
+    pub fn fetch_fields(
+        &mut self,
+        ip: &IpAddr,
+        fields: fields::Fields,
+    ) -> Result<record::Record, Box<dyn Error>> {
+        Ok(self.fetch_network(ip, fields)?.0)
+    }
+
+    /// Retrieve the record associated with `IpAddr`, along with the prefix length of the network
+    /// it was found through. Every address in that network leads to the same record.
+    pub(crate) fn fetch_network(
+        &mut self,
+        ip: &IpAddr,
+        fields: fields::Fields,
+    ) -> Result<(record::Record, usize), Box<dyn Error>> {
+        self.check_ip_version(ip)?;
+
+        let source = &mut self.source;
+        let (file_position, prefix_len) = match &self.index {
+            Some(index) => index.find(ip)?,
+            None => tree::find_record(
+                ip,
+                self.tree_start,
+                self.tree_end,
+                self.is_blacklist,
+                |position, node| source.read_exact_at(position, node),
+            )?,
+        };
+
+        // -------- Record found
+        Ok((self.read_record(file_position, fields)?, prefix_len))
+    }
+
+    /// Retrieve a view of the record associated with `IpAddr`, borrowed from the database in
+    /// memory. Fields are decoded from the raw record bytes when they are accessed.
+    /// Only available for readers created by [FileReader::open_in_memory] or [FileReader::from_bytes].
+    /// ```no_run
+    /// # use std::path::PathBuf;
+    /// use ipqs_db_reader::FileReader;
+    /// use std::{
+    ///     error,
+    ///     net::{IpAddr, Ipv4Addr},
+    ///     str::FromStr};
+    /// let ip: IpAddr = IpAddr::V4(Ipv4Addr::from_str("8.8.0.0")?);
+    /// # let mut path_buf = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    /// # path_buf.push("resources/IPQualityScore-IP-Reputation-Database-IPv4.ipqs");
+    /// let reader = FileReader::open_in_memory(&path_buf)?;
+    /// let record = reader.fetch_ref(&ip)?;
+    /// println!("{:?}", record.country());
+    /// let owned = record.to_owned()?;
+    /// # Ok::<(), Box <dyn error::Error>>(())
+    /// ```
+    pub fn fetch_ref(&self, ip: &IpAddr) -> Result<record_ref::RecordRef<'_>, Box<dyn Error>> {
+        let data = match self.source.bytes() {
+            Some(data) => data,
+            None => return Err("borrowed records require a database held in memory".into()),
+        };
+        self.check_ip_version(ip)?;
+
+        let (file_position, _) = match &self.index {
+            Some(index) => index.find(ip)?,
+            None => tree::find_record::<_, ipqs_db_core::Error>(
+                ip,
+                self.tree_start,
+                self.tree_end,
+                self.is_blacklist,
+                |position, node| {
+                    node.copy_from_slice(source::slice_at(data, position, 8)?);
+                    Ok(())
+                },
+            )?,
+        };
+        let raw = source::slice_at(data, file_position, self.record_buffer.len())?;
+
+        Ok(record_ref::RecordRef::new(
+            raw,
+            data,
+            &self.columns,
+            self.binary_data,
+        ))
+    }
+
+    pub(crate) fn check_ip_version(&self, ip: &IpAddr) -> Result<(), Box<dyn Error>> {
         if self.is_v6 && ip.is_ipv4() {
-            return Err("attempted to fetch IPv4 record using IPv6 data file".into());
+            return Err(ipqs_db_core::Error::Ipv4InIpv6File.into());
         }
         if !self.is_v6 && ip.is_ipv6() {
-            return Err("attempted to fetch IPv6 record using IPv4 data file".into());
+            return Err(ipqs_db_core::Error::Ipv6InIpv4File.into());
         }
+        Ok(())
+    }
 
-        let mut position: usize = 0; // bit within binary representation of ip address
-        let mut previous: // maps bits within binary representation to node positions within tree
-            HashMap<usize, u64> = HashMap::new(); // (for going back up tree if ip address not found)
-        let mut file_position = self.tree_start + 5; // start traversing tree just after tree header
-        let mut node: Vec<u8> = vec![0u8; 8]; // each node has 2 ("left" and "right") 4-byte integer "pointers"
-        let mut binary_representation: Vec<bool> = Vec::new();
-        match ip {
-            IpAddr::V4(ipv4) => {
-                for octet in ipv4.octets() {
-                    for s in 0..8 {
-                        let mask = 0x80 >> s;
-                        binary_representation.push(octet & mask != 0);
-                    }
-                }
-            }
-            IpAddr::V6(ipv6) => {
-                for segment in ipv6.segments() {
-                    for s in 0..16 {
-                        let mask = 0x8000 >> s;
-                        binary_representation.push(segment & mask != 0);
-                    }
-                }
-            }
-        }
+    fn read_record(
+        &mut self,
+        file_position: u64,
+        fields: fields::Fields,
+    ) -> Result<record::Record, Box<dyn Error>> {
+        self.source
+            .read_exact_at(file_position, &mut self.record_buffer)?;
+        let source = &mut self.source;
+        let strings = &self.strings;
+        record::Record::parse(
+            &self.record_buffer,
+            &self.columns,
+            self.binary_data,
+            fields,
+            |offset| strings.get_or_read(offset, || source.read_string(offset)),
+        )
+    }
 
-        // loop over tree, aborting after too many iterations
-        for _ in 0..257 {
-            previous.insert(position, file_position);
-            if binary_representation.len() <= position {
-                // somehow we went through the whole binary representation without finding a record
-                return Err("invalid or nonexistent IP specified for lookup (EID 9)".into());
-            }
-            self.reader.seek(SeekFrom::Start(file_position))?;
-            self.reader.read_exact(&mut node)?;
-            if binary_representation[position] {
-                // bit is 1 - go right
-                file_position = utility::four_byte_int(&node[4..8]);
-            } else {
-                // bit is 0 - go left
-                file_position = utility::four_byte_int(&node[0..4]);
-            }
+    /// Sets how many distinct strings (country codes, cities, ISP names, ...) are kept in memory
+    /// after being read from the file. Lookups returning a cached string share it with every other
+    /// record using it instead of reading and allocating it again.
+    /// The default capacity is [DEFAULT_STRING_CACHE_CAPACITY]; a capacity of 0 disables the cache.
+    pub fn set_string_cache_capacity(&mut self, capacity: usize) {
+        self.strings.set_capacity(capacity);
+    }
 
-            if !self.is_blacklist && file_position == 0 {
-                // specified ip is not in the file
-                // go back up the tree until we reach a 1,
-                // take the 0 path, and follow all right children until we reach a record
-                // or another 0
-                for i in 0..position + 1 {
-                    if binary_representation[position - i] {
-                        binary_representation[position - i] = false;
-                        //for n in position - i + 1..binary_representation.len() { // <-- before clippy
-                        for bit in binary_representation.iter_mut().skip(position - i + 1) {
-                            *bit = true;
-                        }
-                        position -= i;
-                        file_position = previous[&position];
-                        break;
-                    }
-                }
-                continue;
-            }
+    /// Reads every string referenced by the records in the file into the string cache, removing
+    /// the capacity limit, so that no lookup needs to read strings from the file afterwards.
+    pub fn preload_strings(&mut self) -> Result<(), Box<dyn Error>> {
+        self.strings.set_capacity(usize::MAX);
+
+        let source = &mut self.source;
+        let mut records: Vec<u64> = Vec::new();
+        tree::for_each_record(
+            self.tree_start,
+            self.tree_end,
+            |position, node| source.read_exact_at(position, node),
+            |record| records.push(record),
+        )?;
+        records.sort_unstable();
+        records.dedup();
+
+        records
+            .into_iter()
+            .try_for_each(|record| self.read_record(record, fields::Fields::ALL).map(|_| ()))
+    }
 
-            if file_position < self.tree_end {
-                // there is still more tree left
-                if file_position == 0 {
-                    break;
+    /// Copies the tree into a compact in-memory index, so that lookups no longer walk the tree
+    /// node by node through the file. The first 16 bits of an address are resolved with a single
+    /// table lookup, and addresses missing from the tree never have to go back up the tree.
+    /// Lookups return exactly the same records as without the index.
+    ///
+    /// The index takes about as much memory as the tree section of the file.
+    pub fn build_index(&mut self) -> Result<(), Box<dyn Error>> {
+        let first_node = self.tree_start + 5;
+        let mut tree: Vec<u8> = vec![0; (self.tree_end - first_node).try_into()?];
+        self.source.read_exact_at(first_node, &mut tree)?;
+        let index = TreeIndex::build(
+            &tree,
+            self.tree_start,
+            self.tree_end,
+            self.is_v6,
+            self.is_blacklist,
+        )?;
+        self.index = Some(Arc::new(index));
+        Ok(())
+    }
+
+    /// Writes the index (see [FileReader::build_index]), every string of the string pool and the
+    /// column schema to a snapshot file, so that [FileReader::open_with_snapshot] can skip
+    /// preparing them the next time the database is opened. The index is built and the strings
+    /// are preloaded first if necessary.
+    pub fn write_snapshot(&mut self, snapshot_path: &Path) -> Result<(), Box<dyn Error>> {
+        snapshot::write(self, snapshot_path)
+    }
+
+    /// Opens the file at `Path` like [FileReader::open], then loads the index and string pool from
+    /// a snapshot written by [FileReader::write_snapshot]. Returns an error if the snapshot was
+    /// not written from the same file: its size and checksum are compared, which reads the whole
+    /// file once.
+    /// ```no_run
+    /// use std::{error, path::Path};
+    /// use ipqs_db_reader::FileReader;
+    /// let path = Path::new("IPQualityScore-IP-Reputation-Database-IPv6.ipqs");
+    /// let snapshot = Path::new("IPQualityScore-IP-Reputation-Database-IPv6.ipqs.snapshot");
+    /// let reader = match FileReader::open_with_snapshot(path, snapshot) {
+    ///     Ok(reader) => reader,
+    ///     Err(_) => {
+    ///         let mut reader = FileReader::open(path)?;
+    ///         reader.write_snapshot(snapshot)?;
+    ///         reader
+    ///     }
+    /// };
+    /// # Ok::<(), Box <dyn error::Error>>(())
+    /// ```
+    pub fn open_with_snapshot(
+        file_path: &Path,
+        snapshot_path: &Path,
+    ) -> Result<FileReader, Box<dyn Error>> {
+        let mut reader = FileReader::open(file_path)?;
+        reader.load_snapshot(snapshot_path)?;
+        Ok(reader)
+    }
+
+    /// Loads the index and string pool from a snapshot into a reader that is already open,
+    /// see [FileReader::open_with_snapshot]
+    pub fn load_snapshot(&mut self, snapshot_path: &Path) -> Result<(), Box<dyn Error>> {
+        snapshot::load(self, snapshot_path)
+    }
+
+    /// Returns another reader for the same database, e.g. for another thread. The new reader
+    /// shares the columns, string cache and index of this one. Readers opened from a file open
+    /// it again, and return an error if it has been modified or replaced in the meantime.
+    pub fn try_clone(&self) -> Result<FileReader, Box<dyn Error>> {
+        let source = match &self.source {
+            Source::File(_) => {
+                let origin = match &self.origin {
+                    Some(origin) => origin,
+                    None => return Err("reader was not opened from a file".into()),
+                };
+                let file = File::open(&origin.path)?;
+                if !origin.is_same_file(&file)? {
+                    return Err("file has been modified or replaced since it was opened".into());
                 }
-                position += 1;
-                continue;
+                Source::File(BufReader::new(file))
             }
+            Source::Memory(bytes) => Source::Memory(Arc::clone(bytes)),
+        };
+        Ok(FileReader {
+            source,
+            tree_start: self.tree_start,
+            tree_end: self.tree_end,
+            is_v6: self.is_v6,
+            binary_data: self.binary_data,
+            columns: Arc::clone(&self.columns),
+            is_blacklist: self.is_blacklist,
+            record_buffer: vec![0; self.record_buffer.len()],
+            strings: Arc::clone(&self.strings),
+            index: self.index.clone(),
+            origin: self.origin.clone(),
+        })
+    }
 
-            // -------- Record found
-            let mut raw: Vec<u8> = vec![0; self.record_bytes];
-            self.reader.seek(SeekFrom::Start(file_position))?;
-            self.reader.read_exact(&mut raw)?;
-            let record = self::record::Record::parse(raw, self)?;
-            return Ok(record);
+    /// Returns true if the file this reader was opened from has been modified or replaced since
+    /// it was opened. Always false for readers created by [FileReader::from_bytes].
+    pub fn has_changed(&self) -> Result<bool, Box<dyn Error>> {
+        match &self.origin {
+            Some(origin) => Ok(origin.has_changed()?),
+            None => Ok(false),
         }
-        Err("invalid or nonexistent IP specified for lookup (EID 10)".into())
     }
 
-    fn get_ranged_string_value(
-        reader: &mut BufReader<File>,
-        offset: u64,
-    ) -> Result<String, Box<dyn Error>> {
-        reader.seek(SeekFrom::Start(offset))?;
-        let mut size_buf: Vec<u8> = vec![0; 1];
-        reader.read_exact(&mut size_buf)?;
-        let size: usize = usize::from(size_buf[0]);
-        let mut raw: Vec<u8> = vec![0; size];
-        reader.read_exact(&mut raw)?;
-        let value = String::from_utf8(raw)?;
+    /// Opens the file this reader was opened from again, either for reading or into memory like
+    /// the original, e.g. after [FileReader::has_changed] reports a new version of the database.
+    /// The new reader keeps the string cache capacity, and builds an index if this reader has one.
+    pub fn reopen(&self) -> Result<FileReader, Box<dyn Error>> {
+        let mut reader = match &self.origin {
+            Some(origin) if origin.in_memory => FileReader::open_in_memory(&origin.path)?,
+            Some(origin) => FileReader::open(&origin.path)?,
+            None => return Err("reader was not opened from a file".into()),
+        };
+        reader.set_string_cache_capacity(self.strings.capacity());
+        if self.index.is_some() {
+            reader.build_index()?;
+        }
+        Ok(reader)
+    }
+
+    /// Lists the networks of the file with their records, in address order, e.g. to export the
+    /// database. The networks are read by a clone of this reader (see [FileReader::try_clone]).
+    /// ```no_run
+    /// # use std::path::PathBuf;
+    /// use ipqs_db_reader::FileReader;
+    /// use std::error;
+    /// # let mut path_buf = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    /// # path_buf.push("resources/IPQualityScore-IP-Reputation-Database-IPv4.ipqs");
+    /// let reader = FileReader::open(&path_buf)?;
+    /// for network in reader.networks()?.take(10) {
+    ///     let (ip, prefix_len, record) = network?;
+    ///     println!("{}/{} {:?}", ip, prefix_len, record.country());
+    /// }
+    /// # Ok::<(), Box <dyn error::Error>>(())
+    /// ```
+    pub fn networks(&self) -> Result<Networks, Box<dyn Error>> {
+        Ok(Networks::new(self.try_clone()?))
+    }
 
-        Ok(value)
+    /// Returns the details of the file given in its header
+    pub fn metadata(&self) -> metadata::Metadata {
+        metadata::Metadata {
+            is_ipv6: self.is_v6,
+            is_blacklist: self.is_blacklist,
+            binary_data: self.binary_data,
+            columns: self
+                .columns
+                .iter()
+                .map(|column| column.name.clone())
+                .collect(),
+            record_bytes: self.record_buffer.len(),
+            tree_bytes: self.tree_end - self.tree_start,
+        }
     }
 
     /// Returns true if the file contains IPv6 addresses
@@ -291,8 +460,29 @@ impl FileReader {
     }
 }
 
+/// Keeps the type of the errors of lookups that can be sent between threads, and turns the
+/// others into their message
+pub(crate) fn sendable(error: Box<dyn Error>) -> Box<dyn Error + Send + Sync> {
+    if let Some(error) = error.downcast_ref::<ipqs_db_core::Error>() {
+        return Box::new(*error);
+    }
+    match error.downcast::<io::Error>() {
+        Ok(error) => error,
+        Err(error) => error.to_string().into(),
+    }
+}
+
+/// Returns true if `error` is one of the errors of lookups of addresses without a record
+pub(crate) fn is_not_found(error: &(dyn Error + 'static)) -> bool {
+    matches!(
+        error.downcast_ref(),
+        Some(ipqs_db_core::Error::NoRecordBefore | ipqs_db_core::Error::NotInFile)
+    )
+}
+
 #[cfg(test)]
 mod tests {
+    use super::test_database::{TestDatabase, TestRecord};
     use super::*;
     use std::error::Error;
     use std::net::{Ipv4Addr, Ipv6Addr};
@@ -347,4 +537,296 @@ mod tests {
         dbg!(record);
         Ok(())
     }
+
+    #[test]
+    fn fetch_nearest_record() -> Result<(), Box<dyn Error>> {
+        let file = TestDatabase::ipv4()
+            .insert("10.0.0.0".parse()?, 9, TestRecord::new(1, "AA"))
+            .insert("10.128.0.0".parse()?, 9, TestRecord::new(2, "BB"))
+            .insert("192.168.0.0".parse()?, 16, TestRecord::new(3, "CC"))
+            .write();
+        let mut file_reader = FileReader::open(file.path())?;
+
+        // addresses inside a network
+        let record = file_reader.fetch(&"10.1.2.3".parse()?)?;
+        assert_eq!(record.asn(), Some(1));
+        assert_eq!(record.country(), Some("AA"));
+        let record = file_reader.fetch(&"10.200.0.1".parse()?)?;
+        assert_eq!(record.asn(), Some(2));
+        let record = file_reader.fetch(&"192.168.255.255".parse()?)?;
+        assert_eq!(record.asn(), Some(3));
+
+        // addresses between networks fall back to the nearest record before them
+        let record = file_reader.fetch(&"11.0.0.1".parse()?)?;
+        assert_eq!(record.asn(), Some(2));
+        let record = file_reader.fetch(&"192.167.0.1".parse()?)?;
+        assert_eq!(record.asn(), Some(2));
+        let record = file_reader.fetch(&"255.255.255.255".parse()?)?;
+        assert_eq!(record.asn(), Some(3));
+
+        // nothing before the first network
+        assert!(file_reader.fetch(&"9.255.255.255".parse()?).is_err());
+        assert!(file_reader.fetch(&"0.0.0.0".parse()?).is_err());
+
+        // wrong address family
+        assert!(file_reader.fetch(&"::1".parse()?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn fetch_blacklist_has_no_fallback() -> Result<(), Box<dyn Error>> {
+        let file = TestDatabase::ipv4()
+            .blacklist()
+            .insert("10.0.0.0".parse()?, 8, TestRecord::new(1, "AA"))
+            .write();
+        let mut file_reader = FileReader::open(file.path())?;
+        assert!(file_reader.is_blacklist());
+
+        let record = file_reader.fetch(&"10.1.2.3".parse()?)?;
+        assert_eq!(record.asn(), Some(1));
+        assert!(file_reader.fetch(&"11.0.0.1".parse()?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn fetch_nearest_record_ipv6() -> Result<(), Box<dyn Error>> {
+        let file = TestDatabase::ipv6()
+            .insert("2001:db8::".parse()?, 33, TestRecord::new(1, "AA"))
+            .insert("2001:db8:8000::".parse()?, 33, TestRecord::new(2, "BB"))
+            .insert(
+                "2001:db9:ffff:ffff::".parse()?,
+                64,
+                TestRecord::new(3, "CC"),
+            )
+            .write();
+        let mut file_reader = FileReader::open(file.path())?;
+        assert!(file_reader.is_ipv6());
+
+        let record = file_reader.fetch(&"2001:db8::1".parse()?)?;
+        assert_eq!(record.asn(), Some(1));
+        let record = file_reader.fetch(&"2001:db9:ffff:ffff::1".parse()?)?;
+        assert_eq!(record.asn(), Some(3));
+        let record = file_reader.fetch(&"2001:db9:ffff:fffe::1".parse()?)?;
+        assert_eq!(record.asn(), Some(2));
+        let record = file_reader.fetch(&"ffff::".parse()?)?;
+        assert_eq!(record.asn(), Some(3));
+        assert!(file_reader.fetch(&"2001:db7::".parse()?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn string_cache() -> Result<(), Box<dyn Error>> {
+        let file = TestDatabase::ipv4()
+            .insert("10.0.0.0".parse()?, 8, TestRecord::new(1, "US"))
+            .insert("11.0.0.0".parse()?, 8, TestRecord::new(2, "US"))
+            .insert("12.0.0.0".parse()?, 8, TestRecord::new(3, "DE"))
+            .write();
+        let mut file_reader = FileReader::open(file.path())?;
+
+        // records sharing a string share the cached value
+        let first = file_reader.fetch(&"10.0.0.1".parse()?)?;
+        let second = file_reader.fetch(&"11.0.0.1".parse()?)?;
+        assert_eq!(second.country(), Some("US"));
+        assert_eq!(
+            first.country().unwrap().as_ptr(),
+            second.country().unwrap().as_ptr()
+        );
+
+        file_reader.set_string_cache_capacity(0);
+        let third = file_reader.fetch(&"11.0.0.1".parse()?)?;
+        assert_eq!(third.country(), Some("US"));
+        assert_ne!(
+            first.country().unwrap().as_ptr(),
+            third.country().unwrap().as_ptr()
+        );
+
+        file_reader.preload_strings()?;
+        assert_eq!(file_reader.strings.len(), 2);
+        let record = file_reader.fetch(&"12.0.0.1".parse()?)?;
+        assert_eq!(record.country(), Some("DE"));
+        assert_eq!(file_reader.strings.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn metadata() -> Result<(), Box<dyn Error>> {
+        let database =
+            TestDatabase::ipv6().insert("2001:db8::".parse()?, 32, TestRecord::new(1, "AA"));
+        let metadata = FileReader::from_bytes(database.bytes())?.metadata();
+        assert!(metadata.is_ipv6);
+        assert!(!metadata.is_blacklist);
+        assert!(metadata.binary_data);
+        assert_eq!(metadata.columns, ["ASN", "Country", "ZeroFraudScore"]);
+        assert_eq!(metadata.record_bytes, 12);
+        Ok(())
+    }
+
+    #[test]
+    fn try_clone() -> Result<(), Box<dyn Error>> {
+        let database =
+            TestDatabase::ipv4().insert("10.0.0.0".parse()?, 8, TestRecord::new(1, "AA"));
+        let file = database.write();
+        let file_reader = FileReader::open(file.path())?;
+        let mut clone = file_reader.try_clone()?;
+        assert_eq!(clone.fetch(&"10.0.0.1".parse()?)?.asn(), Some(1));
+        assert!(Arc::ptr_eq(&file_reader.strings, &clone.strings));
+
+        let mut clone = FileReader::from_bytes(database.bytes())?.try_clone()?;
+        assert_eq!(clone.fetch(&"10.0.0.1".parse()?)?.country(), Some("AA"));
+
+        // a replaced file is not opened in place of the original
+        std::fs::write(file.path(), TestDatabase::ipv4().bytes())?;
+        assert!(file_reader.try_clone().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn fetch_ref() -> Result<(), Box<dyn Error>> {
+        let database = TestDatabase::ipv4()
+            .insert(
+                "10.0.0.0".parse()?,
+                8,
+                TestRecord {
+                    flags: [0b0000_0011, 0b0000_0100, 0b0110_0000],
+                    asn: 64512,
+                    country: "DE",
+                    fraud_score: 90,
+                },
+            )
+            .insert("11.0.0.0".parse()?, 8, TestRecord::new(2, "US"));
+        let file_reader = FileReader::from_bytes(database.bytes())?;
+
+        let record = file_reader.fetch_ref(&"10.1.2.3".parse()?)?;
+        assert_eq!(record.is_proxy(), Some(true));
+        assert_eq!(record.is_vpn(), Some(true));
+        assert_eq!(record.is_tor(), Some(false));
+        assert_eq!(record.is_hosting_provider(), Some(true));
+        assert_eq!(record.connection_type(), "Residential");
+        assert_eq!(record.abuse_velocity(), "medium");
+        assert_eq!(record.country(), Some("DE"));
+        assert_eq!(record.city(), None);
+        assert_eq!(record.asn(), Some(64512));
+        assert_eq!(record.fraud_score(record::Strictness::Zero), Some(90));
+        assert_eq!(record.fraud_score(record::Strictness::One), None);
+
+        // the view and the owned record agree with a regular lookup
+        let owned = record.to_owned()?;
+        let mut file_reader = FileReader::open_in_memory(database.write().path())?;
+        let fetched = file_reader.fetch(&"10.1.2.3".parse()?)?;
+        assert_eq!(owned.to_string(), fetched.to_string());
+
+        // files read on every lookup cannot lend records
+        let file = database.write();
+        let file_reader = FileReader::open(file.path())?;
+        assert!(file_reader.fetch_ref(&"10.1.2.3".parse()?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn fetch_fields() -> Result<(), Box<dyn Error>> {
+        let mut bytes = TestDatabase::ipv4()
+            .insert(
+                "10.0.0.0".parse()?,
+                8,
+                TestRecord {
+                    flags: [0b0000_0011, 0, 0],
+                    asn: 64512,
+                    country: "DE",
+                    fraud_score: 90,
+                },
+            )
+            .bytes();
+        // the only string is stored at the end of the file, make it invalid UTF-8
+        *bytes.last_mut().unwrap() = 0xff;
+        let mut file_reader = FileReader::from_bytes(bytes)?;
+        let ip = "10.1.2.3".parse()?;
+        assert!(file_reader.fetch(&ip).is_err());
+
+        let fields = fields::Fields::IS_PROXY | fields::Fields::FRAUD_SCORE_ZERO;
+        let record = file_reader.fetch_fields(&ip, fields)?;
+        assert_eq!(record.is_proxy(), Some(true));
+        assert_eq!(record.is_vpn(), None);
+        assert_eq!(record.asn(), None);
+        assert_eq!(record.country(), None);
+        assert_eq!(record.fraud_score(record::Strictness::Zero), Some(90));
+
+        let record = file_reader.fetch_fields(&ip, fields::Fields::NONE)?;
+        assert_eq!(record.is_proxy(), None);
+        assert_eq!(record.fraud_score(record::Strictness::Zero), None);
+        Ok(())
+    }
+
+    #[test]
+    fn core_database_matches_file_reader() -> Result<(), Box<dyn Error>> {
+        let bytes = TestDatabase::ipv4()
+            .insert("10.0.0.0".parse()?, 8, TestRecord::new(1, "AA"))
+            .insert("11.1.0.0".parse()?, 16, TestRecord::new(2, "BB"))
+            .bytes();
+        let database = ipqs_db_core::Database::new(&bytes)?;
+        let mut file_reader = FileReader::from_bytes(bytes.clone())?;
+        assert_eq!(database.metadata(), file_reader.metadata());
+        for ip in ["10.1.2.3", "11.1.255.255", "11.2.0.0", "200.0.0.1"] {
+            let ip = ip.parse()?;
+            assert_eq!(database.fetch(&ip)?, file_reader.fetch(&ip)?);
+        }
+        assert_eq!(
+            database.fetch(&"9.0.0.1".parse()?),
+            Err(ipqs_db_core::Error::NoRecordBefore)
+        );
+        assert_eq!(
+            database.fetch(&"::1".parse()?),
+            Err(ipqs_db_core::Error::Ipv6InIpv4File)
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn json_round_trip() -> Result<(), Box<dyn Error>> {
+        let database = TestDatabase::ipv4().insert(
+            "10.0.0.0".parse()?,
+            8,
+            TestRecord {
+                flags: [0b0100_0011, 0b0000_0100, 0b0111_0000],
+                asn: 15169,
+                country: "US",
+                fraud_score: 87,
+            },
+        );
+        let record = FileReader::from_bytes(database.bytes())?.fetch(&"10.0.0.1".parse()?)?;
+        let built = record::RecordBuilder::new()
+            .is_vpn(true)
+            .latitude(52.52)
+            .longitude(-13.405)
+            .fraud_score(record::Strictness::Three, 100)
+            .city("Berlin")
+            .build();
+
+        for record in [record, built, record::Record::default()] {
+            let json = serde_json::to_string(&record)?;
+            let deserialized: record::Record = serde_json::from_str(&json)?;
+            assert_eq!(record, deserialized, "{}", json);
+        }
+
+        // the documented schema
+        let json = serde_json::to_value(
+            FileReader::from_bytes(database.bytes())?.fetch(&"10.0.0.1".parse()?)?,
+        )?;
+        assert_eq!(json["asn"], 15169);
+        assert_eq!(json["country"], "US");
+        assert_eq!(json["city"], serde_json::Value::Null);
+        assert_eq!(json["fraud_score"]["strictness"][0], 87);
+        assert_eq!(json["is_vpn"], true);
+        assert_eq!(json["is_hosting_provider"], true);
+        assert_eq!(json["columns"][0]["name"], "ASN");
+        assert_eq!(json["columns"][0]["record_type"], 32);
+        assert_eq!(json["columns"][0]["value"], "15169");
+
+        // keys that are missing are left empty
+        let record: record::Record = serde_json::from_str(r#"{"asn": 3320, "is_proxy": true}"#)?;
+        assert_eq!(record.asn(), Some(3320));
+        assert_eq!(record.is_proxy(), Some(true));
+        assert_eq!(record.country(), None);
+        Ok(())
+    }
 }
@@ -0,0 +1,335 @@
+// Copyright 2023 IPQualityScore LLC
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::file_reader::fields::Fields;
+use crate::file_reader::record::Record;
+use crate::file_reader::FileReader;
+
+/// How often a new CachedReader checks whether its file has been replaced
+pub const DEFAULT_RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+// marks the end of the recency list
+const NONE: usize = usize::MAX;
+
+/// Wraps a [FileReader] with a least recently used cache of records, keyed by the network
+/// each record was found through. Once an address has been looked up, every other address
+/// in the same network is answered from the cache.
+///
+/// If the reader was opened from a file, the file is checked for changes at most once per
+/// reload interval. When it has been modified or replaced the file is opened again and the
+/// cache is cleared. If the new file cannot be opened, e.g. because it is still being written,
+/// lookups keep being answered from the old one and the file is tried again after the next
+/// interval; [CachedReader::reload_error] tells why it failed.
+/// ```no_run
+/// # use std::path::PathBuf;
+/// use ipqs_db_reader::CachedReader;
+/// use std::{
+///     error,
+///     net::{IpAddr, Ipv4Addr},
+///     str::FromStr};
+/// # let mut path_buf = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+/// # path_buf.push("resources/IPQualityScore-IP-Reputation-Database-IPv4.ipqs");
+/// let mut reader = CachedReader::open(&path_buf, 10_000)?;
+/// let record = reader.fetch(&IpAddr::V4(Ipv4Addr::from_str("8.8.0.0")?))?;
+/// let record = reader.fetch(&IpAddr::V4(Ipv4Addr::from_str("8.8.0.1")?))?;
+/// println!("{} hits, {} misses", reader.hits(), reader.misses());
+/// # Ok::<(), Box <dyn error::Error>>(())
+/// ```
+#[derive(Debug)]
+pub struct CachedReader {
+    reader: FileReader,
+    capacity: usize,
+    // first address of each cached network -> index into entries
+    networks: BTreeMap<u128, usize>,
+    entries: Vec<Entry>,
+    newest: usize,
+    oldest: usize,
+    hits: u64,
+    misses: u64,
+    reload_interval: Duration,
+    // None for readers that were not opened from a file, which are never reloaded and so
+    // don't need a clock (there is none on wasm32-unknown-unknown)
+    last_checked: Option<Instant>,
+    // why the last check for a new file failed, None once one succeeds
+    reload_error: Option<String>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    first: u128,
+    last: u128,
+    record: Record,
+    newer: usize,
+    older: usize,
+}
+
+impl CachedReader {
+    /// Opens the file at `Path` for reading, caching up to `capacity` networks
+    pub fn open(file_path: &Path, capacity: usize) -> Result<CachedReader, Box<dyn Error>> {
+        Ok(CachedReader::new(FileReader::open(file_path)?, capacity))
+    }
+
+    /// Wraps an existing reader, caching up to `capacity` networks
+    pub fn new(reader: FileReader, capacity: usize) -> CachedReader {
+        let last_checked = reader.origin.as_ref().map(|_| Instant::now());
+        CachedReader {
+            reader,
+            capacity,
+            networks: BTreeMap::new(),
+            entries: Vec::new(),
+            newest: NONE,
+            oldest: NONE,
+            hits: 0,
+            misses: 0,
+            reload_interval: DEFAULT_RELOAD_INTERVAL,
+            last_checked,
+            reload_error: None,
+        }
+    }
+
+    /// Sets how often the file is checked for changes. Defaults to [DEFAULT_RELOAD_INTERVAL].
+    pub fn set_reload_interval(&mut self, interval: Duration) {
+        self.reload_interval = interval;
+    }
+
+    /// Retrieve the record associated with `IpAddr`, from the cache if possible
+    pub fn fetch(&mut self, ip: &IpAddr) -> Result<Record, Box<dyn Error>> {
+        let interval = self.reload_interval;
+        if self
+            .last_checked
+            .is_some_and(|last_checked| last_checked.elapsed() >= interval)
+        {
+            self.last_checked = Some(Instant::now());
+            match self.reload() {
+                Ok(()) => self.reload_error = None,
+                Err(error) => self.reload_error = Some(error.to_string()),
+            }
+        }
+
+        // IPv4 and IPv6 addresses share the keys of the cache, so an address of the other
+        // version must not reach it
+        self.reader.check_ip_version(ip)?;
+        let (address, width) = match ip {
+            IpAddr::V4(ipv4) => (u128::from(u32::from(*ipv4)), 32),
+            IpAddr::V6(ipv6) => (u128::from(*ipv6), 128),
+        };
+        // cached networks never overlap, so only the closest one starting at or before
+        // the address can contain it
+        if let Some((_, &index)) = self.networks.range(..=address).next_back() {
+            if address <= self.entries[index].last {
+                self.hits += 1;
+                self.touch(index);
+                return Ok(self.entries[index].record.clone());
+            }
+        }
+        self.misses += 1;
+
+        let (record, prefix_len) = self.reader.fetch_network(ip, Fields::ALL)?;
+        if self.capacity > 0 {
+            let host_bits = width - prefix_len;
+            let mask = u128::MAX.checked_shr(128 - host_bits as u32).unwrap_or(0);
+            self.insert(address & !mask, address | mask, record.clone());
+        }
+        Ok(record)
+    }
+
+    /// Returns why the file could not be opened again the last time it was checked for
+    /// changes, or None if it was. Lookups are answered from the previous file meanwhile.
+    pub fn reload_error(&self) -> Option<&str> {
+        self.reload_error.as_deref()
+    }
+
+    fn reload(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.reader.has_changed()? {
+            self.reader = self.reader.reopen()?;
+            self.clear();
+        }
+        Ok(())
+    }
+
+    /// Number of lookups answered from the cache
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// Number of lookups that had to search the file
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    /// Number of networks currently cached
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if nothing is cached
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Removes every cached record
+    pub fn clear(&mut self) {
+        self.networks.clear();
+        self.entries.clear();
+        self.newest = NONE;
+        self.oldest = NONE;
+    }
+
+    /// Returns the wrapped reader
+    pub fn reader(&mut self) -> &mut FileReader {
+        &mut self.reader
+    }
+
+    fn insert(&mut self, first: u128, last: u128, record: Record) {
+        let entry = Entry {
+            first,
+            last,
+            record,
+            newer: NONE,
+            older: NONE,
+        };
+        let index = if self.entries.len() < self.capacity {
+            self.entries.push(entry);
+            self.entries.len() - 1
+        } else {
+            // reuse the least recently used entry
+            let index = self.oldest;
+            self.unlink(index);
+            self.networks.remove(&self.entries[index].first);
+            self.entries[index] = entry;
+            index
+        };
+        self.networks.insert(first, index);
+        self.push_newest(index);
+    }
+
+    fn touch(&mut self, index: usize) {
+        if self.newest != index {
+            self.unlink(index);
+            self.push_newest(index);
+        }
+    }
+
+    fn unlink(&mut self, index: usize) {
+        let Entry { newer, older, .. } = self.entries[index];
+        match newer {
+            NONE => self.newest = older,
+            newer => self.entries[newer].older = older,
+        }
+        match older {
+            NONE => self.oldest = newer,
+            older => self.entries[older].newer = newer,
+        }
+    }
+
+    fn push_newest(&mut self, index: usize) {
+        self.entries[index].older = self.newest;
+        self.entries[index].newer = NONE;
+        match self.newest {
+            NONE => self.oldest = index,
+            newest => self.entries[newest].newer = index,
+        }
+        self.newest = index;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_reader::test_database::{TestDatabase, TestRecord};
+
+    fn database() -> TestDatabase {
+        TestDatabase::ipv4()
+            .insert("10.0.0.0".parse().unwrap(), 8, TestRecord::new(1, "AA"))
+            .insert("192.168.0.0".parse().unwrap(), 16, TestRecord::new(2, "BB"))
+    }
+
+    #[test]
+    fn hits_whole_network() -> Result<(), Box<dyn Error>> {
+        let file = database().write();
+        let mut reader = CachedReader::open(file.path(), 10)?;
+
+        assert_eq!(reader.fetch(&"10.0.0.1".parse()?)?.asn(), Some(1));
+        assert_eq!(reader.fetch(&"10.255.255.255".parse()?)?.asn(), Some(1));
+        assert_eq!((reader.hits(), reader.misses()), (1, 1));
+
+        // addresses outside every network are cached by the bits that led to the fallback
+        assert_eq!(reader.fetch(&"11.0.0.1".parse()?)?.asn(), Some(1));
+        assert_eq!(reader.fetch(&"11.0.0.2".parse()?)?.asn(), Some(1));
+        assert_eq!(reader.fetch(&"192.168.3.4".parse()?)?.asn(), Some(2));
+        assert_eq!((reader.hits(), reader.misses()), (2, 3));
+        assert_eq!(reader.len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_other_ip_version() -> Result<(), Box<dyn Error>> {
+        let mut reader = CachedReader::new(FileReader::from_bytes(database().bytes())?, 10);
+        assert_eq!(reader.fetch(&"10.0.0.1".parse()?)?.asn(), Some(1));
+        // the same bits as 10.0.0.1, which is cached
+        let error = reader.fetch(&"::a00:1".parse()?).unwrap_err();
+        assert_eq!(
+            error.downcast_ref(),
+            Some(&ipqs_db_core::Error::Ipv6InIpv4File)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn evicts_least_recently_used() -> Result<(), Box<dyn Error>> {
+        let mut reader = CachedReader::new(FileReader::from_bytes(database().bytes())?, 2);
+
+        reader.fetch(&"10.0.0.1".parse()?)?;
+        reader.fetch(&"192.168.0.1".parse()?)?;
+        reader.fetch(&"10.0.0.2".parse()?)?; // hit, 192.168.0.0/16 is now the oldest
+        reader.fetch(&"255.0.0.1".parse()?)?; // evicts 192.168.0.0/16
+        assert_eq!((reader.hits(), reader.misses()), (1, 3));
+        reader.fetch(&"10.0.0.3".parse()?)?;
+        assert_eq!((reader.hits(), reader.misses()), (2, 3));
+        reader.fetch(&"192.168.0.2".parse()?)?;
+        assert_eq!((reader.hits(), reader.misses()), (2, 4));
+        assert_eq!(reader.len(), 2);
+
+        let mut reader = CachedReader::new(FileReader::from_bytes(database().bytes())?, 0);
+        reader.fetch(&"10.0.0.1".parse()?)?;
+        reader.fetch(&"10.0.0.1".parse()?)?;
+        assert_eq!((reader.hits(), reader.misses()), (0, 2));
+        assert!(reader.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn reloads_replaced_file() -> Result<(), Box<dyn Error>> {
+        let file = database().write();
+        let mut reader = CachedReader::open(file.path(), 10)?;
+        reader.set_reload_interval(Duration::ZERO);
+        assert_eq!(reader.fetch(&"10.0.0.1".parse()?)?.asn(), Some(1));
+
+        let replacement = database().insert("10.0.0.0".parse()?, 24, TestRecord::new(3, "CC"));
+        std::fs::write(file.path(), replacement.bytes())?;
+        assert_eq!(reader.fetch(&"10.0.0.1".parse()?)?.asn(), Some(3));
+        assert_eq!(reader.misses(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_serving_until_replacement_opens() -> Result<(), Box<dyn Error>> {
+        let file = database().write();
+        let mut reader = CachedReader::new(FileReader::open_in_memory(file.path())?, 10);
+        reader.set_reload_interval(Duration::ZERO);
+
+        std::fs::write(file.path(), b"partially written")?;
+        assert_eq!(reader.fetch(&"10.0.0.1".parse()?)?.asn(), Some(1));
+        assert!(reader.reload_error().is_some());
+
+        let replacement = database().insert("10.0.0.0".parse()?, 24, TestRecord::new(3, "CC"));
+        std::fs::write(file.path(), replacement.bytes())?;
+        assert_eq!(reader.fetch(&"10.0.0.1".parse()?)?.asn(), Some(3));
+        assert!(reader.reload_error().is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,294 @@
+// Copyright 2023 IPQualityScore LLC
+use std::error::Error;
+use std::io::{Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use crate::utility;
+use ipqs_db_core::tree::{self, Address};
+
+// the first 16 bits of an address are resolved with a single table lookup
+const ROOT_BITS: usize = 16;
+
+// pointers that can never be a node or a record, since they point into the file header
+const NOT_FOUND: u32 = 0; // blacklist files: the address is not in the file
+const NOTHING_BEFORE: u32 = 1; // there is no record before the address
+
+/// An in-memory copy of the tree, built by [FileReader::build_index](crate::FileReader::build_index).
+///
+/// Nodes keep their file positions as "pointers", but every missing child is replaced by the
+/// record the tree walk would fall back to, so a lookup never has to go back up the tree.
+/// A table indexed by the first 16 bits of the address skips the top of the tree.
+#[derive(Debug)]
+pub(crate) struct TreeIndex {
+    first_node: u64,
+    tree_end: u64,
+    root: Vec<u32>,
+    // prefix length of root entries pointing directly at a record
+    root_prefix: Vec<u8>,
+    nodes: Vec<[u32; 2]>,
+}
+
+impl TreeIndex {
+    /// Builds the index from the bytes of the tree, which starts just after the tree header
+    /// at `tree_start` and ends at `tree_end`
+    pub fn build(
+        tree: &[u8],
+        tree_start: u64,
+        tree_end: u64,
+        is_v6: bool,
+        is_blacklist: bool,
+    ) -> Result<TreeIndex, Box<dyn Error>> {
+        let mut index = TreeIndex {
+            first_node: tree_start + 5,
+            tree_end,
+            root: vec![0; 1 << ROOT_BITS],
+            root_prefix: vec![0; 1 << ROOT_BITS],
+            nodes: tree
+                .chunks_exact(8)
+                .map(|node| {
+                    [
+                        utility::four_byte_int(&node[0..4]) as u32,
+                        utility::four_byte_int(&node[4..8]) as u32,
+                    ]
+                })
+                .collect(),
+        };
+        let width = if is_v6 { 128 } else { 32 };
+
+        // find every missing child, along with the bits leading to it
+        let mut missing: Vec<(usize, usize, u128, usize)> = Vec::new();
+        let mut visited = vec![false; index.nodes.len()];
+        let mut pending: Vec<(u32, u128, usize)> = vec![(index.first_node as u32, 0, 0)];
+        while let Some((pointer, bits, depth)) = pending.pop() {
+            let node = index.node_index(pointer)?;
+            if visited[node] || depth >= width {
+                return Err(ipqs_db_core::Error::InvalidTree.into());
+            }
+            visited[node] = true;
+            for side in 0..2 {
+                let child = index.nodes[node][side];
+                let child_bits = bits | (side as u128) << (width - 1 - depth);
+                if child == 0 {
+                    missing.push((node, side, child_bits, depth));
+                } else if index.is_node(child) {
+                    pending.push((child, child_bits, depth + 1));
+                } else if u64::from(child) < index.first_node {
+                    return Err(ipqs_db_core::Error::InvalidTree.into());
+                }
+            }
+        }
+
+        // point missing children at the record the tree walk falls back to
+        if !is_blacklist {
+            let mut resolved = Vec::with_capacity(missing.len());
+            for (_, _, bits, _) in &missing {
+                let ip = if is_v6 {
+                    IpAddr::V6(Ipv6Addr::from(*bits))
+                } else {
+                    IpAddr::V4(Ipv4Addr::from(*bits as u32))
+                };
+                let nodes = &index.nodes;
+                let first_node = index.first_node;
+                let found = tree::find_record::<_, ipqs_db_core::Error>(
+                    &ip,
+                    tree_start,
+                    tree_end,
+                    false,
+                    |position, node| {
+                        let pointers = nodes[((position - first_node) / 8) as usize];
+                        node[0..4].copy_from_slice(&pointers[0].to_le_bytes());
+                        node[4..8].copy_from_slice(&pointers[1].to_le_bytes());
+                        Ok(())
+                    },
+                );
+                resolved.push(match found {
+                    Ok((record, _)) => record as u32,
+                    Err(_) => NOTHING_BEFORE,
+                });
+            }
+            for ((node, side, _, _), record) in missing.into_iter().zip(resolved) {
+                index.nodes[node][side] = record;
+            }
+        }
+
+        // resolve the first bits of every address
+        for prefix in 0..1usize << ROOT_BITS {
+            let mut child = index.first_node as u32;
+            for depth in 0..ROOT_BITS {
+                let side = (prefix >> (ROOT_BITS - 1 - depth)) & 1;
+                child = index.nodes[index.node_index(child)?][side];
+                if !index.is_node(child) {
+                    index.root_prefix[prefix] = (depth + 1) as u8;
+                    break;
+                }
+            }
+            index.root[prefix] = child;
+        }
+
+        Ok(index)
+    }
+
+    /// Returns the file position of the record for `ip` and the prefix length that decided it,
+    /// exactly like [tree::find_record]
+    pub fn find(&self, ip: &IpAddr) -> Result<(u64, usize), Box<dyn Error>> {
+        let address = Address::new(ip);
+        let first_bits = (address.bits >> (address.width - ROOT_BITS)) as usize;
+        let mut child = self.root[first_bits];
+        let mut prefix_len = usize::from(self.root_prefix[first_bits]);
+        let mut position = ROOT_BITS;
+        while self.is_node(child) {
+            if address.width <= position {
+                return Err(ipqs_db_core::Error::NoRecordBefore.into());
+            }
+            let node = &self.nodes[((u64::from(child) - self.first_node) / 8) as usize];
+            child = node[usize::from(address.bit(position))];
+            position += 1;
+            prefix_len = position;
+        }
+        match child {
+            NOT_FOUND => Err(ipqs_db_core::Error::NotInFile.into()),
+            NOTHING_BEFORE => Err(ipqs_db_core::Error::NoRecordBefore.into()),
+            record => Ok((u64::from(record), prefix_len)),
+        }
+    }
+
+    /// Writes the index to a snapshot, see [FileReader::write_snapshot](crate::FileReader::write_snapshot)
+    pub fn write_to(&self, out: &mut impl Write) -> Result<(), Box<dyn Error>> {
+        out.write_all(&(self.nodes.len() as u64).to_le_bytes())?;
+        for node in &self.nodes {
+            out.write_all(&node[0].to_le_bytes())?;
+            out.write_all(&node[1].to_le_bytes())?;
+        }
+        for child in &self.root {
+            out.write_all(&child.to_le_bytes())?;
+        }
+        out.write_all(&self.root_prefix)?;
+        Ok(())
+    }
+
+    /// Reads an index written by [TreeIndex::write_to] for the tree at `tree_start`
+    pub fn read_from(
+        input: &mut impl Read,
+        tree_start: u64,
+        tree_end: u64,
+    ) -> Result<TreeIndex, Box<dyn Error>> {
+        let first_node = tree_start + 5;
+        let mut len = [0; 8];
+        input.read_exact(&mut len)?;
+        if u64::from_le_bytes(len) * 8 != tree_end - first_node {
+            return Err("snapshot index does not match the tree size".into());
+        }
+        let mut nodes = vec![0; (tree_end - first_node).try_into()?];
+        input.read_exact(&mut nodes)?;
+        let mut root = vec![0; 4 << ROOT_BITS];
+        input.read_exact(&mut root)?;
+        let mut root_prefix = vec![0; 1 << ROOT_BITS];
+        input.read_exact(&mut root_prefix)?;
+
+        let pointer = |bytes: &[u8]| utility::four_byte_int(bytes) as u32;
+        Ok(TreeIndex {
+            first_node,
+            tree_end,
+            root: root.chunks_exact(4).map(pointer).collect(),
+            root_prefix,
+            nodes: nodes
+                .chunks_exact(8)
+                .map(|node| [pointer(&node[0..4]), pointer(&node[4..8])])
+                .collect(),
+        })
+    }
+
+    fn is_node(&self, pointer: u32) -> bool {
+        (self.first_node..self.tree_end).contains(&u64::from(pointer))
+    }
+
+    fn node_index(&self, pointer: u32) -> Result<usize, Box<dyn Error>> {
+        if !self.is_node(pointer) || !(u64::from(pointer) - self.first_node).is_multiple_of(8) {
+            return Err(ipqs_db_core::Error::InvalidTree.into());
+        }
+        Ok(((u64::from(pointer) - self.first_node) / 8) as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_reader::fields::Fields;
+    use crate::file_reader::test_database::{TestDatabase, TestRecord};
+    use crate::FileReader;
+
+    // xorshift, so the networks and addresses are the same on every run
+    struct Random(u64);
+
+    impl Random {
+        fn bits(&mut self, width: usize) -> u128 {
+            let mut next = || {
+                self.0 ^= self.0 << 13;
+                self.0 ^= self.0 >> 7;
+                self.0 ^= self.0 << 17;
+                self.0
+            };
+            (u128::from(next()) << 64 | u128::from(next())) >> (128 - width)
+        }
+    }
+
+    fn ip(bits: u128, width: usize) -> IpAddr {
+        if width == 128 {
+            IpAddr::V6(Ipv6Addr::from(bits))
+        } else {
+            IpAddr::V4(Ipv4Addr::from(bits as u32))
+        }
+    }
+
+    #[test]
+    fn same_results_as_tree_walk() -> Result<(), Box<dyn Error>> {
+        let mut random = Random(0x1234_5678);
+        for (width, is_blacklist) in [(32, false), (128, false), (32, true)] {
+            for _ in 0..10 {
+                let mut database = if width == 128 {
+                    TestDatabase::ipv6()
+                } else {
+                    TestDatabase::ipv4()
+                };
+                if is_blacklist {
+                    database = database.blacklist();
+                }
+                // random networks that do not overlap
+                let mut networks: Vec<(u128, usize)> = Vec::new();
+                for asn in 0..50 {
+                    let prefix_len = (random.bits(5) % 24 + 1) as usize;
+                    let network = random.bits(prefix_len) << (width - prefix_len);
+                    let overlaps = networks.iter().any(|&(other, other_len)| {
+                        let shift = width - prefix_len.min(other_len);
+                        other >> shift == network >> shift
+                    });
+                    if !overlaps {
+                        networks.push((network, prefix_len));
+                        let record = TestRecord::new(asn, "AA");
+                        database = database.insert(ip(network, width), prefix_len, record);
+                    }
+                }
+                let mut walk = FileReader::from_bytes(database.bytes())?;
+                let mut indexed = FileReader::from_bytes(database.bytes())?;
+                indexed.build_index()?;
+
+                for _ in 0..1000 {
+                    let address = ip(random.bits(width), width);
+                    let expected = walk.fetch_network(&address, Fields::ASN);
+                    let actual = indexed.fetch_network(&address, Fields::ASN);
+                    match (expected, actual) {
+                        (Ok((expected, expected_len)), Ok((actual, actual_len))) => {
+                            assert_eq!(expected.asn(), actual.asn(), "{}", address);
+                            assert_eq!(expected_len, actual_len, "{}", address);
+                        }
+                        (Err(expected), Err(actual)) => {
+                            assert_eq!(expected.to_string(), actual.to_string(), "{}", address)
+                        }
+                        (expected, actual) => panic!("{}: {:?} != {:?}", address, expected, actual),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+// Copyright 2023 IPQualityScore LLC
+use std::error::Error;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use crate::file_reader::{fields::Fields, record::Record, FileReader};
+use crate::utility;
+
+// file position of a node or record, the bits of the address leading to it and its depth
+type Entry = (u64, u128, usize);
+
+/// Iterator over the networks of a database, in address order, returned by
+/// [FileReader::networks]. Each item is the first address of a network, its prefix length
+/// and its record.
+///
+/// Only networks that are in the file are listed. Outside of blacklist files, lookups of
+/// addresses between two networks return the record of the network before them.
+#[derive(Debug)]
+pub struct Networks {
+    reader: FileReader,
+    // nodes and records left to visit
+    pending: Vec<Entry>,
+    // guards against loops in a corrupt tree
+    remaining_nodes: u64,
+}
+
+impl Networks {
+    pub(crate) fn new(reader: FileReader) -> Networks {
+        let first_node = reader.tree_start + 5;
+        Networks {
+            remaining_nodes: (reader.tree_end - first_node) / 8,
+            pending: vec![(first_node, 0, 0)],
+            reader,
+        }
+    }
+
+    /// Visits nodes until the next record, and returns its file position, bits and prefix length
+    fn next_record(&mut self) -> Result<Option<Entry>, Box<dyn Error>> {
+        let first_node = self.reader.tree_start + 5;
+        let width = if self.reader.is_v6 { 128 } else { 32 };
+        let mut node = [0u8; 8];
+        while let Some((position, bits, depth)) = self.pending.pop() {
+            if position >= self.reader.tree_end {
+                return Ok(Some((position, bits, depth)));
+            }
+            if self.remaining_nodes == 0 || depth >= width {
+                return Err(ipqs_db_core::Error::InvalidTree.into());
+            }
+            self.remaining_nodes -= 1;
+            self.reader.source.read_exact_at(position, &mut node)?;
+
+            // the right child is pushed first so that the left one comes out first
+            for side in [1, 0] {
+                let pointer = utility::four_byte_int(&node[side * 4..side * 4 + 4]);
+                if pointer == 0 {
+                    continue;
+                }
+                if pointer < first_node {
+                    return Err(ipqs_db_core::Error::InvalidTree.into());
+                }
+                let child_bits = bits | (side as u128) << (width - 1 - depth);
+                self.pending.push((pointer, child_bits, depth + 1));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Iterator for Networks {
+    type Item = Result<(IpAddr, usize, Record), Box<dyn Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (position, bits, prefix_len) = match self.next_record() {
+            Ok(record) => record?,
+            Err(error) => {
+                // a corrupt tree ends the iteration
+                self.pending.clear();
+                return Some(Err(error));
+            }
+        };
+        let ip = if self.reader.is_v6 {
+            IpAddr::V6(Ipv6Addr::from(bits))
+        } else {
+            IpAddr::V4(Ipv4Addr::from(bits as u32))
+        };
+        Some(
+            self.reader
+                .read_record(position, Fields::ALL)
+                .map(|record| (ip, prefix_len, record)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::file_reader::test_database::{TestDatabase, TestRecord};
+    use crate::file_reader::FileReader;
+    use std::error::Error;
+
+    #[test]
+    fn lists_networks_in_order() -> Result<(), Box<dyn Error>> {
+        let database = TestDatabase::ipv4()
+            .insert("192.168.0.0".parse()?, 16, TestRecord::new(3, "CC"))
+            .insert("10.0.0.0".parse()?, 8, TestRecord::new(1, "AA"))
+            .insert("11.1.0.0".parse()?, 24, TestRecord::new(2, "BB"));
+        let reader = FileReader::from_bytes(database.bytes())?;
+        let networks: Vec<_> = reader
+            .networks()?
+            .map(|network| {
+                let (ip, prefix_len, record) = network.unwrap();
+                (ip.to_string(), prefix_len, record.asn())
+            })
+            .collect();
+        assert_eq!(
+            networks,
+            [
+                ("10.0.0.0".to_owned(), 8, Some(1)),
+                ("11.1.0.0".to_owned(), 24, Some(2)),
+                ("192.168.0.0".to_owned(), 16, Some(3)),
+            ]
+        );
+
+        let database = TestDatabase::ipv6()
+            .insert("8000::".parse()?, 1, TestRecord::new(2, "BB"))
+            .insert("2001:db8::".parse()?, 32, TestRecord::new(1, "AA"));
+        let reader = FileReader::from_bytes(database.bytes())?;
+        let networks: Vec<_> = reader
+            .networks()?
+            .map(|network| network.map(|(ip, prefix_len, _)| (ip.to_string(), prefix_len)))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(
+            networks,
+            [("2001:db8::".to_owned(), 32), ("8000::".to_owned(), 1)]
+        );
+        Ok(())
+    }
+}
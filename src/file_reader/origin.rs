@@ -0,0 +1,61 @@
+// Copyright 2023 IPQualityScore LLC
+use std::fs::{File, Metadata};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// The file a FileReader was opened from, used to notice when it is modified or replaced
+#[derive(Clone, Debug)]
+pub(crate) struct Origin {
+    pub path: PathBuf,
+    pub in_memory: bool,
+    signature: Signature,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Signature {
+    len: u64,
+    modified: Option<SystemTime>,
+    inode: Option<u64>,
+}
+
+impl Signature {
+    fn new(metadata: &Metadata) -> Signature {
+        #[cfg(unix)]
+        let inode = {
+            use std::os::unix::fs::MetadataExt;
+            Some(metadata.ino())
+        };
+        #[cfg(not(unix))]
+        let inode = None;
+
+        Signature {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            inode,
+        }
+    }
+}
+
+impl Origin {
+    /// `file` must be the opened file at `path`, so that a file replaced after it was opened
+    /// is still noticed
+    pub fn new(path: &Path, file: &File, in_memory: bool) -> io::Result<Origin> {
+        Ok(Origin {
+            path: path.to_owned(),
+            in_memory,
+            signature: Signature::new(&file.metadata()?),
+        })
+    }
+
+    /// Returns true if the file at the path differs from the one that was opened
+    pub fn has_changed(&self) -> io::Result<bool> {
+        let metadata = std::fs::metadata(&self.path)?;
+        Ok(Signature::new(&metadata) != self.signature)
+    }
+
+    /// Returns true if `file` is the file that was opened, unmodified
+    pub fn is_same_file(&self, file: &File) -> io::Result<bool> {
+        Ok(Signature::new(&file.metadata()?) == self.signature)
+    }
+}
@@ -0,0 +1,134 @@
+// Copyright 2023 IPQualityScore LLC
+use std::borrow::Borrow;
+use std::error::Error;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use rayon::prelude::*;
+
+use crate::file_reader::fields::Fields;
+use crate::file_reader::record::Record;
+use crate::file_reader::string_cache::StringCache;
+use crate::file_reader::{sendable, FileReader};
+
+/// The result of each lookup made by [FileReader::par_fetch]. Errors keep their type, e.g.
+/// `ipqs_db_core::Error::NoRecordBefore` for an address without a record, except for errors
+/// that cannot be sent between threads, which are converted to strings.
+pub type ParallelResult = Result<Record, Box<dyn Error + Send + Sync>>;
+
+impl FileReader {
+    /// Looks up every address in parallel on the rayon thread pool. Each thread of the pool
+    /// works with its own reader, created with [FileReader::try_clone] the first time the thread
+    /// needs one, so the index is shared between them. Each reader gets a copy of the string
+    /// cache rather than sharing it, so that threads don't wait for each other to read strings;
+    /// call [FileReader::preload_strings] first for every thread to start with all of them.
+    ///
+    /// Accepts anything rayon can iterate over in parallel, e.g. `&[IpAddr]` or `Vec<IpAddr>`,
+    /// and returns one result per address in the same order. A plain iterator can be
+    /// used through [ParallelBridge::par_bridge], in which case the order is not kept.
+    /// ```no_run
+    /// # use std::path::PathBuf;
+    /// use ipqs_db_reader::{FileReader, Strictness};
+    /// use std::{error, net::IpAddr};
+    /// # let mut path_buf = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    /// # path_buf.push("resources/IPQualityScore-IP-Reputation-Database-IPv4.ipqs");
+    /// let reader = FileReader::open(&path_buf)?;
+    /// let ips: Vec<IpAddr> = vec!["8.8.0.0".parse()?, "1.1.1.1".parse()?];
+    /// for (ip, record) in ips.iter().zip(reader.par_fetch(&ips)) {
+    ///     println!("{}: {:?}", ip, record.map(|record| record.fraud_score(Strictness::Zero)));
+    /// }
+    /// # Ok::<(), Box <dyn error::Error>>(())
+    /// ```
+    pub fn par_fetch<I>(&self, ips: I) -> Vec<ParallelResult>
+    where
+        I: IntoParallelIterator,
+        I::Item: Borrow<IpAddr>,
+    {
+        self.par_fetch_fields(ips, Fields::ALL)
+    }
+
+    /// Like [FileReader::par_fetch], decoding only the selected `fields` as
+    /// [FileReader::fetch_fields] does
+    pub fn par_fetch_fields<I>(&self, ips: I, fields: Fields) -> Vec<ParallelResult>
+    where
+        I: IntoParallelIterator,
+        I::Item: Borrow<IpAddr>,
+    {
+        // indexed by the thread of the pool, rather than created for every job rayon splits
+        // the work into
+        let readers: Vec<Mutex<Option<FileReader>>> = (0..rayon::current_num_threads())
+            .map(|_| Mutex::new(None))
+            .collect();
+        let try_clone = || -> Result<FileReader, Box<dyn Error + Send + Sync>> {
+            let mut reader = self.try_clone().map_err(sendable)?;
+            reader.strings = Arc::new(StringCache::copy(&self.strings));
+            Ok(reader)
+        };
+        ips.into_par_iter()
+            .map(|ip| -> ParallelResult {
+                let fetch = |reader: &mut FileReader| {
+                    reader.fetch_fields(ip.borrow(), fields).map_err(sendable)
+                };
+                match rayon::current_thread_index().and_then(|index| readers.get(index)) {
+                    Some(slot) => {
+                        let mut slot = slot.lock().unwrap_or_else(PoisonError::into_inner);
+                        if slot.is_none() {
+                            *slot = Some(try_clone()?);
+                        }
+                        fetch(slot.as_mut().unwrap())
+                    }
+                    // not on a thread of the current pool
+                    None => fetch(&mut try_clone()?),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_reader::test_database::{TestDatabase, TestRecord};
+
+    #[test]
+    fn same_results_as_fetch() -> Result<(), Box<dyn Error>> {
+        let database = TestDatabase::ipv4()
+            .insert("10.0.0.0".parse()?, 8, TestRecord::new(1, "AA"))
+            .insert("192.168.0.0".parse()?, 16, TestRecord::new(2, "BB"));
+        let file = database.write();
+        let mut reader = FileReader::open(file.path())?;
+
+        let ips: Vec<IpAddr> = (0..10_000u32)
+            .map(|i| IpAddr::from((i.wrapping_mul(2_654_435_761)).to_be_bytes()))
+            .collect();
+        let results = reader.par_fetch(&ips);
+        assert_eq!(results.len(), ips.len());
+        for (ip, result) in ips.iter().zip(results) {
+            match (reader.fetch(ip), result) {
+                (Ok(expected), Ok(actual)) => assert_eq!(expected.asn(), actual.asn()),
+                (Err(expected), Err(actual)) => {
+                    assert_eq!(expected.to_string(), actual.to_string())
+                }
+                (expected, actual) => panic!("{}: {:?} != {:?}", ip, expected, actual),
+            }
+        }
+
+        // addresses before every network have no record, which is still told apart
+        let errors: Vec<_> = reader
+            .par_fetch(&ips)
+            .into_iter()
+            .filter_map(Result::err)
+            .collect();
+        assert!(!errors.is_empty());
+        assert!(errors
+            .iter()
+            .all(|error| crate::file_reader::is_not_found(error.as_ref())));
+
+        let results = reader.par_fetch_fields(vec!["10.0.0.1".parse::<IpAddr>()?], Fields::ASN);
+        assert_eq!(
+            results[0].as_ref().map(|record| record.country()).ok(),
+            Some(None)
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,408 @@
+// Copyright 2023 IPQualityScore LLC
+use std::error::Error;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::file_reader::cached_reader::DEFAULT_RELOAD_INTERVAL;
+use crate::file_reader::FileReader;
+
+/// A pool of readers for the same database, for servers handling lookups on several threads.
+/// The pool is cheap to clone, and every clone lends readers from the same pool.
+///
+/// [ReaderPool::get] lends a reader until the returned [PooledReader] is dropped, waiting for
+/// one to be returned if all of them are in use. The readers share their columns, string cache
+/// and index (see [FileReader::try_clone]).
+///
+/// If the readers were opened from a file, the file is checked for changes at most once per
+/// reload interval. When it has been modified or replaced the file is opened again; readers
+/// still lent out keep using the old file until they are returned, and are then closed. The new
+/// file is read without holding up the lookups of other threads, which go on with the old one.
+/// If the new file cannot be opened, e.g. because it is still being written, the pool keeps
+/// lending readers of the old one and tries again after the next interval;
+/// [ReaderPool::reload_error] tells why it failed. Servers that would rather not check on the
+/// request path can turn the checks off and call [ReaderPool::reload] from a thread of their own.
+/// ```no_run
+/// # use std::path::PathBuf;
+/// use ipqs_db_reader::ReaderPool;
+/// use std::{
+///     error,
+///     net::{IpAddr, Ipv4Addr},
+///     str::FromStr};
+/// # let mut path_buf = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+/// # path_buf.push("resources/IPQualityScore-IP-Reputation-Database-IPv4.ipqs");
+/// let pool = ReaderPool::open(&path_buf, 8)?;
+/// let mut reader = pool.get()?;
+/// let record = reader.fetch(&IpAddr::V4(Ipv4Addr::from_str("8.8.0.0")?))?;
+/// # Ok::<(), Box <dyn error::Error>>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct ReaderPool {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    size: usize,
+    state: Mutex<State>,
+    returned: Condvar,
+}
+
+#[derive(Debug)]
+struct State {
+    // readers are cloned from this one, it is never lent out. Shared so that a new file can
+    // be opened without holding the lock.
+    template: Arc<FileReader>,
+    idle: Vec<FileReader>,
+    // readers lent out, from any generation
+    lent: usize,
+    // increased every time the file is opened again
+    generation: u64,
+    reload_interval: Duration,
+    // None for readers that were not opened from a file, see CachedReader
+    last_checked: Option<Instant>,
+    // why the last check for a new file failed, None once one succeeds
+    reload_error: Option<String>,
+}
+
+impl ReaderPool {
+    /// Opens `size` readers for the file at `Path`
+    pub fn open(file_path: &Path, size: usize) -> Result<ReaderPool, Box<dyn Error>> {
+        ReaderPool::new(FileReader::open(file_path)?, size)
+    }
+
+    /// Creates a pool of `size` readers cloned from `reader`. Build the index or preload the
+    /// strings of `reader` first for every reader of the pool to share them.
+    pub fn new(reader: FileReader, size: usize) -> Result<ReaderPool, Box<dyn Error>> {
+        if size == 0 {
+            return Err("a reader pool needs at least one reader".into());
+        }
+        let idle = (0..size)
+            .map(|_| reader.try_clone())
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ReaderPool {
+            inner: Arc::new(Inner {
+                size,
+                state: Mutex::new(State {
+                    idle,
+                    lent: 0,
+                    generation: 0,
+                    reload_interval: DEFAULT_RELOAD_INTERVAL,
+                    last_checked: reader.origin.as_ref().map(|_| Instant::now()),
+                    reload_error: None,
+                    template: Arc::new(reader),
+                }),
+                returned: Condvar::new(),
+            }),
+        })
+    }
+
+    /// Sets how often the file is checked for changes. Defaults to
+    /// [DEFAULT_RELOAD_INTERVAL](crate::file_reader::cached_reader::DEFAULT_RELOAD_INTERVAL).
+    /// `Duration::MAX` turns the checks off, for callers of [ReaderPool::reload].
+    pub fn set_reload_interval(&self, interval: Duration) {
+        self.lock().reload_interval = interval;
+    }
+
+    /// Lends a reader, waiting for one to be returned if all of them are in use
+    pub fn get(&self) -> Result<PooledReader, Box<dyn Error>> {
+        self.reload_if_due();
+        let mut state = self.lock();
+        while state.lent >= self.inner.size {
+            state = self
+                .inner
+                .returned
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        self.lend(state)
+    }
+
+    /// Lends a reader if one is available, without waiting
+    pub fn try_get(&self) -> Result<Option<PooledReader>, Box<dyn Error>> {
+        self.reload_if_due();
+        let state = self.lock();
+        if state.lent >= self.inner.size {
+            return Ok(None);
+        }
+        self.lend(state).map(Some)
+    }
+
+    /// Opens the file again if it has been modified or replaced, and returns true if it was.
+    /// Lookups go on with the old file while the new one is read. If the new file cannot be
+    /// opened the error is returned, and also kept for [ReaderPool::reload_error].
+    pub fn reload(&self) -> Result<bool, Box<dyn Error>> {
+        let result = self.open_again();
+        self.lock().reload_error = result.as_ref().err().map(ToString::to_string);
+        result
+    }
+
+    /// Returns why the file could not be opened again the last time it was checked for
+    /// changes, or None if it was. Readers of the previous file are lent meanwhile.
+    pub fn reload_error(&self) -> Option<String> {
+        self.lock().reload_error.clone()
+    }
+
+    /// Number of readers in the pool
+    pub fn size(&self) -> usize {
+        self.inner.size
+    }
+
+    /// Number of readers currently lent out
+    pub fn in_use(&self) -> usize {
+        self.lock().lent
+    }
+
+    /// Locks the state even if a thread panicked while holding the lock, which only happens
+    /// between consistent states
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.inner
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Opens the file again if the reload interval has passed and it has changed. Only one
+    /// thread per interval does it.
+    fn reload_if_due(&self) {
+        {
+            let mut state = self.lock();
+            let interval = state.reload_interval;
+            if state
+                .last_checked
+                .is_none_or(|last_checked| last_checked.elapsed() < interval)
+            {
+                return;
+            }
+            state.last_checked = Some(Instant::now());
+        }
+        // a file that cannot be opened (e.g. while it is being replaced) is tried again
+        // after the next interval, lookups keep using the readers of the previous file and
+        // the error is kept for reload_error
+        let _ = self.reload();
+    }
+
+    /// The lock is only held to swap the new reader in.
+    fn open_again(&self) -> Result<bool, Box<dyn Error>> {
+        let template = Arc::clone(&self.lock().template);
+        if !template.has_changed()? {
+            return Ok(false);
+        }
+        let reader = template.reopen()?;
+        let mut state = self.lock();
+        // another thread loaded the file in the meantime
+        if !Arc::ptr_eq(&state.template, &template) {
+            return Ok(false);
+        }
+        state.template = Arc::new(reader);
+        state.generation += 1;
+        state.idle.clear();
+        Ok(true)
+    }
+
+    fn lend(&self, mut state: MutexGuard<'_, State>) -> Result<PooledReader, Box<dyn Error>> {
+        let reader = match state.idle.pop() {
+            Some(reader) => reader,
+            // replaces a reader of an older generation
+            None => state.template.try_clone()?,
+        };
+        state.lent += 1;
+        Ok(PooledReader {
+            reader: Some(reader),
+            generation: state.generation,
+            pool: Arc::clone(&self.inner),
+        })
+    }
+}
+
+/// A reader lent by a [ReaderPool], returned to the pool when dropped
+pub struct PooledReader {
+    reader: Option<FileReader>,
+    generation: u64,
+    pool: Arc<Inner>,
+}
+
+impl Deref for PooledReader {
+    type Target = FileReader;
+
+    fn deref(&self) -> &FileReader {
+        self.reader.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledReader {
+    fn deref_mut(&mut self) -> &mut FileReader {
+        self.reader.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledReader {
+    fn drop(&mut self) {
+        // the reader is returned even if another thread poisoned the lock, or threads waiting
+        // for it would wait forever
+        let mut state = self
+            .pool
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        state.lent -= 1;
+        // readers of an older file are closed
+        if self.generation == state.generation {
+            if let Some(reader) = self.reader.take() {
+                state.idle.push(reader);
+            }
+        }
+        drop(state);
+        self.pool.returned.notify_one();
+    }
+}
+
+impl fmt::Debug for PooledReader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PooledReader")
+            .field("reader", &self.reader)
+            .field("generation", &self.generation)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_reader::test_database::{TestDatabase, TestRecord};
+    use std::thread;
+
+    fn database() -> TestDatabase {
+        TestDatabase::ipv4().insert("10.0.0.0".parse().unwrap(), 8, TestRecord::new(1, "AA"))
+    }
+
+    #[test]
+    fn lends_and_returns() -> Result<(), Box<dyn Error>> {
+        let pool = ReaderPool::new(FileReader::from_bytes(database().bytes())?, 2)?;
+        let first = pool.get()?;
+        let mut second = pool.get()?;
+        assert_eq!(second.fetch(&"10.0.0.1".parse()?)?.asn(), Some(1));
+        assert_eq!(pool.in_use(), 2);
+        assert!(pool.try_get()?.is_none());
+        drop(first);
+        assert!(pool.try_get()?.is_some());
+        drop(second);
+        assert_eq!(pool.in_use(), 0);
+
+        assert!(ReaderPool::new(FileReader::from_bytes(database().bytes())?, 0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn returns_readers_to_a_poisoned_pool() -> Result<(), Box<dyn Error>> {
+        let pool = ReaderPool::new(FileReader::from_bytes(database().bytes())?, 1)?;
+        let reader = pool.get()?;
+        let poisoner = pool.clone();
+        let _ = thread::spawn(move || {
+            let _state = poisoner.lock();
+            panic!("poisons the lock");
+        })
+        .join();
+        assert!(pool.inner.state.is_poisoned());
+        drop(reader);
+        assert_eq!((pool.in_use(), pool.lock().idle.len()), (0, 1));
+
+        // and keeps lending them, also to threads waiting for one
+        pool.set_reload_interval(Duration::ZERO);
+        let mut reader = pool.get()?;
+        assert_eq!(reader.fetch(&"10.0.0.1".parse()?)?.asn(), Some(1));
+        let waiter = pool.clone();
+        let waiter = thread::spawn(move || waiter.get().is_ok());
+        thread::sleep(Duration::from_millis(10));
+        drop(reader);
+        assert!(waiter.join().unwrap());
+        Ok(())
+    }
+
+    #[test]
+    fn waits_for_a_reader() -> Result<(), Box<dyn Error>> {
+        let file = database().write();
+        let pool = ReaderPool::open(file.path(), 2)?;
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let pool = pool.clone();
+                thread::spawn(move || {
+                    for _ in 0..100 {
+                        let mut reader = pool.get().unwrap();
+                        assert!(pool.in_use() <= 2);
+                        assert_eq!(
+                            reader.fetch(&"10.0.0.1".parse().unwrap()).unwrap().asn(),
+                            Some(1)
+                        );
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(pool.in_use(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn drains_readers_of_replaced_file() -> Result<(), Box<dyn Error>> {
+        let file = database().write();
+        let pool = ReaderPool::open(file.path(), 2)?;
+        pool.set_reload_interval(Duration::ZERO);
+        let old = pool.get()?;
+
+        let replacement = database().insert("10.0.0.0".parse()?, 24, TestRecord::new(3, "CC"));
+        std::fs::write(file.path(), replacement.bytes())?;
+        let mut new = pool.get()?;
+        assert_eq!(new.fetch(&"10.0.0.1".parse()?)?.asn(), Some(3));
+        assert_eq!(old.generation + 1, new.generation);
+
+        // the old reader is closed once returned rather than lent again
+        drop(old);
+        assert_eq!(pool.lock().idle.len(), 0);
+        drop(new);
+        assert_eq!(pool.lock().idle.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_readers_until_replacement_opens() -> Result<(), Box<dyn Error>> {
+        let file = database().write();
+        let pool = ReaderPool::new(FileReader::open_in_memory(file.path())?, 1)?;
+        pool.set_reload_interval(Duration::ZERO);
+
+        std::fs::write(file.path(), b"partially written")?;
+        assert_eq!(pool.get()?.fetch(&"10.0.0.1".parse()?)?.asn(), Some(1));
+        assert!(pool.reload_error().is_some());
+
+        let replacement = database().insert("10.0.0.0".parse()?, 24, TestRecord::new(3, "CC"));
+        std::fs::write(file.path(), replacement.bytes())?;
+        assert_eq!(pool.get()?.fetch(&"10.0.0.1".parse()?)?.asn(), Some(3));
+        assert_eq!(pool.reload_error(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn reloads_when_asked() -> Result<(), Box<dyn Error>> {
+        let file = database().write();
+        let pool = ReaderPool::new(FileReader::open_in_memory(file.path())?, 1)?;
+        pool.set_reload_interval(Duration::MAX);
+        assert!(!pool.reload()?);
+
+        std::fs::write(file.path(), b"partially written")?;
+        assert!(pool.reload().is_err());
+        assert!(pool.reload_error().is_some());
+
+        let replacement = database().insert("10.0.0.0".parse()?, 24, TestRecord::new(3, "CC"));
+        std::fs::write(file.path(), replacement.bytes())?;
+        // the checks are off, get keeps lending readers of the first file
+        assert_eq!(pool.get()?.fetch(&"10.0.0.1".parse()?)?.asn(), Some(1));
+        assert!(pool.reload()?);
+        assert_eq!(pool.reload_error(), None);
+        assert_eq!(pool.get()?.fetch(&"10.0.0.1".parse()?)?.asn(), Some(3));
+        Ok(())
+    }
+}
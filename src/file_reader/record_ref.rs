@@ -0,0 +1,234 @@
+// Copyright 2023 IPQualityScore LLC
+use crate::binary_option as flag;
+use crate::binary_option::BinaryOption;
+use crate::column::Column;
+use crate::file_reader::fields::Fields;
+use crate::file_reader::record::{self, Record, Strictness};
+use crate::file_reader::source;
+use crate::utility;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+/// A record borrowed from a database held in memory, returned by
+/// [FileReader::fetch_ref](crate::FileReader::fetch_ref).
+///
+/// Nothing is copied or decoded until a field is accessed: each accessor reads its value
+/// straight from the raw record bytes and the string pool. The accessors mirror those of
+/// [Record], and [RecordRef::to_owned] converts the view into a [Record].
+/// Strings that cannot be decoded are returned as None.
+#[derive(Clone, Copy)]
+pub struct RecordRef<'a> {
+    raw: &'a [u8],
+    data: &'a [u8],
+    columns: &'a [Column],
+    binary_data: bool,
+}
+
+impl<'a> RecordRef<'a> {
+    pub(crate) fn new(
+        raw: &'a [u8],
+        data: &'a [u8],
+        columns: &'a [Column],
+        binary_data: bool,
+    ) -> RecordRef<'a> {
+        RecordRef {
+            raw,
+            data,
+            columns,
+            binary_data,
+        }
+    }
+
+    /// Copies every field into an owned [Record]
+    pub fn to_owned(&self) -> Result<Record, Box<dyn Error>> {
+        Record::parse(
+            self.raw,
+            self.columns,
+            self.binary_data,
+            Fields::ALL,
+            |offset| Ok(Arc::from(source::string_at(self.data, offset)?)),
+        )
+    }
+
+    // files with the binary data flag set have two additional bytes per record
+    fn flag(&self, byte: usize, flag: u8) -> Option<bool> {
+        if !self.binary_data {
+            return None;
+        }
+        Some(
+            BinaryOption {
+                data: self.raw[byte],
+            }
+            .has(flag),
+        )
+    }
+
+    // files with or without binary data share connection type/abuse velocity byte
+    fn common_byte(&self) -> u8 {
+        if self.binary_data {
+            self.raw[2]
+        } else {
+            self.raw[0]
+        }
+    }
+
+    /// Returns the bytes of the column called `name`
+    fn column(&self, name: &str) -> Option<&'a [u8]> {
+        let mut current_byte = if self.binary_data { 3 } else { 1 };
+        for column in self.columns {
+            let width = column_width(column);
+            if column.name == name {
+                return self.raw.get(current_byte..current_byte + width);
+            }
+            current_byte += width;
+        }
+        None
+    }
+
+    fn string(&self, name: &str) -> Option<&'a str> {
+        let bytes = self.column(name)?;
+        if bytes.len() != 4 {
+            return None;
+        }
+        source::string_at(self.data, utility::four_byte_int(bytes)).ok()
+    }
+
+    fn float(&self, name: &str) -> Option<f32> {
+        self.column(name)
+            .filter(|bytes| bytes.len() == 4)
+            .map(utility::four_byte_float)
+    }
+
+    pub fn is_proxy(&self) -> Option<bool> {
+        self.flag(0, flag::IS_PROXY)
+    }
+
+    pub fn is_vpn(&self) -> Option<bool> {
+        self.flag(0, flag::IS_VPN)
+    }
+
+    pub fn is_tor(&self) -> Option<bool> {
+        self.flag(0, flag::IS_TOR)
+    }
+
+    pub fn is_crawler(&self) -> Option<bool> {
+        self.flag(0, flag::IS_CRAWLER)
+    }
+
+    pub fn is_bot(&self) -> Option<bool> {
+        self.flag(0, flag::IS_BOT)
+    }
+
+    pub fn recent_abuse(&self) -> Option<bool> {
+        self.flag(0, flag::RECENT_ABUSE)
+    }
+
+    pub fn is_blacklisted(&self) -> Option<bool> {
+        self.flag(0, flag::IS_BLACKLISTED)
+    }
+
+    pub fn is_private(&self) -> Option<bool> {
+        self.flag(0, flag::IS_PRIVATE)
+    }
+
+    pub fn is_mobile(&self) -> Option<bool> {
+        self.flag(1, flag::IS_MOBILE)
+    }
+
+    pub fn has_open_ports(&self) -> Option<bool> {
+        self.flag(1, flag::HAS_OPEN_PORTS)
+    }
+
+    pub fn is_hosting_provider(&self) -> Option<bool> {
+        self.flag(1, flag::IS_HOSTING_PROVIDER)
+    }
+
+    pub fn active_vpn(&self) -> Option<bool> {
+        self.flag(1, flag::ACTIVE_VPN)
+    }
+
+    pub fn active_tor(&self) -> Option<bool> {
+        self.flag(1, flag::ACTIVE_TOR)
+    }
+
+    pub fn public_access_point(&self) -> Option<bool> {
+        self.flag(1, flag::PUBLIC_ACCESS_POINT)
+    }
+
+    pub fn connection_type(&self) -> &'static str {
+        record::connection_type(self.common_byte())
+    }
+
+    pub fn abuse_velocity(&self) -> &'static str {
+        record::abuse_velocity(self.common_byte())
+    }
+
+    pub fn country(&self) -> Option<&'a str> {
+        self.string("Country")
+    }
+
+    pub fn city(&self) -> Option<&'a str> {
+        self.string("City")
+    }
+
+    pub fn region(&self) -> Option<&'a str> {
+        self.string("Region")
+    }
+
+    pub fn isp(&self) -> Option<&'a str> {
+        self.string("ISP")
+    }
+
+    pub fn organization(&self) -> Option<&'a str> {
+        self.string("Organization")
+    }
+
+    pub fn asn(&self) -> Option<u64> {
+        self.column("ASN")
+            .filter(|bytes| bytes.len() == 4)
+            .map(utility::four_byte_int)
+    }
+
+    pub fn timezone(&self) -> Option<&'a str> {
+        self.string("Timezone")
+    }
+
+    pub fn latitude(&self) -> Option<f32> {
+        self.float("Latitude")
+    }
+
+    pub fn longitude(&self) -> Option<f32> {
+        self.float("Longitude")
+    }
+
+    pub fn fraud_score(&self, strictness: Strictness) -> Option<u32> {
+        let name = match strictness {
+            Strictness::Zero => "ZeroFraudScore",
+            Strictness::One => "OneFraudScore",
+            Strictness::Two => "TwoFraudScore",
+            Strictness::Three => "ThreeFraudScore",
+        };
+        self.column(name)
+            .and_then(|bytes| bytes.first())
+            .map(|score| u32::from(*score))
+    }
+}
+
+impl fmt::Debug for RecordRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecordRef")
+            .field("raw", &self.raw)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Number of bytes a column takes up in each record, matching [Record::parse]
+fn column_width(column: &Column) -> usize {
+    match column.name.as_str() {
+        "ASN" | "Latitude" | "Longitude" => 4,
+        "ZeroFraudScore" | "OneFraudScore" | "TwoFraudScore" | "ThreeFraudScore" => 1,
+        _ if column.record_type.has(flag::STRING_DATA) => 4,
+        _ => 0,
+    }
+}
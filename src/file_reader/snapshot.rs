@@ -0,0 +1,316 @@
+// Copyright 2023 IPQualityScore LLC
+//! Snapshot files hold everything [FileReader::write_snapshot] works out from a database, so
+//! that it does not have to be worked out again every time the database is opened.
+//!
+//! All integers are little-endian:
+//!
+//! | bytes        | contents                                                       |
+//! |--------------|----------------------------------------------------------------|
+//! | 8            | `IPQSSNAP`                                                     |
+//! | 4            | snapshot version                                               |
+//! | 8, 8         | size and checksum of the database file                         |
+//! | 8, 8, 4, 1   | tree start, tree end, record bytes and file options            |
+//! | 4 + columns  | number of columns, then each name (1 byte length) and type     |
+//! | index        | number of nodes, the nodes, then the root table                |
+//! | 8 + strings  | number of strings, then each offset and length-prefixed string |
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::binary_option::BinaryOption;
+use crate::column::Column;
+use crate::file_reader::index::TreeIndex;
+use crate::file_reader::source::Source;
+use crate::file_reader::FileReader;
+
+const MAGIC: &[u8; 8] = b"IPQSSNAP";
+const SNAPSHOT_VERSION: u32 = 1;
+
+const IS_V6: u8 = 0b0000_0001;
+const IS_BLACKLIST: u8 = 0b0000_0010;
+const BINARY_DATA: u8 = 0b0000_0100;
+
+/// Writes the index, string pool and columns of `reader` to `path`. The snapshot is written
+/// next to `path` first and then renamed, so a snapshot being loaded is never half written.
+pub(crate) fn write(reader: &mut FileReader, path: &Path) -> Result<(), Box<dyn Error>> {
+    if reader.index.is_none() {
+        reader.build_index()?;
+    }
+    // preloading lifts the capacity set by the caller, which is put back once the strings
+    // are written
+    let capacity = reader.strings.capacity();
+    let result = reader
+        .preload_strings()
+        .and_then(|()| write_preloaded(reader, path));
+    reader.strings.set_capacity(capacity);
+    result
+}
+
+fn write_preloaded(reader: &mut FileReader, path: &Path) -> Result<(), Box<dyn Error>> {
+    let (size, checksum) = checksum(&mut reader.source)?;
+
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    let mut partial = PartialFile {
+        path: partial,
+        renamed: false,
+    };
+    let mut out = BufWriter::new(File::create(&partial.path)?);
+    out.write_all(MAGIC)?;
+    out.write_all(&SNAPSHOT_VERSION.to_le_bytes())?;
+    out.write_all(&size.to_le_bytes())?;
+    out.write_all(&checksum.to_le_bytes())?;
+    write_header(reader, &mut out)?;
+
+    if let Some(index) = &reader.index {
+        index.write_to(&mut out)?;
+    }
+
+    let strings = reader.strings.values();
+    out.write_all(&(strings.len() as u64).to_le_bytes())?;
+    for (offset, value) in strings {
+        out.write_all(&offset.to_le_bytes())?;
+        let len: u8 = value
+            .len()
+            .try_into()
+            .map_err(|_| "string is too long for the string pool")?;
+        out.write_all(&[len])?;
+        out.write_all(value.as_bytes())?;
+    }
+
+    out.into_inner()
+        .map_err(|error| error.into_error())?
+        .sync_all()?;
+    fs::rename(&partial.path, path)?;
+    partial.renamed = true;
+    Ok(())
+}
+
+/// A snapshot being written, removed unless it was renamed into place
+struct PartialFile {
+    path: OsString,
+    renamed: bool,
+}
+
+impl Drop for PartialFile {
+    fn drop(&mut self) {
+        if !self.renamed {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Loads the index and string pool in the snapshot at `path` into `reader`, after checking the
+/// snapshot was written from the same database
+pub(crate) fn load(reader: &mut FileReader, path: &Path) -> Result<(), Box<dyn Error>> {
+    let mut input = BufReader::new(File::open(path)?);
+    let mut magic = [0; 8];
+    input.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err("file is not a snapshot".into());
+    }
+    if read_u32(&mut input)? != SNAPSHOT_VERSION {
+        return Err("unsupported snapshot version".into());
+    }
+    let expected = (read_u64(&mut input)?, read_u64(&mut input)?);
+    if checksum(&mut reader.source)? != expected {
+        return Err("snapshot was not written from this database file".into());
+    }
+
+    // the header is compared rather than trusted, it is cheap to parse from the file
+    let mut header = Vec::new();
+    write_header(reader, &mut header)?;
+    let mut snapshot_header = vec![0; header.len()];
+    input.read_exact(&mut snapshot_header)?;
+    if header != snapshot_header {
+        return Err("snapshot columns do not match the database file".into());
+    }
+
+    let index = TreeIndex::read_from(&mut input, reader.tree_start, reader.tree_end)?;
+
+    let mut strings = Vec::new();
+    for _ in 0..read_u64(&mut input)? {
+        let offset = read_u64(&mut input)?;
+        let mut len = [0; 1];
+        input.read_exact(&mut len)?;
+        let mut value = vec![0; usize::from(len[0])];
+        input.read_exact(&mut value)?;
+        strings.push((offset, Arc::from(String::from_utf8(value)?)));
+    }
+
+    reader.index = Some(Arc::new(index));
+    reader.strings.set_capacity(usize::MAX);
+    reader.strings.extend(strings);
+    Ok(())
+}
+
+fn write_header(reader: &FileReader, out: &mut impl Write) -> Result<(), Box<dyn Error>> {
+    out.write_all(&reader.tree_start.to_le_bytes())?;
+    out.write_all(&reader.tree_end.to_le_bytes())?;
+    out.write_all(&(reader.record_buffer.len() as u32).to_le_bytes())?;
+    let mut options = 0;
+    for (set, option) in [
+        (reader.is_v6, IS_V6),
+        (reader.is_blacklist, IS_BLACKLIST),
+        (reader.binary_data, BINARY_DATA),
+    ] {
+        if set {
+            options |= option;
+        }
+    }
+    out.write_all(&[options])?;
+
+    out.write_all(&(reader.columns.len() as u32).to_le_bytes())?;
+    for Column {
+        name,
+        record_type: BinaryOption { data },
+        ..
+    } in reader.columns.iter()
+    {
+        // column names are at most 23 bytes
+        out.write_all(&[name.len() as u8])?;
+        out.write_all(name.as_bytes())?;
+        out.write_all(&[*data])?;
+    }
+    Ok(())
+}
+
+/// Returns the size and checksum of the whole database. The checksum is FNV-1a over 8 byte
+/// words, which is quick enough to run on every open; it only detects accidental changes.
+fn checksum(source: &mut Source) -> Result<(u64, u64), Box<dyn Error>> {
+    let mut checksum = Checksum::new();
+    match source {
+        Source::File(reader) => {
+            reader.seek(SeekFrom::Start(0))?;
+            let mut buffer = vec![0; 1 << 16];
+            loop {
+                // fill the whole buffer, so that words never straddle two reads
+                let mut filled = 0;
+                while filled < buffer.len() {
+                    match reader.read(&mut buffer[filled..])? {
+                        0 => break,
+                        read => filled += read,
+                    }
+                }
+                checksum.update(&buffer[..filled]);
+                if filled < buffer.len() {
+                    break;
+                }
+            }
+        }
+        Source::Memory(bytes) => checksum.update((**bytes).as_ref()),
+    }
+    Ok((checksum.len, checksum.hash))
+}
+
+struct Checksum {
+    len: u64,
+    hash: u64,
+}
+
+impl Checksum {
+    fn new() -> Checksum {
+        Checksum {
+            len: 0,
+            hash: 0xcbf2_9ce4_8422_2325,
+        }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        let words = bytes.chunks_exact(8);
+        let rest = words.remainder();
+        for word in words {
+            self.mix(u64::from_le_bytes(word.try_into().unwrap()));
+        }
+        for byte in rest {
+            self.mix(u64::from(*byte));
+        }
+        self.len += bytes.len() as u64;
+    }
+
+    fn mix(&mut self, word: u64) {
+        self.hash = (self.hash ^ word).wrapping_mul(0x0000_0100_0000_01b3);
+    }
+}
+
+fn read_u32(input: &mut impl Read) -> Result<u32, Box<dyn Error>> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(input: &mut impl Read) -> Result<u64, Box<dyn Error>> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_reader::test_database::{TestDatabase, TestRecord};
+
+    fn database() -> TestDatabase {
+        TestDatabase::ipv4()
+            .insert("10.0.0.0".parse().unwrap(), 8, TestRecord::new(1, "AA"))
+            .insert("192.168.0.0".parse().unwrap(), 16, TestRecord::new(2, "BB"))
+    }
+
+    #[test]
+    fn loads_written_snapshot() -> Result<(), Box<dyn Error>> {
+        let file = database().write();
+        let snapshot = file.path().with_extension("snapshot");
+        let mut reader = FileReader::open(file.path())?;
+        reader.set_string_cache_capacity(1);
+        reader.write_snapshot(&snapshot)?;
+        assert_eq!(reader.strings.capacity(), 1);
+
+        let mut reader = FileReader::open_with_snapshot(file.path(), &snapshot)?;
+        assert!(reader.index.is_some());
+        assert_eq!(reader.strings.len(), 2);
+        assert_eq!(reader.fetch(&"10.1.2.3".parse()?)?.asn(), Some(1));
+        assert_eq!(reader.fetch(&"192.169.0.1".parse()?)?.country(), Some("BB"));
+        assert!(reader.fetch(&"9.0.0.1".parse()?).is_err());
+
+        // the snapshot is checked against the file whichever way it was opened
+        let mut reader = FileReader::from_bytes(database().bytes())?;
+        reader.load_snapshot(&snapshot)?;
+
+        fs::remove_file(&snapshot)?;
+        Ok(())
+    }
+
+    #[test]
+    fn removes_partial_snapshot_on_error() -> Result<(), Box<dyn Error>> {
+        let file = database().write();
+        // a snapshot cannot be renamed over a directory
+        let snapshot = file.path().with_extension("snapshot.d");
+        fs::create_dir(&snapshot)?;
+        assert!(FileReader::open(file.path())?
+            .write_snapshot(&snapshot)
+            .is_err());
+        let mut partial = snapshot.clone().into_os_string();
+        partial.push(".partial");
+        assert!(!Path::new(&partial).exists());
+
+        fs::remove_dir(&snapshot)?;
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_snapshot_of_another_file() -> Result<(), Box<dyn Error>> {
+        let file = database().write();
+        let snapshot = file.path().with_extension("snapshot");
+        FileReader::open(file.path())?.write_snapshot(&snapshot)?;
+
+        let other = database().insert("10.0.0.0".parse()?, 24, TestRecord::new(3, "AA"));
+        fs::write(file.path(), other.bytes())?;
+        assert!(FileReader::open_with_snapshot(file.path(), &snapshot).is_err());
+
+        fs::remove_file(&snapshot)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,67 @@
+// Copyright 2023 IPQualityScore LLC
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::sync::Arc;
+
+pub(crate) use crate::utility::{slice_at, string_at};
+
+/// Where the bytes of the flat file database are read from
+pub(crate) enum Source {
+    /// read from the file on every lookup
+    File(BufReader<File>),
+    /// the whole database is held in memory (or memory-mapped)
+    Memory(Arc<dyn AsRef<[u8]> + Send + Sync>),
+}
+
+impl Source {
+    /// Fills `buf` with the bytes starting at `position`
+    pub fn read_exact_at(&mut self, position: u64, buf: &mut [u8]) -> Result<(), Box<dyn Error>> {
+        match self {
+            Source::File(reader) => {
+                reader.seek(SeekFrom::Start(position))?;
+                reader.read_exact(buf)?;
+            }
+            Source::Memory(bytes) => {
+                buf.copy_from_slice(slice_at((**bytes).as_ref(), position, buf.len())?);
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the length-prefixed string at `offset` in the string pool
+    pub fn read_string(&mut self, offset: u64) -> Result<String, Box<dyn Error>> {
+        match self {
+            Source::File(reader) => {
+                reader.seek(SeekFrom::Start(offset))?;
+                let mut size_buf = [0u8; 1];
+                reader.read_exact(&mut size_buf)?;
+                let size: usize = usize::from(size_buf[0]);
+                let mut raw: Vec<u8> = vec![0; size];
+                reader.read_exact(&mut raw)?;
+                let value = String::from_utf8(raw)?;
+
+                Ok(value)
+            }
+            Source::Memory(bytes) => Ok(string_at((**bytes).as_ref(), offset)?.to_owned()),
+        }
+    }
+
+    /// Returns the whole database if it is held in memory
+    pub fn bytes(&self) -> Option<&[u8]> {
+        match self {
+            Source::File(_) => None,
+            Source::Memory(bytes) => Some((**bytes).as_ref()),
+        }
+    }
+}
+
+impl fmt::Debug for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::File(reader) => f.debug_tuple("File").field(reader).finish(),
+            Source::Memory(bytes) => write!(f, "Memory({} bytes)", (**bytes).as_ref().len()),
+        }
+    }
+}
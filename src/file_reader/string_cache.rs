@@ -0,0 +1,123 @@
+// Copyright 2023 IPQualityScore LLC
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+
+/// Number of strings kept by a new FileReader
+pub const DEFAULT_STRING_CACHE_CAPACITY: usize = 65_536;
+
+/// Caches the strings of the string pool by their file offset, so that values shared by many
+/// records ("US", "America/Chicago", ISP names, ...) are only read and allocated once.
+///
+/// Once the cache holds `capacity` strings, new strings are read from the file on every lookup.
+#[derive(Debug)]
+pub(crate) struct StringCache {
+    inner: Mutex<Strings>,
+}
+
+#[derive(Debug)]
+struct Strings {
+    values: HashMap<u64, Arc<str>>,
+    capacity: usize,
+}
+
+impl StringCache {
+    pub fn new(capacity: usize) -> StringCache {
+        StringCache {
+            inner: Mutex::new(Strings {
+                values: HashMap::new(),
+                capacity,
+            }),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.inner.lock().unwrap().capacity
+    }
+
+    /// Changes the capacity, dropping every cached string if they no longer fit
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.capacity = capacity;
+        if inner.values.len() > capacity {
+            inner.values.clear();
+        }
+    }
+
+    /// Returns every cached string along with its offset
+    pub fn values(&self) -> Vec<(u64, Arc<str>)> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .values
+            .iter()
+            .map(|(offset, value)| (*offset, Arc::clone(value)))
+            .collect()
+    }
+
+    /// Returns a separate cache holding the strings cached so far, with the same capacity
+    #[cfg(feature = "rayon")]
+    pub fn copy(&self) -> StringCache {
+        let inner = self.inner.lock().unwrap();
+        StringCache {
+            inner: Mutex::new(Strings {
+                values: inner.values.clone(),
+                capacity: inner.capacity,
+            }),
+        }
+    }
+
+    /// Adds strings read elsewhere, e.g. from a snapshot, up to the capacity
+    pub fn extend(&self, values: impl IntoIterator<Item = (u64, Arc<str>)>) {
+        let mut inner = self.inner.lock().unwrap();
+        for (offset, value) in values {
+            if inner.values.len() >= inner.capacity {
+                break;
+            }
+            inner.values.insert(offset, value);
+        }
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().values.len()
+    }
+
+    /// Returns the string at `offset`, calling `read` to fetch it from the file if it is not cached
+    pub fn get_or_read<F>(&self, offset: u64, read: F) -> Result<Arc<str>, Box<dyn Error>>
+    where
+        F: FnOnce() -> Result<String, Box<dyn Error>>,
+    {
+        if let Some(value) = self.inner.lock().unwrap().values.get(&offset) {
+            return Ok(Arc::clone(value));
+        }
+        // the lock is not held while reading, other readers sharing the cache may continue
+        let value: Arc<str> = Arc::from(read()?);
+        let mut inner = self.inner.lock().unwrap();
+        if inner.values.len() < inner.capacity {
+            inner.values.insert(offset, Arc::clone(&value));
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounded() -> Result<(), Box<dyn Error>> {
+        let cache = StringCache::new(1);
+        let first = cache.get_or_read(10, || Ok("US".to_owned()))?;
+        let again = cache.get_or_read(10, || Err("should be cached".into()))?;
+        assert!(Arc::ptr_eq(&first, &again));
+
+        // full, so the second string is read every time
+        cache.get_or_read(20, || Ok("DE".to_owned()))?;
+        assert!(cache.get_or_read(20, || Err("not cached".into())).is_err());
+        assert_eq!(cache.len(), 1);
+
+        cache.set_capacity(0);
+        assert_eq!(cache.len(), 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,219 @@
+// Copyright 2023 IPQualityScore LLC
+
+// Builds small flat file databases for tests, since the real files are not distributed
+// with the source code.
+
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::binary_option as flag;
+
+const COLUMNS: [(&str, u8); 3] = [
+    ("ASN", flag::INT_DATA),
+    ("Country", flag::STRING_DATA),
+    ("ZeroFraudScore", flag::SMALL_INT_DATA),
+];
+// two binary data bytes, connection type/abuse velocity byte, ASN, Country offset, fraud score
+const RECORD_BYTES: usize = 12;
+
+static FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// The values stored for one network
+#[derive(Clone, Copy)]
+pub(crate) struct TestRecord {
+    pub flags: [u8; 3],
+    pub asn: u32,
+    pub country: &'static str,
+    pub fraud_score: u8,
+}
+
+impl TestRecord {
+    pub fn new(asn: u32, country: &'static str) -> TestRecord {
+        TestRecord {
+            flags: [0; 3],
+            asn,
+            country,
+            fraud_score: 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Child {
+    None,
+    Node(usize),
+    Record(usize),
+}
+
+pub(crate) struct TestDatabase {
+    is_v6: bool,
+    is_blacklist: bool,
+    nodes: Vec<[Child; 2]>,
+    records: Vec<TestRecord>,
+}
+
+impl TestDatabase {
+    pub fn ipv4() -> TestDatabase {
+        TestDatabase::new(false)
+    }
+
+    pub fn ipv6() -> TestDatabase {
+        TestDatabase::new(true)
+    }
+
+    fn new(is_v6: bool) -> TestDatabase {
+        TestDatabase {
+            is_v6,
+            is_blacklist: false,
+            nodes: vec![[Child::None; 2]],
+            records: Vec::new(),
+        }
+    }
+
+    pub fn blacklist(mut self) -> TestDatabase {
+        self.is_blacklist = true;
+        self
+    }
+
+    /// Adds a network, e.g. `insert("10.0.0.0".parse()?, 8, record)` for 10.0.0.0/8
+    pub fn insert(
+        mut self,
+        network: IpAddr,
+        prefix_len: usize,
+        record: TestRecord,
+    ) -> TestDatabase {
+        let (bits, width) = match network {
+            IpAddr::V4(ipv4) => (u128::from(u32::from(ipv4)), 32),
+            IpAddr::V6(ipv6) => (u128::from(ipv6), 128),
+        };
+        assert_eq!(
+            self.is_v6,
+            width == 128,
+            "network does not match database type"
+        );
+        self.records.push(record);
+        let mut node = 0;
+        for position in 0..prefix_len {
+            let side = ((bits >> (width - 1 - position)) & 1) as usize;
+            if position == prefix_len - 1 {
+                self.nodes[node][side] = Child::Record(self.records.len() - 1);
+                break;
+            }
+            node = match self.nodes[node][side] {
+                Child::Node(next) => next,
+                _ => {
+                    self.nodes.push([Child::None; 2]);
+                    self.nodes[node][side] = Child::Node(self.nodes.len() - 1);
+                    self.nodes.len() - 1
+                }
+            };
+        }
+        self
+    }
+
+    /// Serializes the database into the flat file format
+    pub fn bytes(&self) -> Vec<u8> {
+        let tree_start = 11 + 24 * COLUMNS.len();
+        let tree_end = tree_start + 5 + 8 * self.nodes.len();
+        let records_start = tree_end;
+        let strings_start = records_start + RECORD_BYTES * self.records.len();
+
+        let mut strings: Vec<u8> = Vec::new();
+        let mut string_offsets: Vec<(&str, usize)> = Vec::new();
+        for record in &self.records {
+            if !string_offsets.iter().any(|(s, _)| *s == record.country) {
+                string_offsets.push((record.country, strings_start + strings.len()));
+                strings.push(record.country.len() as u8);
+                strings.extend_from_slice(record.country.as_bytes());
+            }
+        }
+        let total_bytes = strings_start + strings.len();
+
+        let mut bytes = Vec::with_capacity(total_bytes);
+        // header
+        let mut options = 0b1000_0000; // binary data
+        options |= if self.is_v6 { 0b0000_0010 } else { 0b0000_0001 };
+        if self.is_blacklist {
+            options |= 0b0000_0100;
+        }
+        bytes.push(options);
+        bytes.push(0x01); // version
+        bytes.extend_from_slice(&varint3(tree_start));
+        bytes.extend_from_slice(&[RECORD_BYTES as u8, 0]);
+        bytes.extend_from_slice(&(total_bytes as u32).to_le_bytes());
+        // columns
+        for (name, record_type) in COLUMNS {
+            let mut column = [0u8; 24];
+            column[..name.len()].copy_from_slice(name.as_bytes());
+            column[23] = record_type;
+            bytes.extend_from_slice(&column);
+        }
+        // tree
+        bytes.push(flag::TREE_DATA);
+        bytes.extend_from_slice(&((tree_end - tree_start) as u32).to_le_bytes());
+        for node in &self.nodes {
+            for child in node {
+                let pointer = match *child {
+                    Child::None => 0,
+                    Child::Node(index) => tree_start + 5 + 8 * index,
+                    Child::Record(index) => records_start + RECORD_BYTES * index,
+                };
+                bytes.extend_from_slice(&(pointer as u32).to_le_bytes());
+            }
+        }
+        // records
+        for record in &self.records {
+            let offset = string_offsets
+                .iter()
+                .find(|(s, _)| *s == record.country)
+                .map(|(_, offset)| *offset)
+                .unwrap();
+            bytes.extend_from_slice(&record.flags);
+            bytes.extend_from_slice(&record.asn.to_le_bytes());
+            bytes.extend_from_slice(&(offset as u32).to_le_bytes());
+            bytes.push(record.fraud_score);
+        }
+        bytes.extend_from_slice(&strings);
+
+        bytes
+    }
+
+    /// Writes the database to a temporary file, which is removed when the returned value is dropped
+    pub fn write(&self) -> TestFile {
+        let path = std::env::temp_dir().join(format!(
+            "ipqs-test-{}-{}.ipqs",
+            std::process::id(),
+            FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&path, self.bytes()).unwrap();
+        TestFile { path }
+    }
+}
+
+// variable length integer, padded to three bytes
+fn varint3(value: usize) -> [u8; 3] {
+    assert!(value < 1 << 14);
+    if value < 0x80 {
+        [value as u8, 0, 0]
+    } else {
+        [(value & 0x7f) as u8 | 0x80, (value >> 7) as u8, 0]
+    }
+}
+
+pub(crate) struct TestFile {
+    path: PathBuf,
+}
+
+impl TestFile {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TestFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -7,26 +7,32 @@
 //! For an overview of the flat file database features and to get started with your own
 //! file, please see our
 //! [Flat File IP Address Database Documentation Overview](https://www.ipqualityscore.com/documentation/ip-reputation-database/overview).
+//!
+//! The file format itself is decoded by the `ipqs_db_core` crate, which works without the
+//! standard library. This crate adds the file-based readers on top of it.
 
+pub mod client_ip;
+#[cfg(feature = "ffi")]
+pub mod ffi;
 pub mod file_reader;
-pub use file_reader::record::{Record, Strictness};
+pub mod proxy_protocol;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod reputation_source;
+#[cfg(feature = "tower")]
+pub mod tower;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub use client_ip::TrustedProxies;
+pub use file_reader::cached_reader::CachedReader;
+pub use file_reader::fields::Fields;
+pub use file_reader::metadata::Metadata;
+pub use file_reader::reader_pool::{PooledReader, ReaderPool};
+#[cfg(feature = "json")]
+pub use file_reader::record::ApiResponse;
+pub use file_reader::record::{Record, RecordBuilder, Strictness};
+pub use file_reader::record_ref::RecordRef;
 pub use file_reader::FileReader;
-mod binary_option;
-mod column;
-
-mod utility {
-    // interpret an array of four bytes as a Little Endian unsigned integer
-    pub(crate) fn four_byte_int(bytes: &[u8]) -> u64 {
-        let mut buffer = [0u8; 4];
-        buffer[..4].copy_from_slice(bytes);
-
-        u32::from_le_bytes(buffer) as u64
-    }
-    // interpret an array of four bytes as a 32-bit floating-point number
-    pub(crate) fn four_byte_float(bytes: &[u8]) -> f32 {
-        let mut buffer = [0u8; 4];
-        buffer[..4].copy_from_slice(bytes);
-
-        f32::from_le_bytes(buffer)
-    }
-}
+pub use reputation_source::{DualStack, IpReputationSource, MemorySource};
+// the file format is decoded by the no_std core crate
+use ipqs_db_core::{binary_option, column, utility};
@@ -0,0 +1,441 @@
+// Copyright 2023 IPQualityScore LLC
+//! Reads the PROXY protocol header that HAProxy and other load balancers send at the start of
+//! proxied TCP connections, to look up the client instead of the load balancer. Both the text
+//! (v1) and the binary (v2) headers are read.
+//! ```no_run
+//! use ipqs_db_reader::proxy_protocol::ProxiedStream;
+//! use ipqs_db_reader::{ReaderPool, Strictness};
+//! use std::{error, net::TcpListener, time::Duration};
+//!
+//! let pool = ReaderPool::open("IPQualityScore-IP-Reputation-Database-IPv4.ipqs".as_ref(), 8)?;
+//! let listener = TcpListener::bind("0.0.0.0:4000")?;
+//! for stream in listener.incoming() {
+//!     let stream = stream?;
+//!     // the header is expected right away, so a peer that never sends one is dropped
+//!     stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+//!     let stream = match ProxiedStream::accept(stream, &pool) {
+//!         Ok(stream) => stream,
+//!         Err(_) => continue,
+//!     };
+//!     let fraud_score = stream.record().and_then(|record| record.fraud_score(Strictness::Zero));
+//!     println!("{:?}: fraud score {:?}", stream.client_ip(), fraud_score);
+//!     let stream = stream.into_inner();
+//!     // hand the stream, positioned after the header, to the application
+//! }
+//! # Ok::<(), Box <dyn error::Error>>(())
+//! ```
+//!
+//! Only accept PROXY headers on listeners that the load balancer alone can connect to: the
+//! header is sent by the peer, so anyone else could claim any source address.
+use std::error::Error;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use crate::{IpReputationSource, Record};
+
+const V1_PREFIX: &[u8] = b"PROXY ";
+// the longest v1 header, "PROXY TCP6 <address> <address> <port> <port>\r\n"
+const V1_MAX_LEN: usize = 107;
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+const V2_HEADER_LEN: usize = 16;
+
+/// A PROXY protocol header
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProxyHeader {
+    /// 1 for the text header, 2 for the binary header
+    pub version: u8,
+    /// The address of the client, or None for connections that are not proxied for a TCP or
+    /// UDP client: `PROXY UNKNOWN`, v2 `LOCAL` connections (e.g. health checks of the load
+    /// balancer) and Unix socket addresses.
+    pub source: Option<SocketAddr>,
+    /// The address the client connected to, None when `source` is
+    pub destination: Option<SocketAddr>,
+}
+
+impl ProxyHeader {
+    /// Parses the header at the start of `bytes`. Returns the header and its length, or None if
+    /// `bytes` is a valid start of a header that is not complete yet.
+    pub fn parse(bytes: &[u8]) -> Result<Option<(ProxyHeader, usize)>, Box<dyn Error>> {
+        if bytes.starts_with(V2_SIGNATURE) {
+            parse_v2(bytes)
+        } else if bytes.starts_with(V1_PREFIX) {
+            parse_v1(bytes)
+        } else if V1_PREFIX.starts_with(bytes) || V2_SIGNATURE.starts_with(bytes) {
+            Ok(None)
+        } else {
+            Err("connection does not start with a PROXY protocol header".into())
+        }
+    }
+
+    /// Reads the header at the start of `reader`. No byte after the header is read, so the
+    /// reader can be handed to the application as it is.
+    pub fn read_from(reader: &mut impl Read) -> Result<ProxyHeader, Box<dyn Error>> {
+        let mut bytes = Vec::with_capacity(V1_MAX_LEN);
+        loop {
+            if let Some((header, _)) = ProxyHeader::parse(&bytes)? {
+                return Ok(header);
+            }
+            // the length of a v1 header is only known at its end
+            let wanted = if bytes.len() >= V2_HEADER_LEN && bytes.starts_with(V2_SIGNATURE) {
+                V2_HEADER_LEN + u16::from_be_bytes([bytes[14], bytes[15]]) as usize - bytes.len()
+            } else {
+                1
+            };
+            let start = bytes.len();
+            bytes.resize(start + wanted, 0);
+            reader.read_exact(&mut bytes[start..])?;
+        }
+    }
+
+    /// Returns the address of the client, None if the connection is not proxied for one.
+    /// IPv4-mapped IPv6 addresses, sent by proxies listening on IPv6 sockets, are returned as
+    /// IPv4 addresses.
+    pub fn client_ip(&self) -> Option<IpAddr> {
+        self.source.map(|address| address.ip().to_canonical())
+    }
+}
+
+/// `PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\n`
+fn parse_v1(bytes: &[u8]) -> Result<Option<(ProxyHeader, usize)>, Box<dyn Error>> {
+    let end = match bytes
+        .windows(2)
+        .take(V1_MAX_LEN - 1)
+        .position(|w| w == b"\r\n")
+    {
+        Some(end) => end,
+        None if bytes.len() < V1_MAX_LEN => return Ok(None),
+        None => return Err("PROXY v1 header is too long".into()),
+    };
+    let invalid = || {
+        format!(
+            "invalid PROXY v1 header: {}",
+            String::from_utf8_lossy(&bytes[..end])
+        )
+    };
+    let line = std::str::from_utf8(&bytes[V1_PREFIX.len()..end]).map_err(|_| invalid())?;
+    let fields: Vec<&str> = line.split(' ').collect();
+    let (source, destination) = match fields.as_slice() {
+        // the rest of the line is ignored
+        ["UNKNOWN", ..] => (None, None),
+        [protocol @ ("TCP4" | "TCP6"), source, destination, source_port, destination_port] => {
+            let ipv4 = *protocol == "TCP4";
+            let address = |ip: &str, port: &str| -> Option<SocketAddr> {
+                let ip: IpAddr = if ipv4 {
+                    ip.parse::<Ipv4Addr>().ok()?.into()
+                } else {
+                    ip.parse::<Ipv6Addr>().ok()?.into()
+                };
+                Some(SocketAddr::new(ip, parse_port(port)?))
+            };
+            (
+                Some(address(source, source_port).ok_or_else(invalid)?),
+                Some(address(destination, destination_port).ok_or_else(invalid)?),
+            )
+        }
+        _ => return Err(invalid().into()),
+    };
+    let header = ProxyHeader {
+        version: 1,
+        source,
+        destination,
+    };
+    Ok(Some((header, end + 2)))
+}
+
+// a decimal port without leading zeros
+fn parse_port(port: &str) -> Option<u16> {
+    let digits = port.bytes().all(|b| b.is_ascii_digit());
+    (digits && (port == "0" || !port.starts_with('0'))).then(|| port.parse().ok())?
+}
+
+/// The 12 byte signature, the version and command, the address family and protocol, the
+/// length of the rest, then the addresses and optional TLVs
+fn parse_v2(bytes: &[u8]) -> Result<Option<(ProxyHeader, usize)>, Box<dyn Error>> {
+    if bytes.len() < V2_HEADER_LEN {
+        return Ok(None);
+    }
+    let (version, command) = (bytes[12] >> 4, bytes[12] & 0xF);
+    if version != 2 {
+        return Err(format!("unsupported PROXY protocol version {}", version).into());
+    }
+    let len = V2_HEADER_LEN + u16::from_be_bytes([bytes[14], bytes[15]]) as usize;
+    if bytes.len() < len {
+        return Ok(None);
+    }
+    let addresses = &bytes[V2_HEADER_LEN..len];
+    let (source, destination) = match command {
+        // LOCAL: sent by the proxy itself, the addresses are ignored
+        0x0 => (None, None),
+        // PROXY
+        0x1 => match bytes[13] >> 4 {
+            // AF_INET
+            0x1 => {
+                let [a, b, c, d, e, f, g, h, sp0, sp1, dp0, dp1, ..] = *addresses else {
+                    return Err("PROXY v2 header is too short for IPv4 addresses".into());
+                };
+                (
+                    Some(SocketAddr::new(
+                        Ipv4Addr::new(a, b, c, d).into(),
+                        u16::from_be_bytes([sp0, sp1]),
+                    )),
+                    Some(SocketAddr::new(
+                        Ipv4Addr::new(e, f, g, h).into(),
+                        u16::from_be_bytes([dp0, dp1]),
+                    )),
+                )
+            }
+            // AF_INET6
+            0x2 => {
+                if addresses.len() < 36 {
+                    return Err("PROXY v2 header is too short for IPv6 addresses".into());
+                }
+                let ip = |at: usize| -> IpAddr {
+                    let mut octets = [0; 16];
+                    octets.copy_from_slice(&addresses[at..at + 16]);
+                    Ipv6Addr::from(octets).into()
+                };
+                let port = |at: usize| u16::from_be_bytes([addresses[at], addresses[at + 1]]);
+                (
+                    Some(SocketAddr::new(ip(0), port(32))),
+                    Some(SocketAddr::new(ip(16), port(34))),
+                )
+            }
+            // AF_UNSPEC and AF_UNIX
+            _ => (None, None),
+        },
+        _ => return Err(format!("unsupported PROXY v2 command {}", command).into()),
+    };
+    let header = ProxyHeader {
+        version: 2,
+        source,
+        destination,
+    };
+    Ok(Some((header, len)))
+}
+
+/// A stream whose PROXY header has been read, with the [Record] of its client. Reads and writes
+/// go to the stream, starting after the header.
+#[derive(Debug)]
+pub struct ProxiedStream<S> {
+    stream: S,
+    header: ProxyHeader,
+    record: Option<Record>,
+}
+
+impl<S: Read> ProxiedStream<S> {
+    /// Reads the PROXY header of `stream` and looks up its client in `source`. Returns an error
+    /// if the stream does not start with a valid header. Lookup errors, such as an IPv6 client
+    /// looked up in an IPv4 database, are treated like addresses without a record.
+    pub fn accept(
+        mut stream: S,
+        source: &impl IpReputationSource,
+    ) -> Result<ProxiedStream<S>, Box<dyn Error>> {
+        let header = ProxyHeader::read_from(&mut stream)?;
+        let record = match header.client_ip() {
+            Some(ip) => source.lookup(&ip).unwrap_or(None),
+            None => None,
+        };
+        Ok(ProxiedStream {
+            stream,
+            header,
+            record,
+        })
+    }
+}
+
+impl<S> ProxiedStream<S> {
+    pub fn header(&self) -> &ProxyHeader {
+        &self.header
+    }
+
+    /// Returns the address of the client, None if the connection is not proxied for one
+    pub fn client_ip(&self) -> Option<IpAddr> {
+        self.header.client_ip()
+    }
+
+    /// Returns the record of the client, None if the client has none or is unknown
+    pub fn record(&self) -> Option<&Record> {
+        self.record.as_ref()
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S: Read> Read for ProxiedStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.read(buf)
+    }
+}
+
+impl<S: Write> Write for ProxiedStream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MemorySource, RecordBuilder};
+    use std::io::Cursor;
+
+    fn address(address: &str) -> Option<SocketAddr> {
+        Some(address.parse().unwrap())
+    }
+
+    fn v2(command: u8, family: u8, addresses: &[u8]) -> Vec<u8> {
+        let mut bytes = V2_SIGNATURE.to_vec();
+        bytes.extend([0x20 | command, family]);
+        bytes.extend((addresses.len() as u16).to_be_bytes());
+        bytes.extend(addresses);
+        bytes
+    }
+
+    #[test]
+    fn parses_v1() -> Result<(), Box<dyn Error>> {
+        let (header, len) =
+            ProxyHeader::parse(b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\nGET /")?.unwrap();
+        assert_eq!(len, 45);
+        assert_eq!(header.version, 1);
+        assert_eq!(header.source, address("192.0.2.1:56324"));
+        assert_eq!(header.destination, address("198.51.100.1:443"));
+
+        let line = b"PROXY TCP6 2001:db8::1 2001:db8::2 65535 0\r\n";
+        let (header, _) = ProxyHeader::parse(line)?.unwrap();
+        assert_eq!(header.source, address("[2001:db8::1]:65535"));
+
+        for line in [
+            &b"PROXY UNKNOWN\r\n"[..],
+            b"PROXY UNKNOWN ffff:: ffff:: 1 2\r\n",
+        ] {
+            let (header, len) = ProxyHeader::parse(line)?.unwrap();
+            assert_eq!((header.source, len), (None, line.len()));
+        }
+
+        // incomplete headers
+        for bytes in [
+            &b""[..],
+            b"PRO",
+            b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r",
+        ] {
+            assert!(ProxyHeader::parse(bytes)?.is_none());
+        }
+
+        for line in [
+            &b"GET / HTTP/1.1\r\n"[..],
+            b"PROXY TCP4 2001:db8::1 2001:db8::2 1 2\r\n",
+            b"PROXY TCP6 192.0.2.1 198.51.100.1 1 2\r\n",
+            b"PROXY TCP4 192.0.2.1 198.51.100.1 01 2\r\n",
+            b"PROXY TCP4 192.0.2.1 198.51.100.1 65536 2\r\n",
+            b"PROXY TCP4 192.0.2.1 198.51.100.1 1\r\n",
+            b"PROXY TCP4  192.0.2.1 198.51.100.1 1 2\r\n",
+            b"PROXY UDP4 192.0.2.1 198.51.100.1 1 2\r\n",
+            &[b"PROXY UNKNOWN ".as_slice(), &[b'a'; 100]].concat(),
+        ] {
+            assert!(ProxyHeader::parse(line).is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn parses_v2() -> Result<(), Box<dyn Error>> {
+        let bytes = v2(
+            1,
+            0x11,
+            &[192, 0, 2, 1, 198, 51, 100, 1, 0xDC, 0x04, 0x01, 0xBB],
+        );
+        let (header, len) = ProxyHeader::parse(&bytes)?.unwrap();
+        assert_eq!((header.version, len), (2, 28));
+        assert_eq!(header.source, address("192.0.2.1:56324"));
+        assert_eq!(header.destination, address("198.51.100.1:443"));
+
+        // IPv6 addresses followed by a TLV
+        let mut addresses = "2001:db8::1".parse::<Ipv6Addr>()?.octets().to_vec();
+        addresses.extend("2001:db8::2".parse::<Ipv6Addr>()?.octets());
+        addresses.extend([0, 80, 1, 187, 0x04, 0, 1, 0]);
+        let (header, len) = ProxyHeader::parse(&v2(1, 0x21, &addresses))?.unwrap();
+        assert_eq!(len, 56);
+        assert_eq!(header.source, address("[2001:db8::1]:80"));
+        assert_eq!(header.destination, address("[2001:db8::2]:443"));
+
+        // LOCAL, AF_UNSPEC and AF_UNIX connections
+        for bytes in [
+            v2(0, 0x11, &[0; 12]),
+            v2(1, 0x00, &[]),
+            v2(1, 0x31, &[0; 216]),
+        ] {
+            assert_eq!(ProxyHeader::parse(&bytes)?.unwrap().0.source, None);
+        }
+
+        assert!(ProxyHeader::parse(&bytes[..20])?.is_none());
+        let mut version_1 = bytes.clone();
+        version_1[12] = 0x11;
+        for bytes in [
+            version_1,
+            v2(2, 0x11, &[0; 12]),
+            v2(1, 0x11, &[0; 8]),
+            v2(1, 0x21, &[0; 12]),
+        ] {
+            assert!(ProxyHeader::parse(&bytes).is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn reads_only_the_header() -> Result<(), Box<dyn Error>> {
+        let v2_header = v2(1, 0x11, &[192, 0, 2, 1, 198, 51, 100, 1, 0, 1, 0, 2]);
+        for header in [
+            &b"PROXY UNKNOWN\r\n"[..],
+            b"PROXY TCP4 192.0.2.1 198.51.100.1 1 2\r\n",
+            &v2_header,
+        ] {
+            let mut reader = Cursor::new([header, b"data"].concat());
+            ProxyHeader::read_from(&mut reader)?;
+            assert_eq!(reader.position() as usize, header.len());
+        }
+        let mut truncated = Cursor::new(&v2_header[..20]);
+        assert!(ProxyHeader::read_from(&mut truncated).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn accepts_streams() -> Result<(), Box<dyn Error>> {
+        let mut source = MemorySource::new();
+        source.insert(
+            "192.0.2.0".parse()?,
+            24,
+            RecordBuilder::new().asn(64500).build(),
+        );
+
+        let bytes = b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\nhello".as_slice();
+        let mut stream = ProxiedStream::accept(bytes, &source)?;
+        assert_eq!(stream.client_ip(), Some("192.0.2.1".parse()?));
+        assert_eq!(stream.record().and_then(Record::asn), Some(64500));
+        let mut data = String::new();
+        stream.read_to_string(&mut data)?;
+        assert_eq!(data, "hello");
+
+        let bytes = b"PROXY TCP6 ::ffff:192.0.2.1 2001:db8::2 56324 443\r\n".as_slice();
+        let stream = ProxiedStream::accept(bytes, &source)?;
+        assert_eq!(stream.client_ip(), Some("192.0.2.1".parse()?));
+        assert_eq!(stream.record().and_then(Record::asn), Some(64500));
+
+        // clients without a record, or with a failed lookup
+        let bytes = b"PROXY TCP6 2001:db8::1 2001:db8::2 1 2\r\n".as_slice();
+        let stream = ProxiedStream::accept(bytes, &source)?;
+        assert!(stream.client_ip().is_some() && stream.record().is_none());
+
+        assert!(ProxiedStream::accept(b"hello".as_slice(), &source).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,336 @@
+// Copyright 2023 IPQualityScore LLC
+//! Python bindings, built with the `python` feature into an extension module named
+//! `ipqs_db_reader` (e.g. `maturin build --release`, configured by `pyproject.toml`):
+//!
+//! ```python
+//! import ipqs_db_reader
+//!
+//! reader = ipqs_db_reader.Reader("IPQualityScore-IP-Reputation-Database-IPv4.ipqs")
+//! record = reader.lookup("8.8.8.8")  # a dict, or None without a record
+//! records = reader.lookup_many(addresses)  # the GIL is released during the lookups
+//! for network, record in reader.networks():
+//!     print(network, record["country"])
+//! ```
+//!
+//! Records are dicts with the field names of the JSON format of [Record], except that the
+//! fraud scores are `fraud_score_0` to `fraud_score_3`. Addresses can be strings or
+//! `ipaddress` objects.
+use std::error::Error;
+use std::io;
+use std::net::IpAddr;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyOSError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use crate::file_reader::{is_not_found, Networks};
+use crate::{FileReader, Metadata, ReaderPool, Record, Strictness};
+
+create_exception!(
+    ipqs_db_reader,
+    DatabaseError,
+    PyException,
+    "The database file is invalid, the message gives the EID of the error."
+);
+
+/// Errors of lookups made without the GIL, which cannot hold a `Box<dyn Error>`
+enum LookupError {
+    Io(String),
+    Database(String),
+}
+
+impl LookupError {
+    fn new(error: &(dyn Error + 'static)) -> LookupError {
+        if error.is::<io::Error>() {
+            LookupError::Io(error.to_string())
+        } else {
+            LookupError::Database(error.to_string())
+        }
+    }
+}
+
+impl From<LookupError> for PyErr {
+    fn from(error: LookupError) -> PyErr {
+        match error {
+            LookupError::Io(message) => PyOSError::new_err(message),
+            LookupError::Database(message) => DatabaseError::new_err(message),
+        }
+    }
+}
+
+fn to_py_err(error: Box<dyn Error>) -> PyErr {
+    LookupError::new(error.as_ref()).into()
+}
+
+/// Reads an IPQS flat file database. `Reader(path)` reads the whole file into memory and
+/// indexes it; `Reader(path, in_memory=False)` reads records from the file as they are
+/// looked up. A reader can be shared by threads, which look up addresses at the same time with
+/// up to one reader per core.
+#[pyclass(name = "Reader", module = "ipqs_db_reader")]
+#[derive(Debug)]
+pub struct PyReader {
+    pool: ReaderPool,
+    // read from the header once, so that neither needs a reader of the pool
+    metadata: Metadata,
+}
+
+impl PyReader {
+    fn check_ip_version(&self, ip: &IpAddr) -> PyResult<()> {
+        if ip.is_ipv6() != self.metadata.is_ipv6 {
+            return Err(PyValueError::new_err(format!(
+                "{} does not match the IP version of the database",
+                ip
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn lookup(reader: &mut FileReader, ip: &IpAddr) -> Result<Option<Record>, LookupError> {
+    match reader.fetch(ip) {
+        Ok(record) => Ok(Some(record)),
+        Err(error) if is_not_found(error.as_ref()) => Ok(None),
+        Err(error) => Err(LookupError::new(error.as_ref())),
+    }
+}
+
+#[pymethods]
+impl PyReader {
+    #[new]
+    #[pyo3(signature = (path, in_memory = true))]
+    fn new(py: Python<'_>, path: PathBuf, in_memory: bool) -> PyResult<PyReader> {
+        let reader = py
+            .detach(|| {
+                if !in_memory {
+                    return FileReader::open(&path).map_err(|error| LookupError::new(&*error));
+                }
+                let mut reader =
+                    FileReader::open_in_memory(&path).map_err(|error| LookupError::new(&*error))?;
+                reader
+                    .build_index()
+                    .map_err(|error| LookupError::new(&*error))?;
+                Ok(reader)
+            })
+            .map_err(PyErr::from)?;
+        let metadata = reader.metadata();
+        let size = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        let pool = ReaderPool::new(reader, size).map_err(to_py_err)?;
+        // the reader keeps the file it was opened with, like a FileReader
+        pool.set_reload_interval(Duration::MAX);
+        Ok(PyReader { pool, metadata })
+    }
+
+    /// Returns the record of `ip` as a dict, or None if the database has no record for it
+    fn lookup<'py>(&self, py: Python<'py>, ip: IpAddr) -> PyResult<Option<Bound<'py, PyDict>>> {
+        self.check_ip_version(&ip)?;
+        let record = py.detach(|| {
+            let mut reader = self.pool.get().map_err(|error| LookupError::new(&*error))?;
+            lookup(&mut reader, &ip)
+        })?;
+        record.map(|record| record_dict(py, &record)).transpose()
+    }
+
+    /// Looks up every address of `ips` without holding the GIL, and returns a list with the
+    /// record of each address, or None for addresses without a record
+    fn lookup_many<'py>(
+        &self,
+        py: Python<'py>,
+        ips: Vec<IpAddr>,
+    ) -> PyResult<Vec<Option<Bound<'py, PyDict>>>> {
+        for ip in &ips {
+            self.check_ip_version(ip)?;
+        }
+        let records = py.detach(|| {
+            let mut reader = self.pool.get().map_err(|error| LookupError::new(&*error))?;
+            ips.iter()
+                .map(|ip| lookup(&mut reader, ip))
+                .collect::<Result<Vec<_>, _>>()
+        })?;
+        records
+            .iter()
+            .map(|record| {
+                record
+                    .as_ref()
+                    .map(|record| record_dict(py, record))
+                    .transpose()
+            })
+            .collect()
+    }
+
+    /// Returns an iterator over the networks of the database, in address order, as
+    /// `(ipaddress.ip_network, dict)` pairs
+    fn networks(&self, py: Python<'_>) -> PyResult<PyNetworks> {
+        let networks = py.detach(|| {
+            let reader = self.pool.get().map_err(|error| LookupError::new(&*error))?;
+            reader.networks().map_err(|error| LookupError::new(&*error))
+        })?;
+        Ok(PyNetworks {
+            networks: Mutex::new(networks),
+        })
+    }
+
+    /// Returns the details of the file given in its header as a dict
+    fn metadata<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let metadata = &self.metadata;
+        let dict = PyDict::new(py);
+        dict.set_item("is_ipv6", metadata.is_ipv6)?;
+        dict.set_item("is_blacklist", metadata.is_blacklist)?;
+        dict.set_item("binary_data", metadata.binary_data)?;
+        dict.set_item("columns", &metadata.columns)?;
+        dict.set_item("record_bytes", metadata.record_bytes)?;
+        dict.set_item("tree_bytes", metadata.tree_bytes)?;
+        Ok(dict)
+    }
+}
+
+/// Iterator returned by `Reader.networks()`
+#[pyclass(name = "Networks", module = "ipqs_db_reader")]
+#[derive(Debug)]
+pub struct PyNetworks {
+    networks: Mutex<Networks>,
+}
+
+#[pymethods]
+impl PyNetworks {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__<'py>(
+        &self,
+        py: Python<'py>,
+    ) -> PyResult<Option<(Bound<'py, PyAny>, Bound<'py, PyDict>)>> {
+        let next = self
+            .networks
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .next();
+        let (ip, prefix_len, record) = match next {
+            Some(network) => network.map_err(to_py_err)?,
+            None => return Ok(None),
+        };
+        let network = py
+            .import("ipaddress")?
+            .call_method1("ip_network", (format!("{}/{}", ip, prefix_len),))?;
+        Ok(Some((network, record_dict(py, &record)?)))
+    }
+}
+
+fn record_dict<'py>(py: Python<'py>, record: &Record) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    dict.set_item("is_proxy", record.is_proxy())?;
+    dict.set_item("is_vpn", record.is_vpn())?;
+    dict.set_item("is_tor", record.is_tor())?;
+    dict.set_item("is_crawler", record.is_crawler())?;
+    dict.set_item("is_bot", record.is_bot())?;
+    dict.set_item("recent_abuse", record.recent_abuse())?;
+    dict.set_item("is_blacklisted", record.is_blacklisted())?;
+    dict.set_item("is_private", record.is_private())?;
+    dict.set_item("is_mobile", record.is_mobile())?;
+    dict.set_item("has_open_ports", record.has_open_ports())?;
+    dict.set_item("is_hosting_provider", record.is_hosting_provider())?;
+    dict.set_item("active_vpn", record.active_vpn())?;
+    dict.set_item("active_tor", record.active_tor())?;
+    dict.set_item("public_access_point", record.public_access_point())?;
+    dict.set_item("connection_type", record.connection_type())?;
+    dict.set_item("abuse_velocity", record.abuse_velocity())?;
+    dict.set_item("country", record.country())?;
+    dict.set_item("city", record.city())?;
+    dict.set_item("region", record.region())?;
+    dict.set_item("isp", record.isp())?;
+    dict.set_item("organization", record.organization())?;
+    dict.set_item("asn", record.asn())?;
+    dict.set_item("timezone", record.timezone())?;
+    dict.set_item("latitude", record.latitude())?;
+    dict.set_item("longitude", record.longitude())?;
+    for (name, strictness) in [
+        ("fraud_score_0", Strictness::Zero),
+        ("fraud_score_1", Strictness::One),
+        ("fraud_score_2", Strictness::Two),
+        ("fraud_score_3", Strictness::Three),
+    ] {
+        dict.set_item(name, record.fraud_score(strictness))?;
+    }
+    Ok(dict)
+}
+
+#[pymodule]
+fn ipqs_db_reader(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyReader>()?;
+    module.add_class::<PyNetworks>()?;
+    module.add("DatabaseError", module.py().get_type::<DatabaseError>())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_reader::test_database::{TestDatabase, TestRecord};
+
+    #[test]
+    fn lookups() {
+        let file = TestDatabase::ipv4()
+            .insert("10.0.0.0".parse().unwrap(), 8, TestRecord::new(1, "AA"))
+            .insert("11.0.0.0".parse().unwrap(), 8, TestRecord::new(2, "BB"))
+            .write();
+        Python::initialize();
+        Python::attach(|py| {
+            let reader = PyReader::new(py, file.path().to_owned(), true).unwrap();
+            let record = reader.lookup(py, "10.1.2.3".parse().unwrap()).unwrap();
+            let asn: u64 = record
+                .unwrap()
+                .get_item("asn")
+                .unwrap()
+                .unwrap()
+                .extract()
+                .unwrap();
+            assert_eq!(asn, 1);
+            assert!(reader
+                .lookup(py, "9.0.0.1".parse().unwrap())
+                .unwrap()
+                .is_none());
+            assert!(reader.lookup(py, "::1".parse().unwrap()).is_err());
+
+            let records = reader
+                .lookup_many(
+                    py,
+                    vec!["11.0.0.1".parse().unwrap(), "9.0.0.1".parse().unwrap()],
+                )
+                .unwrap();
+            assert_eq!(records.len(), 2);
+            assert!(records[0].is_some() && records[1].is_none());
+
+            let networks = reader.networks(py).unwrap();
+            let (network, record) = networks.__next__(py).unwrap().unwrap();
+            assert_eq!(network.str().unwrap().to_string(), "10.0.0.0/8");
+            let country: String = record
+                .get_item("country")
+                .unwrap()
+                .unwrap()
+                .extract()
+                .unwrap();
+            assert_eq!(country, "AA");
+            assert!(networks.__next__(py).unwrap().is_some());
+            assert!(networks.__next__(py).unwrap().is_none());
+
+            let metadata = reader.metadata(py).unwrap();
+            let is_ipv6: bool = metadata
+                .get_item("is_ipv6")
+                .unwrap()
+                .unwrap()
+                .extract()
+                .unwrap();
+            assert!(!is_ipv6);
+
+            assert!(PyReader::new(py, PathBuf::from("/nonexistent.ipqs"), true)
+                .unwrap_err()
+                .is_instance_of::<PyOSError>(py));
+        });
+    }
+}
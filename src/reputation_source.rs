@@ -0,0 +1,383 @@
+// Copyright 2023 IPQualityScore LLC
+//! A common interface for everything that can look up the reputation of an IP address, so that
+//! application code can switch between a [FileReader], a [CachedReader], a [ReaderPool] or a
+//! [MemorySource] in tests.
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::file_reader::{is_not_found, sendable};
+use crate::{CachedReader, FileReader, ReaderPool, Record};
+
+/// Looks up the [Record] of an IP address. Lookups only need a shared reference, so a source
+/// can be shared between threads behind an `Arc`.
+///
+/// [FileReader] and [CachedReader] need exclusive access to look up an address, so they are
+/// sources when wrapped in a `Mutex`, which serializes the lookups. Use a [ReaderPool] for
+/// lookups from several threads at once.
+/// ```
+/// use ipqs_db_reader::{IpReputationSource, MemorySource, RecordBuilder};
+/// use std::{error, net::IpAddr};
+///
+/// fn is_risky(
+///     source: &impl IpReputationSource,
+///     ip: IpAddr,
+/// ) -> Result<bool, Box<dyn error::Error + Send + Sync>> {
+///     Ok(source.lookup(&ip)?.and_then(|record| record.is_vpn()).unwrap_or(false))
+/// }
+///
+/// let mut source = MemorySource::new();
+/// source.insert("10.0.0.0".parse()?, 8, RecordBuilder::new().is_vpn(true).build());
+/// source.insert("11.0.0.0".parse()?, 8, RecordBuilder::new().is_vpn(false).build());
+/// assert!(is_risky(&source, "10.1.2.3".parse()?)?);
+/// assert!(!is_risky(&source, "11.1.2.3".parse()?)?);
+/// # Ok::<(), Box <dyn error::Error + Send + Sync>>(())
+/// ```
+pub trait IpReputationSource {
+    /// Returns the record for `ip`, or None if the source has no record for it. Errors are
+    /// reserved for lookups that could not be made, e.g. an unreadable file or an address of
+    /// the wrong IP version, and can be sent to another thread.
+    fn lookup(&self, ip: &IpAddr) -> Result<Option<Record>, Box<dyn Error + Send + Sync>>;
+}
+
+impl IpReputationSource for Mutex<FileReader> {
+    fn lookup(&self, ip: &IpAddr) -> Result<Option<Record>, Box<dyn Error + Send + Sync>> {
+        not_found_as_none(lock(self)?.fetch(ip))
+    }
+}
+
+impl IpReputationSource for Mutex<CachedReader> {
+    fn lookup(&self, ip: &IpAddr) -> Result<Option<Record>, Box<dyn Error + Send + Sync>> {
+        not_found_as_none(lock(self)?.fetch(ip))
+    }
+}
+
+impl IpReputationSource for ReaderPool {
+    /// Borrows a reader from the pool for the lookup, waiting for one if all are in use
+    fn lookup(&self, ip: &IpAddr) -> Result<Option<Record>, Box<dyn Error + Send + Sync>> {
+        let mut reader = self.get().map_err(sendable)?;
+        not_found_as_none(reader.fetch(ip))
+    }
+}
+
+impl<T: IpReputationSource + ?Sized> IpReputationSource for &T {
+    fn lookup(&self, ip: &IpAddr) -> Result<Option<Record>, Box<dyn Error + Send + Sync>> {
+        (**self).lookup(ip)
+    }
+}
+
+impl<T: IpReputationSource + ?Sized> IpReputationSource for Box<T> {
+    fn lookup(&self, ip: &IpAddr) -> Result<Option<Record>, Box<dyn Error + Send + Sync>> {
+        (**self).lookup(ip)
+    }
+}
+
+impl<T: IpReputationSource + ?Sized> IpReputationSource for Arc<T> {
+    fn lookup(&self, ip: &IpAddr) -> Result<Option<Record>, Box<dyn Error + Send + Sync>> {
+        (**self).lookup(ip)
+    }
+}
+
+fn lock<T>(reader: &Mutex<T>) -> Result<MutexGuard<'_, T>, Box<dyn Error + Send + Sync>> {
+    // a reader that panicked in the middle of a lookup may be left anywhere in its file
+    reader
+        .lock()
+        .map_err(|_| "reader was poisoned by a panic during a lookup".into())
+}
+
+fn not_found_as_none(
+    result: Result<Record, Box<dyn Error>>,
+) -> Result<Option<Record>, Box<dyn Error + Send + Sync>> {
+    match result {
+        Ok(record) => Ok(Some(record)),
+        Err(error) if is_not_found(&*error) => Ok(None),
+        Err(error) => Err(sendable(error)),
+    }
+}
+
+/// Combines a source of IPv4 records with a source of IPv6 records, since each database file
+/// only holds one IP version
+/// ```no_run
+/// # use std::path::PathBuf;
+/// use ipqs_db_reader::{DualStack, IpReputationSource, ReaderPool};
+/// use std::error;
+/// # let mut ipv4_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+/// # ipv4_path.push("resources/IPQualityScore-IP-Reputation-Database-IPv4.ipqs");
+/// # let mut ipv6_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+/// # ipv6_path.push("resources/IPQualityScore-IP-Reputation-Database-IPv6.ipqs");
+/// let source = DualStack::new(ReaderPool::open(&ipv4_path, 8)?, ReaderPool::open(&ipv6_path, 8)?);
+/// let record = source.lookup(&"8.8.0.0".parse()?);
+/// let record = source.lookup(&"2001:4860:4860::8844".parse()?);
+/// # Ok::<(), Box <dyn error::Error>>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct DualStack<V4, V6> {
+    pub ipv4: V4,
+    pub ipv6: V6,
+}
+
+impl<V4, V6> DualStack<V4, V6> {
+    pub fn new(ipv4: V4, ipv6: V6) -> DualStack<V4, V6> {
+        DualStack { ipv4, ipv6 }
+    }
+}
+
+impl<V4: IpReputationSource, V6: IpReputationSource> IpReputationSource for DualStack<V4, V6> {
+    fn lookup(&self, ip: &IpAddr) -> Result<Option<Record>, Box<dyn Error + Send + Sync>> {
+        match ip {
+            IpAddr::V4(_) => self.ipv4.lookup(ip),
+            IpAddr::V6(_) => self.ipv6.lookup(ip),
+        }
+    }
+}
+
+/// A source backed by a map of networks held in memory, for tests. It answers like a database
+/// file (that is not a blacklist): the most specific network containing an address is used,
+/// and an address outside every network gets the record of the closest network before it.
+/// Addresses before every network of their IP version have no record.
+#[derive(Clone, Debug, Default)]
+pub struct MemorySource {
+    // disjoint ranges of addresses keyed by IP version and first address, like the leaves of
+    // the tree of a file. A network inserted inside another one splits it.
+    ranges: BTreeMap<(bool, u128), Range>,
+}
+
+#[derive(Clone, Debug)]
+struct Range {
+    last: u128,
+    // of the network the range was inserted for
+    prefix_len: u32,
+    record: Record,
+}
+
+impl MemorySource {
+    pub fn new() -> MemorySource {
+        MemorySource::default()
+    }
+
+    /// Adds the network of `prefix_len` bits starting at `network`, replacing any record
+    /// previously added for the same network. Use a prefix length of 32 (IPv4) or 128 (IPv6)
+    /// for a single address.
+    pub fn insert(&mut self, network: IpAddr, prefix_len: u32, record: Record) {
+        let (is_v6, bits) = bits(&network);
+        let prefix_len = prefix_len.min(width(is_v6));
+        let first = bits & mask(is_v6, prefix_len);
+        let last = first | (mask(is_v6, width(is_v6)) & !mask(is_v6, prefix_len));
+
+        // ranges of more specific networks are kept, the others lose what the network covers
+        let overlapping: Vec<u128> = self
+            .ranges
+            .range((is_v6, 0)..=(is_v6, last))
+            .rev()
+            .take_while(|(_, range)| range.last >= first)
+            .map(|(&(_, start), _)| start)
+            .collect();
+        let mut kept = Vec::new();
+        for start in overlapping.into_iter().rev() {
+            let range = self.ranges.remove(&(is_v6, start)).unwrap();
+            if range.prefix_len > prefix_len {
+                kept.push((start, range.last));
+                self.ranges.insert((is_v6, start), range);
+                continue;
+            }
+            if start < first {
+                let before = Range {
+                    last: first - 1,
+                    ..range.clone()
+                };
+                self.ranges.insert((is_v6, start), before);
+            }
+            if range.last > last {
+                self.ranges.insert((is_v6, last + 1), range);
+            }
+        }
+
+        // the network fills the gaps between the ranges kept
+        let mut next = Some(first);
+        for (start, end) in kept {
+            if let Some(gap) = next.filter(|&gap| gap < start) {
+                self.insert_range(is_v6, gap, start - 1, prefix_len, &record);
+            }
+            next = end.checked_add(1);
+        }
+        if let Some(gap) = next.filter(|&gap| gap <= last) {
+            self.insert_range(is_v6, gap, last, prefix_len, &record);
+        }
+    }
+
+    fn insert_range(
+        &mut self,
+        is_v6: bool,
+        first: u128,
+        last: u128,
+        prefix_len: u32,
+        record: &Record,
+    ) {
+        let range = Range {
+            last,
+            prefix_len,
+            record: record.clone(),
+        };
+        self.ranges.insert((is_v6, first), range);
+    }
+}
+
+impl IpReputationSource for MemorySource {
+    fn lookup(&self, ip: &IpAddr) -> Result<Option<Record>, Box<dyn Error + Send + Sync>> {
+        let (is_v6, bits) = bits(ip);
+        // the range containing the address, or else the closest one before it
+        Ok(self
+            .ranges
+            .range((is_v6, 0)..=(is_v6, bits))
+            .next_back()
+            .map(|(_, range)| range.record.clone()))
+    }
+}
+
+// IPv4 addresses are kept in the low 32 bits
+fn bits(ip: &IpAddr) -> (bool, u128) {
+    match ip {
+        IpAddr::V4(ipv4) => (false, u128::from(u32::from(*ipv4))),
+        IpAddr::V6(ipv6) => (true, u128::from(*ipv6)),
+    }
+}
+
+fn width(is_v6: bool) -> u32 {
+    if is_v6 {
+        128
+    } else {
+        32
+    }
+}
+
+fn mask(is_v6: bool, prefix_len: u32) -> u128 {
+    let width = width(is_v6);
+    let host_bits = width - prefix_len.min(width);
+    let all = u128::MAX >> (128 - width);
+    all & !u128::MAX.checked_shr(128 - host_bits).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RecordBuilder;
+
+    #[test]
+    fn memory_source() -> Result<(), Box<dyn Error>> {
+        let mut source = MemorySource::new();
+        source.insert("10.1.2.3".parse()?, 32, RecordBuilder::new().asn(3).build());
+        source.insert("10.0.0.0".parse()?, 8, RecordBuilder::new().asn(1).build());
+        source.insert("10.1.0.0".parse()?, 16, RecordBuilder::new().asn(2).build());
+        source.insert(
+            "2001:db8::".parse()?,
+            32,
+            RecordBuilder::new().asn(4).build(),
+        );
+
+        let asn = |source: &MemorySource, ip: &str| -> Option<u64> {
+            source.lookup(&ip.parse().unwrap()).unwrap()?.asn()
+        };
+        // the most specific network, whatever the order they were inserted in
+        assert_eq!(asn(&source, "10.200.0.1"), Some(1));
+        assert_eq!(asn(&source, "10.0.0.0"), Some(1));
+        assert_eq!(asn(&source, "10.1.0.1"), Some(2));
+        assert_eq!(asn(&source, "10.1.2.3"), Some(3));
+        assert_eq!(asn(&source, "10.1.2.4"), Some(2));
+        assert_eq!(asn(&source, "10.2.0.0"), Some(1));
+        assert_eq!(asn(&source, "2001:db8:1::1"), Some(4));
+        // outside every network, the closest network before the address
+        assert_eq!(asn(&source, "11.0.0.1"), Some(1));
+        assert_eq!(asn(&source, "2001:db9::1"), Some(4));
+        assert_eq!(asn(&source, "9.255.255.255"), None);
+        assert_eq!(asn(&source, "::a01:203"), None);
+
+        source.insert("10.1.0.0".parse()?, 16, RecordBuilder::new().asn(5).build());
+        assert_eq!(asn(&source, "10.1.0.1"), Some(5));
+        assert_eq!(asn(&source, "10.1.2.3"), Some(3));
+        source.insert("0.0.0.0".parse()?, 0, RecordBuilder::new().asn(6).build());
+        assert_eq!(asn(&source, "9.0.0.1"), Some(6));
+        assert_eq!(asn(&source, "255.255.255.255"), Some(6));
+        assert_eq!(asn(&source, "10.1.0.1"), Some(5));
+        Ok(())
+    }
+
+    #[test]
+    fn memory_source_answers_like_a_file() -> Result<(), Box<dyn Error>> {
+        use crate::file_reader::test_database::{TestDatabase, TestRecord};
+
+        let networks = [
+            ("10.0.0.0", 8, 1),
+            ("172.16.0.0", 12, 2),
+            ("192.168.0.0", 16, 3),
+        ];
+        let mut database = TestDatabase::ipv4();
+        let mut source = MemorySource::new();
+        for (network, prefix_len, asn) in networks {
+            database = database.insert(network.parse()?, prefix_len, TestRecord::new(asn, "AA"));
+            let record = RecordBuilder::new().asn(u64::from(asn)).build();
+            source.insert(network.parse()?, prefix_len as u32, record);
+        }
+        let file = Mutex::new(FileReader::from_bytes(database.bytes())?);
+        for ip in [
+            "9.0.0.1",
+            "10.0.0.1",
+            "10.255.255.255",
+            "11.0.0.1",
+            "172.31.0.1",
+            "172.32.0.0",
+            "255.0.0.1",
+        ] {
+            let ip = ip.parse()?;
+            let asn = |source: &dyn IpReputationSource| source.lookup(&ip).unwrap()?.asn();
+            assert_eq!(asn(&source), asn(&file), "{}", ip);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn file_reader_has_no_record() -> Result<(), Box<dyn Error>> {
+        use crate::file_reader::test_database::{TestDatabase, TestRecord};
+
+        let database =
+            TestDatabase::ipv4().insert("10.0.0.0".parse()?, 8, TestRecord::new(1, "AA"));
+        let ipv4 = Mutex::new(FileReader::from_bytes(database.bytes())?);
+        let blacklist = database.blacklist();
+        let sources: Vec<Box<dyn IpReputationSource>> = vec![
+            Box::new(DualStack::new(ipv4, MemorySource::new())),
+            Box::new(Mutex::new(FileReader::from_bytes(blacklist.bytes())?)),
+        ];
+        for source in &sources {
+            assert_eq!(
+                source
+                    .lookup(&"10.0.0.1".parse()?)
+                    .unwrap()
+                    .and_then(|r| r.asn()),
+                Some(1)
+            );
+            assert!(source.lookup(&"9.0.0.1".parse()?).unwrap().is_none());
+        }
+        // the wrong IP version is still an error
+        assert!(sources[1].lookup(&"::1".parse()?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn shared_between_threads() -> Result<(), Box<dyn Error>> {
+        let mut source = MemorySource::new();
+        source.insert("10.0.0.0".parse()?, 8, RecordBuilder::new().asn(1).build());
+        let source: Arc<dyn IpReputationSource + Send + Sync> = Arc::new(source);
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let source = Arc::clone(&source);
+                // errors can be sent back from the thread too
+                std::thread::spawn(move || source.lookup(&"10.0.0.1".parse().unwrap()))
+            })
+            .collect();
+        for thread in threads {
+            let record = thread.join().unwrap().unwrap();
+            assert_eq!(record.and_then(|r| r.asn()), Some(1));
+        }
+        Ok(())
+    }
+}
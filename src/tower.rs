@@ -0,0 +1,501 @@
+// Copyright 2023 IPQualityScore LLC
+//! Tower middleware, built with the `tower` feature, that looks up the reputation of the client
+//! of every request. [ReputationLayer] inserts the [ClientIp] and, if the database has one, the
+//! [Record](crate::Record) of the client into the request extensions. With the `axum` feature, handlers get
+//! them through the [Reputation] and [ClientIp] extractors:
+//! ```no_run
+//! # #[cfg(feature = "axum")]
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use axum::{routing::get, Router};
+//! use ipqs_db_reader::tower::{Reputation, ReputationLayer};
+//! use ipqs_db_reader::{ReaderPool, Strictness};
+//!
+//! async fn handler(Reputation(record): Reputation) -> String {
+//!     match record.and_then(|record| record.fraud_score(Strictness::Zero)) {
+//!         Some(fraud_score) => format!("fraud score {}", fraud_score),
+//!         None => "no record".to_owned(),
+//!     }
+//! }
+//!
+//! let pool = ReaderPool::open("IPQualityScore-IP-Reputation-Database-IPv4.ipqs".as_ref(), 8)?;
+//! let app: Router = Router::new()
+//!     .route("/", get(handler))
+//!     // requests from the load balancer are attributed to the client it forwards them for
+//!     .layer(ReputationLayer::new(pool).trust_proxy("10.0.0.0".parse()?, 8));
+//! // served with app.into_make_service_with_connect_info::<SocketAddr>()
+//! # Ok(())
+//! # }
+//! # #[cfg(not(feature = "axum"))]
+//! # fn main() {}
+//! ```
+//!
+//! The client is the peer address of the connection: the `ConnectInfo<SocketAddr>` extension
+//! of axum, or a `SocketAddr` extension inserted by the server. When the peer is a trusted
+//! proxy, the client is found in the header the proxies write, `X-Forwarded-For` unless set
+//! otherwise with [ReputationLayer::trusted_proxies], as described in [crate::client_ip].
+//! Requests whose client cannot be told, e.g. a request from a trusted proxy without the
+//! header, get a [ClientIpError] instead, which the extractors reject with a 400 status.
+//! Requests without a peer address get no extension at all, which the extractors reject with
+//! a 500 status since the app is not set up to find clients.
+//!
+//! Lookups block the task, so the source should be quick to query, e.g. a [ReaderPool] of
+//! readers held in memory with an index. Lookup errors, such as an IPv6 client looked up in an
+//! IPv4 database, are treated like addresses without a record.
+//!
+//! [ReaderPool]: crate::ReaderPool
+use std::error::Error;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use http::header::{HeaderName, FORWARDED};
+use http::Request;
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::client_ip::{IpNetwork, ProxyHeaders, TrustedProxies};
+use crate::IpReputationSource;
+
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+const X_REAL_IP: HeaderName = HeaderName::from_static("x-real-ip");
+
+/// The address of the client of a request, inserted into the request extensions by
+/// [ReputationLayer]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ClientIp(pub IpAddr);
+
+/// Why the client of a request could not be told, inserted into the request extensions by
+/// [ReputationLayer] instead of a [ClientIp]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClientIpError(pub String);
+
+impl fmt::Display for ClientIpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for ClientIpError {}
+
+/// Looks up the client of every request in a shared source, see the [module](self)
+/// documentation. Every service the layer wraps shares the source, so it should allow
+/// lookups from several threads at once, like a [ReaderPool](crate::ReaderPool) or a
+/// [DualStack](crate::DualStack) of them.
+#[derive(Debug)]
+pub struct ReputationLayer<S> {
+    source: Arc<S>,
+    trusted_proxies: Arc<TrustedProxies>,
+}
+
+impl<S> ReputationLayer<S> {
+    pub fn new(source: S) -> ReputationLayer<S> {
+        ReputationLayer::shared(Arc::new(source))
+    }
+
+    /// Looks up clients in a source that is also used elsewhere
+    pub fn shared(source: Arc<S>) -> ReputationLayer<S> {
+        ReputationLayer {
+            source,
+            trusted_proxies: Arc::new(TrustedProxies::default()),
+        }
+    }
+
+    /// Trusts the forwarding headers of peers in the network of `prefix_len` bits starting at
+    /// `network`, e.g. the subnet of the load balancers. Use a prefix length of 32 (IPv4) or
+    /// 128 (IPv6) for a single proxy.
+    pub fn trust_proxy(mut self, network: IpAddr, prefix_len: u32) -> ReputationLayer<S> {
+        Arc::make_mut(&mut self.trusted_proxies).push(IpNetwork::new(network, prefix_len));
+        self
+    }
+
+    /// Replaces the trusted proxies, and the header they write, with `trusted_proxies`
+    pub fn trusted_proxies(mut self, trusted_proxies: TrustedProxies) -> ReputationLayer<S> {
+        self.trusted_proxies = Arc::new(trusted_proxies);
+        self
+    }
+
+    /// Returns the address of the client, or why it cannot be told. None if the request has no
+    /// peer address.
+    fn client_ip<B>(&self, request: &Request<B>) -> Option<Result<IpAddr, Box<dyn Error>>> {
+        let peer = peer_ip(request)?;
+        let mut proxy_headers = ProxyHeaders::new();
+        // values that are not ASCII cannot be parsed, so they are kept as invalid addresses
+        let values = |name| {
+            request
+                .headers()
+                .get_all(name)
+                .iter()
+                .map(|value| value.to_str().unwrap_or(""))
+        };
+        for value in values(FORWARDED) {
+            proxy_headers = proxy_headers.forwarded(value);
+        }
+        for value in values(X_FORWARDED_FOR) {
+            proxy_headers = proxy_headers.x_forwarded_for(value);
+        }
+        for value in values(X_REAL_IP) {
+            proxy_headers = proxy_headers.x_real_ip(value);
+        }
+        Some(self.trusted_proxies.client_ip(peer, &proxy_headers))
+    }
+}
+
+// not derived, which would require S: Clone
+impl<S> Clone for ReputationLayer<S> {
+    fn clone(&self) -> ReputationLayer<S> {
+        ReputationLayer {
+            source: Arc::clone(&self.source),
+            trusted_proxies: Arc::clone(&self.trusted_proxies),
+        }
+    }
+}
+
+impl<S, I> Layer<I> for ReputationLayer<S> {
+    type Service = ReputationService<S, I>;
+
+    fn layer(&self, inner: I) -> ReputationService<S, I> {
+        ReputationService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// The service returned by [ReputationLayer]
+#[derive(Debug)]
+pub struct ReputationService<S, I> {
+    inner: I,
+    layer: ReputationLayer<S>,
+}
+
+impl<S, I: Clone> Clone for ReputationService<S, I> {
+    fn clone(&self) -> ReputationService<S, I> {
+        ReputationService {
+            inner: self.inner.clone(),
+            layer: self.layer.clone(),
+        }
+    }
+}
+
+impl<S, I, B> Service<Request<B>> for ReputationService<S, I>
+where
+    S: IpReputationSource,
+    I: Service<Request<B>>,
+{
+    type Response = I::Response;
+    type Error = I::Error;
+    type Future = I::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), I::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<B>) -> I::Future {
+        match self.layer.client_ip(&request) {
+            Some(Ok(ip)) => {
+                request.extensions_mut().insert(ClientIp(ip));
+                if let Ok(Some(record)) = self.layer.source.lookup(&ip) {
+                    request.extensions_mut().insert(record);
+                }
+            }
+            Some(Err(error)) => {
+                request
+                    .extensions_mut()
+                    .insert(ClientIpError(error.to_string()));
+            }
+            None => {}
+        }
+        self.inner.call(request)
+    }
+}
+
+/// Returns the peer address of the connection, with IPv4-mapped IPv6 addresses as IPv4
+fn peer_ip<B>(request: &Request<B>) -> Option<IpAddr> {
+    let extensions = request.extensions();
+    #[cfg(feature = "axum")]
+    if let Some(axum::extract::ConnectInfo(address)) =
+        extensions.get::<axum::extract::ConnectInfo<SocketAddr>>()
+    {
+        return Some(address.ip().to_canonical());
+    }
+    extensions
+        .get::<SocketAddr>()
+        .map(|address| address.ip().to_canonical())
+}
+
+#[cfg(feature = "axum")]
+mod extract {
+    use axum::extract::FromRequestParts;
+    use http::request::Parts;
+    use http::StatusCode;
+
+    use super::{ClientIp, ClientIpError};
+    use crate::Record;
+
+    const NO_CLIENT: &str = "the client address is unknown: add a ReputationLayer and serve \
+                             the app with into_make_service_with_connect_info::<SocketAddr>()";
+
+    /// Returns the client found by the layer. Rejects requests whose client could not be
+    /// told with a 400 status, and requests the layer did not see with a 500 status.
+    fn client_ip(parts: &Parts) -> Result<ClientIp, (StatusCode, String)> {
+        if let Some(client_ip) = parts.extensions.get::<ClientIp>() {
+            return Ok(*client_ip);
+        }
+        match parts.extensions.get::<ClientIpError>() {
+            Some(error) => Err((
+                StatusCode::BAD_REQUEST,
+                format!("the client address cannot be told: {}", error),
+            )),
+            None => Err((StatusCode::INTERNAL_SERVER_ERROR, NO_CLIENT.to_owned())),
+        }
+    }
+
+    /// Extracts the [Record] of the client found by [ReputationLayer](super::ReputationLayer),
+    /// or None if the database has no record for it. Rejects the request with a 400 status if
+    /// the client could not be told from the request, and with a 500 status if there is no
+    /// layer or peer address to find it.
+    #[derive(Clone, Debug)]
+    pub struct Reputation(pub Option<Record>);
+
+    impl<T: Send + Sync> FromRequestParts<T> for Reputation {
+        type Rejection = (StatusCode, String);
+
+        async fn from_request_parts(
+            parts: &mut Parts,
+            _: &T,
+        ) -> Result<Reputation, Self::Rejection> {
+            client_ip(parts)?;
+            Ok(Reputation(parts.extensions.get::<Record>().cloned()))
+        }
+    }
+
+    impl<T: Send + Sync> FromRequestParts<T> for ClientIp {
+        type Rejection = (StatusCode, String);
+
+        async fn from_request_parts(parts: &mut Parts, _: &T) -> Result<ClientIp, Self::Rejection> {
+            client_ip(parts)
+        }
+    }
+}
+
+#[cfg(feature = "axum")]
+pub use extract::Reputation;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client_ip::ForwardingHeader;
+    use crate::{MemorySource, Record, RecordBuilder};
+    use std::convert::Infallible;
+    use std::future::Ready;
+
+    /// Returns the client and the ASN of its record found by the layer
+    #[derive(Clone)]
+    struct Echo;
+
+    impl Service<Request<()>> for Echo {
+        type Response = (Option<IpAddr>, Option<u64>);
+        type Error = Infallible;
+        type Future = Ready<Result<Self::Response, Infallible>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: Request<()>) -> Self::Future {
+            let client = request.extensions().get::<ClientIp>().map(|ip| ip.0);
+            let asn = request.extensions().get::<Record>().and_then(Record::asn);
+            std::future::ready(Ok((client, asn)))
+        }
+    }
+
+    fn layer(header: ForwardingHeader) -> ReputationLayer<MemorySource> {
+        let mut source = MemorySource::new();
+        source.insert(
+            "198.51.100.0".parse().unwrap(),
+            24,
+            RecordBuilder::new().asn(64500).build(),
+        );
+        source.insert(
+            "2001:db8::".parse().unwrap(),
+            32,
+            RecordBuilder::new().asn(64501).build(),
+        );
+        // addresses after the networks above get a record without an ASN rather than theirs
+        source.insert(
+            "199.0.0.0".parse().unwrap(),
+            8,
+            RecordBuilder::new().build(),
+        );
+        source.insert(
+            "2001:db9::".parse().unwrap(),
+            32,
+            RecordBuilder::new().build(),
+        );
+        let trusted_proxies = TrustedProxies::new(["fd00::/8".parse().unwrap()]).header(header);
+        ReputationLayer::new(source)
+            .trusted_proxies(trusted_proxies)
+            .trust_proxy("10.0.0.0".parse().unwrap(), 8)
+    }
+
+    fn request(peer: &str, headers: &[(&str, &str)]) -> Request<()> {
+        let mut request = Request::new(());
+        request
+            .extensions_mut()
+            .insert(peer.parse::<SocketAddr>().unwrap());
+        for (name, value) in headers {
+            request.headers_mut().append(
+                HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                value.parse().unwrap(),
+            );
+        }
+        request
+    }
+
+    fn call_with(
+        header: ForwardingHeader,
+        peer: &str,
+        headers: &[(&str, &str)],
+    ) -> (Option<IpAddr>, Option<u64>) {
+        let mut service = layer(header).layer(Echo);
+        service.call(request(peer, headers)).into_inner().unwrap()
+    }
+
+    fn call(peer: &str, headers: &[(&str, &str)]) -> (Option<IpAddr>, Option<u64>) {
+        call_with(ForwardingHeader::XForwardedFor, peer, headers)
+    }
+
+    #[test]
+    fn finds_clients() {
+        let ip = |ip: &str| Some(ip.parse::<IpAddr>().unwrap());
+
+        // headers of untrusted peers are ignored
+        let spoofed = [("x-forwarded-for", "198.51.100.7")];
+        assert_eq!(
+            call("203.0.113.9:4000", &spoofed),
+            (ip("203.0.113.9"), None)
+        );
+        assert_eq!(
+            call("198.51.100.7:4000", &[]),
+            (ip("198.51.100.7"), Some(64500))
+        );
+        assert_eq!(
+            call("[::ffff:198.51.100.7]:4000", &[]),
+            (ip("198.51.100.7"), Some(64500))
+        );
+
+        // the last address that is not a trusted proxy
+        let forwarded_for = [("x-forwarded-for", "192.0.2.1, 198.51.100.7, 10.1.1.1")];
+        assert_eq!(
+            call("10.0.0.1:4000", &forwarded_for),
+            (ip("198.51.100.7"), Some(64500))
+        );
+        let forwarded = [
+            (
+                "forwarded",
+                r#"for=192.0.2.1;proto=https, For="[2001:db8::1]:443""#,
+            ),
+            ("forwarded", "for=fd00::1"),
+            ("x-forwarded-for", "198.51.100.7"),
+        ];
+        assert_eq!(
+            call_with(ForwardingHeader::Forwarded, "[fd00::2]:4000", &forwarded),
+            (ip("2001:db8::1"), Some(64501))
+        );
+        // only the configured header is read
+        assert_eq!(
+            call("[fd00::2]:4000", &forwarded),
+            (ip("198.51.100.7"), Some(64500))
+        );
+        let real_ip = [("x-real-ip", "198.51.100.7")];
+        assert_eq!(
+            call_with(ForwardingHeader::XRealIp, "10.0.0.1:4000", &real_ip),
+            (ip("198.51.100.7"), Some(64500))
+        );
+
+        // from the proxy itself, through proxies only, and through a hop that cannot be parsed
+        assert_eq!(call("10.0.0.1:4000", &[]), (None, None));
+        let proxies = [("x-forwarded-for", "10.2.2.2,10.1.1.1")];
+        assert_eq!(call("10.0.0.1:4000", &proxies), (ip("10.2.2.2"), None));
+        let unknown = [("x-forwarded-for", "198.51.100.7, unknown")];
+        assert_eq!(call("10.0.0.1:4000", &unknown), (None, None));
+    }
+
+    /// Keeps the requests it is called with
+    #[derive(Clone, Default)]
+    struct Keep(Arc<std::sync::Mutex<Vec<Request<()>>>>);
+
+    impl Service<Request<()>> for Keep {
+        type Response = ();
+        type Error = Infallible;
+        type Future = Ready<Result<(), Infallible>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: Request<()>) -> Self::Future {
+            self.0.lock().unwrap().push(request);
+            std::future::ready(Ok(()))
+        }
+    }
+
+    #[test]
+    fn records_why_clients_are_unknown() {
+        let kept = Keep::default();
+        let mut service = layer(ForwardingHeader::XForwardedFor).layer(kept.clone());
+        service
+            .call(request("10.0.0.1:4000", &[]))
+            .into_inner()
+            .unwrap();
+        service.call(Request::new(())).into_inner().unwrap();
+        let requests = kept.0.lock().unwrap();
+        let error = requests[0].extensions().get::<ClientIpError>().unwrap();
+        assert!(error.0.contains("X-Forwarded-For"), "{}", error);
+        assert!(requests[0].extensions().get::<ClientIp>().is_none());
+        // without a peer address there is nothing to tell
+        assert!(requests[1].extensions().get::<ClientIpError>().is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "axum")]
+    fn extractors() {
+        use axum::extract::FromRequestParts;
+        use std::future::Future;
+        use std::pin::pin;
+        use std::task::Waker;
+
+        fn block_on<F: Future>(future: F) -> F::Output {
+            let mut context = Context::from_waker(Waker::noop());
+            match pin!(future).poll(&mut context) {
+                Poll::Ready(output) => output,
+                Poll::Pending => unreachable!(),
+            }
+        }
+
+        let (mut parts, _) = Request::new(()).into_parts();
+        let (status, _) = block_on(Reputation::from_request_parts(&mut parts, &())).unwrap_err();
+        assert_eq!(status, http::StatusCode::INTERNAL_SERVER_ERROR);
+        parts
+            .extensions
+            .insert(ClientIpError("no X-Forwarded-For header".to_owned()));
+        let (status, message) =
+            block_on(ClientIp::from_request_parts(&mut parts, &())).unwrap_err();
+        assert_eq!(status, http::StatusCode::BAD_REQUEST);
+        assert!(message.contains("no X-Forwarded-For header"));
+        parts.extensions.clear();
+        parts
+            .extensions
+            .insert(ClientIp("192.0.2.1".parse().unwrap()));
+        let Reputation(record) = block_on(Reputation::from_request_parts(&mut parts, &())).unwrap();
+        assert!(record.is_none());
+        parts
+            .extensions
+            .insert(RecordBuilder::new().asn(64500).build());
+        let Reputation(record) = block_on(Reputation::from_request_parts(&mut parts, &())).unwrap();
+        assert_eq!(record.unwrap().asn(), Some(64500));
+        let client = block_on(ClientIp::from_request_parts(&mut parts, &())).unwrap();
+        assert_eq!(client.0.to_string(), "192.0.2.1");
+    }
+}
@@ -0,0 +1,78 @@
+// Copyright 2023 IPQualityScore LLC
+//! JavaScript bindings for WebAssembly, built with the `wasm` feature, e.g.
+//! `wasm-pack build --target web -- --features wasm`. Browsers and edge runtimes have no file
+//! system, so the database is given as bytes:
+//!
+//! ```js
+//! import init, { Reader } from "./pkg/ipqs_db_reader.js";
+//!
+//! await init();
+//! const bytes = new Uint8Array(await (await fetch("IPv4.ipqs")).arrayBuffer());
+//! const reader = new Reader(bytes);
+//! const record = reader.lookup("8.8.8.8"); // an object, or null without a record
+//! console.log(record?.country, record?.fraud_score.strictness[0]);
+//! ```
+//!
+//! Records are plain objects in the JSON format of [Record](crate::Record).
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+use crate::file_reader::is_not_found;
+use crate::FileReader;
+
+/// A database read from bytes in memory
+#[wasm_bindgen(js_name = Reader)]
+#[derive(Debug)]
+pub struct WasmReader {
+    reader: FileReader,
+}
+
+#[wasm_bindgen(js_class = Reader)]
+impl WasmReader {
+    /// Reads a database from a copy of `bytes` and indexes it
+    #[wasm_bindgen(constructor)]
+    pub fn new(bytes: Vec<u8>) -> Result<WasmReader, JsError> {
+        let mut reader = FileReader::from_bytes(bytes).map_err(js_error)?;
+        reader.build_index().map_err(js_error)?;
+        Ok(WasmReader { reader })
+    }
+
+    /// Returns the record of `ip`, or null if the database has no record for it
+    pub fn lookup(&mut self, ip: &str) -> Result<JsValue, JsError> {
+        let ip = ip
+            .parse()
+            .map_err(|_| JsError::new(&format!("invalid IP address: {}", ip)))?;
+        match self.reader.fetch(&ip) {
+            Ok(record) => to_js(&record),
+            Err(error) if is_not_found(error.as_ref()) => Ok(JsValue::NULL),
+            Err(error) => Err(js_error(error)),
+        }
+    }
+
+    /// Returns an array with the record of each address, or null for addresses without one
+    #[wasm_bindgen(js_name = lookupMany)]
+    pub fn lookup_many(&mut self, ips: Vec<String>) -> Result<Vec<JsValue>, JsError> {
+        ips.iter().map(|ip| self.lookup(ip)).collect()
+    }
+
+    /// Returns the details of the file given in its header
+    pub fn metadata(&self) -> Result<JsValue, JsError> {
+        to_js(&self.reader.metadata())
+    }
+
+    #[wasm_bindgen(getter, js_name = isIpv6)]
+    pub fn is_ipv6(&self) -> bool {
+        self.reader.is_ipv6()
+    }
+}
+
+fn to_js<T: Serialize>(value: &T) -> Result<JsValue, JsError> {
+    // plain objects rather than Maps, and null rather than undefined, as JSON.parse would give
+    value
+        .serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+        .map_err(|error| JsError::new(&error.to_string()))
+}
+
+fn js_error(error: Box<dyn std::error::Error>) -> JsError {
+    JsError::new(&error.to_string())
+}